@@ -0,0 +1,215 @@
+//! Non-interactive commands for use in scripts.
+
+use crate::console::{update_type_from_name, Command};
+use rust_openttd_admin::connection::Connection;
+use rust_openttd_admin::metrics::{self, Metrics};
+use rust_openttd_admin::packet::admin::{client_packets, json, server_packets, AdminWrite};
+use rust_openttd_admin::rcon::ERROR_COLOUR;
+use rust_openttd_admin::types::AdminUpdateType;
+use std::error::Error;
+use std::net::TcpListener;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Id of the ping that marks the end of a poll.
+const POLL_PING_ID: u32 = 0x4F50_4C4C;
+
+/// Time between pings used to measure the latency.
+const PING_INTERVAL: Duration = Duration::from_secs(15);
+
+/// Returns the text of a command from its arguments, allowing it to be given
+/// either as a single argument or as multiple words.
+fn text_argument(args: &[String], usage: &str) -> Result<String, Box<dyn Error>> {
+    if args.is_empty() {
+        Err(usage.into())
+    } else {
+        Ok(args.join(" "))
+    }
+}
+
+/// Execute a console command. Fails if the server reports an error.
+fn rcon(connection: &mut Connection, args: &[String]) -> Result<i32, Box<dyn Error>> {
+    let command = text_argument(args, "usage: rcon <command>")?;
+    connection.send(&client_packets::Rcon { command: &command })?;
+    let mut status = 0;
+    loop {
+        match connection.read_packet()? {
+            server_packets::Packet::Rcon(rcon) => {
+                if rcon.color == ERROR_COLOUR {
+                    status = 1;
+                }
+                println!("{}", rcon.output);
+            }
+            server_packets::Packet::RconEnd(_) => return Ok(status),
+            server_packets::Packet::Error(error) => {
+                eprintln!("the server returned error {}", error.error_code);
+                return Ok(1);
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Broadcast a chat message.
+fn say(connection: &mut Connection, args: &[String]) -> Result<i32, Box<dyn Error>> {
+    let message = text_argument(args, "usage: say <message>")?;
+    Command::Say(message).send(connection.stream_mut())?;
+    Ok(0)
+}
+
+/// Print all clients or companies.
+fn poll(connection: &mut Connection, args: &[String]) -> Result<i32, Box<dyn Error>> {
+    let usage = "usage: poll <clients|companies> [--json]";
+    let json = args.iter().any(|arg| arg == "--json");
+    let mut targets = args.iter().filter(|arg| *arg != "--json");
+    let update_type = match targets.next().map(String::as_str) {
+        Some("clients") => AdminUpdateType::ClientInfo,
+        Some("companies") => AdminUpdateType::CompanyInfo,
+        _ => return Err(usage.into()),
+    };
+    connection.send(&client_packets::Poll {
+        update_type,
+        id: u32::MAX,
+    })?;
+    // The server answers in order, so all answers have arrived at the pong.
+    connection.send(&client_packets::Ping { id: POLL_PING_ID })?;
+
+    let mut clients = Vec::new();
+    let mut companies = Vec::new();
+    loop {
+        match connection.read_packet()? {
+            server_packets::Packet::ClientInfo(info) => clients.push(info),
+            server_packets::Packet::CompanyInfo(info) => companies.push(info),
+            server_packets::Packet::Pong(pong) if pong.id == POLL_PING_ID => break,
+            server_packets::Packet::Error(error) => {
+                eprintln!("the server returned error {}", error.error_code);
+                return Ok(1);
+            }
+            _ => {}
+        }
+    }
+
+    if json {
+        let output = match update_type {
+            AdminUpdateType::ClientInfo => serde_json::to_string_pretty(&clients)?,
+            _ => serde_json::to_string_pretty(&companies)?,
+        };
+        println!("{}", output);
+    } else {
+        for client in clients {
+            println!(
+                "{}\t{}\t{}\t{}",
+                client.id, client.name, client.company_id, client.address
+            );
+        }
+        for company in companies {
+            println!(
+                "{}\t{}\t{}\t{}",
+                company.id, company.name, company.manager, company.inaugurated_year
+            );
+        }
+    }
+    Ok(0)
+}
+
+/// Print updates of the given types as JSON lines until the server shuts
+/// down.
+fn watch(connection: &mut Connection, args: &[String]) -> Result<i32, Box<dyn Error>> {
+    let types = match args {
+        [] => "chat,console",
+        [flag, types] if flag == "--types" => types.as_str(),
+        _ => return Err("usage: watch [--types <TYPES>]".into()),
+    };
+    for name in types.split(',') {
+        let update_type = update_type_from_name(name.trim())
+            .ok_or_else(|| format!("unknown update type {:?}", name))?;
+        if !connection.register_updates(update_type)? {
+            return Err(format!("the server does not send updates for {}", name).into());
+        }
+    }
+    loop {
+        let packet = connection.read_packet()?;
+        println!("{}", json::to_string(&packet)?);
+        match packet {
+            server_packets::Packet::Shutdown => return Ok(0),
+            server_packets::Packet::Error(_) => return Ok(1),
+            _ => {}
+        }
+    }
+}
+
+/// Serve Prometheus metrics until the server shuts down.
+fn serve_metrics(connection: &mut Connection, args: &[String]) -> Result<i32, Box<dyn Error>> {
+    let address = match args {
+        [] => "127.0.0.1:9977",
+        [flag, address] if flag == "--listen" => address.as_str(),
+        _ => return Err("usage: metrics [--listen <ADDRESS>]".into()),
+    };
+    let listener = TcpListener::bind(address)?;
+    for update_type in &[
+        AdminUpdateType::CompanyEconomy,
+        AdminUpdateType::CompanyStats,
+        AdminUpdateType::CompanyInfo,
+        AdminUpdateType::ClientInfo,
+        AdminUpdateType::Date,
+    ] {
+        connection.register_updates(*update_type)?;
+    }
+    connection.send(&client_packets::Poll {
+        update_type: AdminUpdateType::ClientInfo,
+        id: u32::MAX,
+    })?;
+
+    let metrics = Arc::new(Mutex::new(Metrics::new()));
+    metrics
+        .lock()
+        .unwrap()
+        .update(&server_packets::Packet::Welcome(
+            connection.welcome().clone(),
+        ));
+    {
+        let metrics = metrics.clone();
+        thread::spawn(move || metrics::serve(listener, metrics));
+    }
+    {
+        let metrics = metrics.clone();
+        let mut stream = connection.stream().try_clone()?;
+        thread::spawn(move || {
+            for id in 0u32.. {
+                metrics.lock().unwrap().ping_sent(id, Instant::now());
+                if stream.write_packet(&client_packets::Ping { id }).is_err() {
+                    break;
+                }
+                thread::sleep(PING_INTERVAL);
+            }
+        });
+    }
+    loop {
+        let packet = connection.read_packet()?;
+        metrics.lock().unwrap().update(&packet);
+        match packet {
+            server_packets::Packet::Shutdown => return Ok(0),
+            server_packets::Packet::Error(_) => return Ok(1),
+            _ => {}
+        }
+    }
+}
+
+/// Run a command and return the exit status.
+pub fn run(
+    mut connection: Connection,
+    command: &str,
+    args: &[String],
+) -> Result<i32, Box<dyn Error>> {
+    let status = match command {
+        "rcon" => rcon(&mut connection, args)?,
+        "say" => say(&mut connection, args)?,
+        "poll" => poll(&mut connection, args)?,
+        "watch" => watch(&mut connection, args)?,
+        "metrics" => serve_metrics(&mut connection, args)?,
+        _ => return Err(format!("unknown command {:?}", command).into()),
+    };
+    Command::Quit.send(connection.stream_mut())?;
+    Ok(status)
+}
@@ -0,0 +1,116 @@
+//! Command-line options and setting up the admin connection.
+
+use rust_openttd_admin::connection::{Connection, ConnectionError};
+use rust_openttd_admin::packet::admin::client_packets;
+
+pub const USAGE: &str = "\
+Usage: openttd-admin [OPTIONS] [COMMAND]
+
+Without a command, an interactive console is started.
+
+Commands:
+    rcon <COMMAND>                     Execute a console command and print its output
+    say <MESSAGE>                      Broadcast a chat message
+    poll <clients|companies> [--json]  Print the connected clients or companies
+    watch [--types <TYPES>]            Print updates as JSON lines, e.g. --types chat,console
+    metrics [--listen <ADDRESS>]       Serve Prometheus metrics (default: 127.0.0.1:9977)
+
+Options:
+    -H, --host <HOST>          Server to connect to (default: localhost)
+    -p, --port <PORT>          Admin port of the server (default: 3977)
+    -P, --password <PASSWORD>  Admin password (default: $OPENTTD_ADMIN_PASSWORD)
+        --help                 Print this message";
+
+/// Why the command-line arguments were not parsed.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum ArgsError {
+    /// `--help` was given.
+    Help,
+    Invalid(String),
+}
+
+/// The options needed to connect to a server.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Options {
+    pub host: String,
+    pub port: u16,
+    pub password: String,
+}
+
+impl Options {
+    /// Parse the options from the command-line arguments. Parsing stops at
+    /// the first argument that is not an option, which starts the command, or
+    /// after `--`. The remaining arguments are returned separately.
+    pub fn from_args<I: Iterator<Item = String>>(
+        mut args: I,
+    ) -> Result<(Options, Vec<String>), ArgsError> {
+        let mut options = Options {
+            host: "localhost".to_string(),
+            port: 3977,
+            password: std::env::var("OPENTTD_ADMIN_PASSWORD").unwrap_or_default(),
+        };
+        let mut rest = Vec::new();
+        while let Some(arg) = args.next() {
+            let mut value = || {
+                args.next()
+                    .ok_or_else(|| ArgsError::Invalid(format!("missing value for {}", arg)))
+            };
+            match arg.as_str() {
+                "-H" | "--host" => options.host = value()?,
+                "-p" | "--port" => {
+                    options.port = value()?.parse().map_err(|_| {
+                        ArgsError::Invalid("the port should be a number".to_string())
+                    })?
+                }
+                "-P" | "--password" => options.password = value()?,
+                "--help" => return Err(ArgsError::Help),
+                "--" => break,
+                _ if arg.starts_with('-') => {
+                    return Err(ArgsError::Invalid(format!("unknown option {}", arg)))
+                }
+                _ => {
+                    rest.push(arg);
+                    break;
+                }
+            }
+        }
+        rest.extend(args);
+        Ok((options, rest))
+    }
+}
+
+/// Connect and authenticate to the server.
+pub fn connect(options: &Options) -> Result<Connection, ConnectionError> {
+    Connection::connect(
+        (options.host.as_str(), options.port),
+        &client_packets::Join {
+            password: &options.password,
+            name: "openttd-admin",
+            version: env!("CARGO_PKG_VERSION"),
+        },
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Result<(Options, Vec<String>), ArgsError> {
+        Options::from_args(args.iter().map(|arg| arg.to_string()))
+    }
+
+    #[test]
+    fn options_before_command() {
+        let (options, rest) = parse(&["-H", "example.com", "-p", "3978", "say", "-P"]).unwrap();
+        assert_eq!(options.host, "example.com");
+        assert_eq!(options.port, 3978);
+        assert_eq!(rest, vec!["say", "-P"]);
+
+        let (_, rest) = parse(&["rcon", "x", "--help"]).unwrap();
+        assert_eq!(rest, vec!["rcon", "x", "--help"]);
+        let (_, rest) = parse(&["--", "-H"]).unwrap();
+        assert_eq!(rest, vec!["-H"]);
+        assert_eq!(parse(&["--help", "say"]), Err(ArgsError::Help));
+        assert!(parse(&["--hots", "say"]).is_err());
+    }
+}
@@ -0,0 +1,159 @@
+//! Parsing of console input and formatting of server output.
+
+use rust_openttd_admin::packet::admin::{client_packets, AdminWrite, Result};
+use rust_openttd_admin::types::AdminUpdateType;
+
+/// A line entered in the console.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Command {
+    /// Execute a command on the server console.
+    Rcon(String),
+    /// Broadcast a chat message.
+    Say(String),
+    /// Send a private chat message to a client.
+    PrivateMessage { client: u32, message: String },
+    /// Send JSON to the GameScript.
+    Gamescript(String),
+    /// Poll the server for an update.
+    Poll {
+        update_type: AdminUpdateType,
+        id: u32,
+    },
+    /// Leave the console.
+    Quit,
+}
+
+/// Returns the update type with the given name, e.g. `client_info`.
+pub fn update_type_from_name(name: &str) -> Option<AdminUpdateType> {
+    Some(match name {
+        "date" => AdminUpdateType::Date,
+        "client_info" | "clients" => AdminUpdateType::ClientInfo,
+        "company_info" | "companies" => AdminUpdateType::CompanyInfo,
+        "company_economy" => AdminUpdateType::CompanyEconomy,
+        "company_stats" => AdminUpdateType::CompanyStats,
+        "chat" => AdminUpdateType::Chat,
+        "console" => AdminUpdateType::Console,
+        "cmd_names" => AdminUpdateType::CmdNames,
+        "cmd_logging" => AdminUpdateType::CmdLogging,
+        "gamescript" => AdminUpdateType::Gamescript,
+        _ => return None,
+    })
+}
+
+/// Parse a poll id, where `all` polls every client or company.
+fn parse_id(id: Option<&str>) -> std::result::Result<u32, String> {
+    match id {
+        None | Some("all") => Ok(u32::MAX),
+        Some(id) => id.parse().map_err(|_| format!("invalid id {:?}", id)),
+    }
+}
+
+impl Command {
+    /// Parse a line of input. Returns `None` for an empty line.
+    pub fn parse(line: &str) -> std::result::Result<Option<Command>, String> {
+        let line = line.trim();
+        if line.is_empty() {
+            return Ok(None);
+        }
+        if !line.starts_with('/') {
+            return Ok(Some(Command::Rcon(line.to_string())));
+        }
+        let (name, argument) = match line.find(' ') {
+            Some(index) => (&line[1..index], line[index + 1..].trim()),
+            None => (&line[1..], ""),
+        };
+        let command = match name {
+            "say" => Command::Say(argument.to_string()),
+            "pm" => {
+                let (client, message) = argument
+                    .split_once(' ')
+                    .ok_or("usage: /pm <client> <message>")?;
+                Command::PrivateMessage {
+                    client: client
+                        .parse()
+                        .map_err(|_| format!("invalid client id {:?}", client))?,
+                    message: message.trim().to_string(),
+                }
+            }
+            "gs" => Command::Gamescript(argument.to_string()),
+            "poll" => {
+                let mut parts = argument.split_whitespace();
+                let update_type = parts.next().ok_or("usage: /poll <type> [id]")?;
+                Command::Poll {
+                    update_type: update_type_from_name(update_type)
+                        .ok_or_else(|| format!("unknown update type {:?}", update_type))?,
+                    id: parse_id(parts.next())?,
+                }
+            }
+            "quit" | "exit" => Command::Quit,
+            _ => return Err(format!("unknown command /{}", name)),
+        };
+        Ok(Some(command))
+    }
+
+    /// Send the packet for this command.
+    pub fn send<W: std::io::Write>(&self, writer: &mut W) -> Result<()> {
+        match self {
+            Command::Rcon(command) => writer.write_packet(&client_packets::Rcon { command }),
+            Command::Say(message) => writer.write_packet(&client_packets::Chat::broadcast(message)),
+            Command::PrivateMessage { client, message } => {
+                writer.write_packet(&client_packets::Chat::to_client(*client, message))
+            }
+            Command::Gamescript(json) => writer.write_packet(&client_packets::Gamescript { json }),
+            Command::Poll { update_type, id } => writer.write_packet(&client_packets::Poll {
+                update_type: *update_type,
+                id: *id,
+            }),
+            Command::Quit => writer.write_packet(&client_packets::Quit),
+        }
+    }
+}
+
+/// Wrap text in the ANSI escape code closest to an OpenTTD text colour.
+pub fn colourize(colour: u16, text: &str) -> String {
+    let code = match colour {
+        0 | 15 => "34",        // blue, dark blue
+        1 | 14 => "37",        // silver, grey
+        2 | 6 => "33",         // gold, orange
+        3 => "31",             // red
+        4 => "35",             // purple
+        5 | 10 | 11 => "33;2", // light brown, cream, brown
+        7 | 9 => "32",         // green, dark green
+        8 => "93",             // yellow
+        12 => "97",            // white
+        13 => "94",            // light blue
+        16 => "30",            // black
+        _ => return text.to_string(),
+    };
+    format!("\x1b[{}m{}\x1b[0m", code, text)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn parse_commands() {
+        assert_eq!(Command::parse("  "), Ok(None));
+        assert_eq!(
+            Command::parse("kick 3"),
+            Ok(Some(Command::Rcon("kick 3".to_string())))
+        );
+        assert_eq!(
+            Command::parse("/pm 3 hello there"),
+            Ok(Some(Command::PrivateMessage {
+                client: 3,
+                message: "hello there".to_string()
+            }))
+        );
+        assert_eq!(
+            Command::parse("/poll clients"),
+            Ok(Some(Command::Poll {
+                update_type: AdminUpdateType::ClientInfo,
+                id: u32::MAX
+            }))
+        );
+        assert!(Command::parse("/poll weather").is_err());
+        assert!(Command::parse("/dance").is_err());
+    }
+}
@@ -0,0 +1,139 @@
+//! A console for the OpenTTD admin port. Lines are executed as rcon commands
+//! on the server, while chat and console output is shown as it arrives.
+//!
+//! Besides rcon commands, the console understands:
+//!
+//! - `/say <message>`: broadcast a chat message.
+//! - `/pm <client> <message>`: send a private message to a client.
+//! - `/gs <json>`: send JSON to the GameScript.
+//! - `/poll <type> [id]`: poll the server for an update.
+//! - `/quit`: leave the console.
+//!
+//! For scripting, the commands `rcon`, `say`, `poll` and `watch` run
+//! without a console. The `metrics` command serves Prometheus metrics.
+
+mod commands;
+mod connection;
+mod console;
+
+use connection::{ArgsError, Options, USAGE};
+use console::{colourize, Command};
+use rust_openttd_admin::clients::Clients;
+use rust_openttd_admin::connection::Connection;
+use rust_openttd_admin::packet::admin::{client_packets, server_packets, AdminRead};
+use rust_openttd_admin::types::AdminUpdateType;
+use std::error::Error;
+use std::io::BufRead;
+use std::net::TcpStream;
+use std::process;
+
+/// Print incoming packets until the connection closes.
+fn print_packets(mut stream: TcpStream) {
+    let mut clients = Clients::new();
+    loop {
+        let packet = match stream.read_packet() {
+            Ok(packet) => packet,
+            Err(err) => {
+                eprintln!("connection lost: {}", err);
+                process::exit(1);
+            }
+        };
+        clients.update(&packet);
+        match packet {
+            server_packets::Packet::ClientInfo(_)
+            | server_packets::Packet::ClientUpdate(_)
+            | server_packets::Packet::ClientQuit(_) => {}
+            server_packets::Packet::Chat(chat) => {
+                let name = clients
+                    .name(chat.client)
+                    .map(str::to_string)
+                    .unwrap_or_else(|| format!("client #{}", chat.client));
+                println!("[chat] {}: {}", name, chat.message);
+            }
+            server_packets::Packet::Console(console) => {
+                println!("[{}] {}", console.origin, console.text);
+            }
+            server_packets::Packet::Rcon(rcon) => {
+                println!("{}", colourize(rcon.color, &rcon.output));
+            }
+            server_packets::Packet::Gamescript(gamescript) => {
+                println!("[gamescript] {}", gamescript.json);
+            }
+            server_packets::Packet::Shutdown => {
+                println!("the server is shutting down");
+                process::exit(0);
+            }
+            server_packets::Packet::Newgame => println!("a new game has started"),
+            server_packets::Packet::Error(error) => {
+                eprintln!("the server returned error {}", error.error_code);
+                process::exit(1);
+            }
+            server_packets::Packet::RconEnd(_) | server_packets::Packet::Pong(_) => {}
+            packet => println!("{:?}", packet),
+        }
+    }
+}
+
+fn interactive(mut connection: Connection) -> Result<(), Box<dyn Error>> {
+    println!(
+        "connected to {} (OpenTTD {})",
+        connection.welcome().server_name,
+        connection.welcome().openttd_version
+    );
+    for update_type in &[
+        AdminUpdateType::ClientInfo,
+        AdminUpdateType::Chat,
+        AdminUpdateType::Console,
+    ] {
+        connection.register_updates(*update_type)?;
+    }
+    connection.send(&client_packets::Poll {
+        update_type: AdminUpdateType::ClientInfo,
+        id: u32::MAX,
+    })?;
+
+    let mut stream = connection.into_inner();
+    let reader = stream.try_clone()?;
+    std::thread::spawn(move || print_packets(reader));
+
+    let stdin = std::io::stdin();
+    for line in stdin.lock().lines() {
+        match Command::parse(&line?) {
+            Ok(Some(Command::Quit)) => break,
+            Ok(Some(command)) => command.send(&mut stream)?,
+            Ok(None) => {}
+            Err(message) => eprintln!("{}", message),
+        }
+    }
+    Command::Quit.send(&mut stream)?;
+    Ok(())
+}
+
+fn run(options: Options, args: &[String]) -> Result<i32, Box<dyn Error>> {
+    let connection = connection::connect(&options)?;
+    match args.split_first() {
+        None => interactive(connection).map(|()| 0),
+        Some((command, args)) => commands::run(connection, command, args),
+    }
+}
+
+fn main() {
+    let (options, args) = match Options::from_args(std::env::args().skip(1)) {
+        Ok(parsed) => parsed,
+        Err(ArgsError::Help) => {
+            println!("{}", USAGE);
+            process::exit(0);
+        }
+        Err(ArgsError::Invalid(message)) => {
+            eprintln!("{}\n\n{}", message, USAGE);
+            process::exit(2);
+        }
+    };
+    match run(options, &args) {
+        Ok(status) => process::exit(status),
+        Err(err) => {
+            eprintln!("error: {}", err);
+            process::exit(1);
+        }
+    }
+}
@@ -0,0 +1,545 @@
+//! Commands that players type in the chat, like `!help` or `!rules`.
+//!
+//! A [`Router`] holds the registered [`Command`]s. It implements
+//! [`AdminHandler`], so it can be passed to [`handler::run`](crate::handler::run)
+//! directly, or be called from another handler through
+//! [`Router::handle_packet`]. Register for `Chat` and `ClientInfo` updates
+//! and poll the clients once, so the router knows who is talking.
+//!
+//! ```no_run
+//! use rust_openttd_admin::chat_commands::{Argument, Command, Permission, Reply, Router};
+//!
+//! let router = Router::new()
+//!     .admin("Alice")
+//!     .command(Command::new("rules", "Show the rules", |_, _| {
+//!         Ok(Reply::Private("Do not block other players.".to_string()))
+//!     }))
+//!     .command(
+//!         Command::new("kick", "Kick a client", |ctx, invocation| {
+//!             let client = invocation.arguments.integer("client").unwrap();
+//!             ctx.rcon(&format!("kick {}", client))?;
+//!             Ok(Reply::Nothing)
+//!         })
+//!         .argument(Argument::integer("client"))
+//!         .permission(Permission::Admins),
+//!     );
+//! ```
+
+use crate::clients::Clients;
+use crate::handler::{AdminHandler, Context};
+use crate::packet::admin::server_packets::{self, Packet};
+use crate::packet::admin::Result;
+use std::collections::{HashMap, HashSet};
+
+/// Who is allowed to use a command.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Permission {
+    Everyone,
+    /// Clients playing as a company, not spectators.
+    CompanyMembers,
+    /// Clients in the admin list of the router.
+    Admins,
+}
+
+/// What an argument should look like.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum ArgumentKind {
+    /// A single word.
+    Word,
+    /// A whole number.
+    Integer,
+    /// All remaining text. Only makes sense as the last argument.
+    Rest,
+}
+
+/// An argument of a command.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Argument {
+    name: &'static str,
+    kind: ArgumentKind,
+    optional: bool,
+}
+
+impl Argument {
+    pub fn word(name: &'static str) -> Argument {
+        Argument {
+            name,
+            kind: ArgumentKind::Word,
+            optional: false,
+        }
+    }
+
+    pub fn integer(name: &'static str) -> Argument {
+        Argument {
+            name,
+            kind: ArgumentKind::Integer,
+            optional: false,
+        }
+    }
+
+    pub fn rest(name: &'static str) -> Argument {
+        Argument {
+            name,
+            kind: ArgumentKind::Rest,
+            optional: false,
+        }
+    }
+
+    /// Make the argument optional. Optional arguments should come after the
+    /// required ones.
+    pub fn optional(mut self) -> Argument {
+        self.optional = true;
+        self
+    }
+
+    fn usage(&self) -> String {
+        let name = match self.kind {
+            ArgumentKind::Rest => format!("{}...", self.name),
+            _ => self.name.to_string(),
+        };
+        if self.optional {
+            format!("[{}]", name)
+        } else {
+            format!("<{}>", name)
+        }
+    }
+}
+
+/// The parsed arguments of a command.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct Arguments {
+    values: HashMap<&'static str, String>,
+}
+
+impl Arguments {
+    /// The value of an argument, if it was given.
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.values.get(name).map(String::as_str)
+    }
+
+    /// The value of an integer argument, if it was given.
+    pub fn integer(&self, name: &str) -> Option<i64> {
+        self.get(name).and_then(|value| value.parse().ok())
+    }
+}
+
+/// The client that sent a command.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Sender {
+    pub client_id: u32,
+    /// The name of the client, or `client #<id>` if the client is unknown.
+    pub name: String,
+    /// The company of the client, or `None` for spectators.
+    pub company_id: Option<u8>,
+    pub is_admin: bool,
+}
+
+/// A command as typed by a client.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Invocation {
+    pub sender: Sender,
+    pub arguments: Arguments,
+}
+
+/// The answer to a command.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Reply {
+    Nothing,
+    /// Answer only the client that sent the command.
+    Private(String),
+    /// Answer everyone.
+    Broadcast(String),
+}
+
+type Callback = Box<dyn FnMut(&mut Context, &Invocation) -> Result<Reply>>;
+
+/// A chat command.
+pub struct Command {
+    name: &'static str,
+    description: &'static str,
+    arguments: Vec<Argument>,
+    permission: Permission,
+    callback: Callback,
+}
+
+impl Command {
+    /// A command that everyone may use and that takes no arguments.
+    pub fn new<F>(name: &'static str, description: &'static str, callback: F) -> Command
+    where
+        F: FnMut(&mut Context, &Invocation) -> Result<Reply> + 'static,
+    {
+        Command {
+            name,
+            description,
+            arguments: Vec::new(),
+            permission: Permission::Everyone,
+            callback: Box::new(callback),
+        }
+    }
+
+    /// Returns true if the command has this lowercase name. Commands are
+    /// matched regardless of the case they were registered with.
+    fn is_named(&self, name: &str) -> bool {
+        self.name.to_lowercase() == name
+    }
+
+    pub fn argument(mut self, argument: Argument) -> Command {
+        self.arguments.push(argument);
+        self
+    }
+
+    pub fn permission(mut self, permission: Permission) -> Command {
+        self.permission = permission;
+        self
+    }
+
+    /// The usage line shown by `help`, e.g. `!kick <client> [reason...]`.
+    pub fn usage(&self, prefix: &str) -> String {
+        let mut usage = format!("{}{}", prefix, self.name);
+        for argument in &self.arguments {
+            usage.push(' ');
+            usage.push_str(&argument.usage());
+        }
+        usage
+    }
+
+    fn allows(&self, sender: &Sender) -> bool {
+        match self.permission {
+            Permission::Everyone => true,
+            Permission::CompanyMembers => sender.company_id.is_some() || sender.is_admin,
+            Permission::Admins => sender.is_admin,
+        }
+    }
+
+    fn parse_arguments(&self, mut text: &str) -> Option<Arguments> {
+        let mut arguments = Arguments::default();
+        for argument in &self.arguments {
+            text = text.trim_start();
+            let value = match argument.kind {
+                ArgumentKind::Rest => {
+                    let rest = text.trim_end();
+                    text = "";
+                    rest
+                }
+                ArgumentKind::Word | ArgumentKind::Integer => {
+                    let end = text.find(char::is_whitespace).unwrap_or(text.len());
+                    let (word, rest) = text.split_at(end);
+                    text = rest;
+                    word
+                }
+            };
+            if value.is_empty() {
+                if argument.optional {
+                    continue;
+                }
+                return None;
+            }
+            if argument.kind == ArgumentKind::Integer && value.parse::<i64>().is_err() {
+                return None;
+            }
+            arguments.values.insert(argument.name, value.to_string());
+        }
+        if text.trim().is_empty() {
+            Some(arguments)
+        } else {
+            None
+        }
+    }
+}
+
+/// Routes chat messages to commands.
+pub struct Router {
+    prefix: String,
+    commands: Vec<Command>,
+    admin_names: HashSet<String>,
+    admin_addresses: HashSet<String>,
+    clients: Clients,
+}
+
+impl Default for Router {
+    fn default() -> Router {
+        Router::new()
+    }
+}
+
+impl Router {
+    /// A router for commands starting with `!`.
+    pub fn new() -> Router {
+        Router {
+            prefix: "!".to_string(),
+            commands: Vec::new(),
+            admin_names: HashSet::new(),
+            admin_addresses: HashSet::new(),
+            clients: Clients::new(),
+        }
+    }
+
+    pub fn with_prefix(mut self, prefix: &str) -> Router {
+        self.prefix = prefix.to_string();
+        self
+    }
+
+    /// Add a client name to the admin list. Anyone can pick any name, so
+    /// this is only safe on servers that are password protected.
+    pub fn admin(mut self, name: &str) -> Router {
+        self.admin_names.insert(name.to_string());
+        self
+    }
+
+    /// Add a network address to the admin list.
+    pub fn admin_address(mut self, address: &str) -> Router {
+        self.admin_addresses.insert(address.to_string());
+        self
+    }
+
+    /// Register a command. A command named `help` replaces the built-in one.
+    pub fn command(mut self, command: Command) -> Router {
+        self.commands.push(command);
+        self
+    }
+
+    /// The clients the router has seen.
+    pub fn clients(&self) -> &Clients {
+        &self.clients
+    }
+
+    /// Update the known clients and handle the packet if it is a command.
+    pub fn handle_packet(&mut self, ctx: &mut Context, packet: &Packet) -> Result<()> {
+        self.clients.update(packet);
+        match packet {
+            Packet::Chat(chat) => self.handle_chat(ctx, chat),
+            _ => Ok(()),
+        }
+    }
+
+    /// Handle a chat message if it is a command.
+    pub fn handle_chat(&mut self, ctx: &mut Context, chat: &server_packets::Chat) -> Result<()> {
+        // Only NETWORK_ACTION_CHAT, NETWORK_ACTION_CHAT_COMPANY and
+        // NETWORK_ACTION_CHAT_CLIENT are messages typed by a client.
+        if !(3..=5).contains(&chat.action) {
+            return Ok(());
+        }
+        let text = match chat.message.trim().strip_prefix(self.prefix.as_str()) {
+            Some(text) => text,
+            None => return Ok(()),
+        };
+        let (name, arguments) = match text.find(char::is_whitespace) {
+            Some(end) => text.split_at(end),
+            None => (text, ""),
+        };
+        let name = name.to_lowercase();
+        if name.is_empty() {
+            return Ok(());
+        }
+        let sender = self.sender(chat.client);
+
+        let reply = match self
+            .commands
+            .iter()
+            .position(|command| command.is_named(&name))
+        {
+            Some(index) => {
+                let command = &mut self.commands[index];
+                if !command.allows(&sender) {
+                    Reply::Private(format!(
+                        "You are not allowed to use {}{}.",
+                        self.prefix, command.name
+                    ))
+                } else {
+                    match command.parse_arguments(arguments) {
+                        Some(arguments) => {
+                            let invocation = Invocation { sender, arguments };
+                            (command.callback)(ctx, &invocation)?
+                        }
+                        None => Reply::Private(format!("Usage: {}", command.usage(&self.prefix))),
+                    }
+                }
+            }
+            None if name == "help" => {
+                for line in self.help(&sender, arguments.trim()) {
+                    ctx.private_message(chat.client, &line)?;
+                }
+                Reply::Nothing
+            }
+            None => Reply::Private(format!(
+                "Unknown command {}{}, try {}help.",
+                self.prefix, name, self.prefix
+            )),
+        };
+        match reply {
+            Reply::Nothing => Ok(()),
+            Reply::Private(message) => ctx.private_message(chat.client, &message),
+            Reply::Broadcast(message) => ctx.say(&message),
+        }
+    }
+
+    fn sender(&self, client_id: u32) -> Sender {
+        match self.clients.get(client_id) {
+            Some(client) => Sender {
+                client_id,
+                name: client.name.clone(),
+                company_id: self.clients.company(client_id),
+                is_admin: self.admin_names.contains(&client.name)
+                    || self.admin_addresses.contains(&client.address),
+            },
+            None => Sender {
+                client_id,
+                name: format!("client #{}", client_id),
+                company_id: None,
+                is_admin: false,
+            },
+        }
+    }
+
+    /// The lines of the built-in help, either for all commands the sender may
+    /// use or for a single command.
+    fn help(&self, sender: &Sender, topic: &str) -> Vec<String> {
+        let topic = topic
+            .trim_start_matches(self.prefix.as_str())
+            .to_lowercase();
+        let lines: Vec<String> = self
+            .commands
+            .iter()
+            .filter(|command| command.allows(sender))
+            .filter(|command| topic.is_empty() || command.is_named(&topic))
+            .map(|command| format!("{} - {}", command.usage(&self.prefix), command.description))
+            .collect();
+        if lines.is_empty() && !topic.is_empty() {
+            vec![format!("Unknown command {}{}.", self.prefix, topic)]
+        } else {
+            lines
+        }
+    }
+}
+
+impl AdminHandler for Router {
+    fn on_packet(&mut self, ctx: &mut Context, packet: &Packet) -> Result<()> {
+        self.handle_packet(ctx, packet)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::clients::SPECTATOR;
+    use crate::packet::admin::{client_packets, AdminWrite};
+    use crate::types::Date;
+
+    fn client_info(id: u32, name: &str, company_id: u8) -> Packet {
+        Packet::ClientInfo(server_packets::ClientInfo {
+            id,
+            address: "192.0.2.1".to_string(),
+            name: name.to_string(),
+            language: 0,
+            date_joined: Date::from_ymd(1950, 0, 1).unwrap().into(),
+            company_id,
+        })
+    }
+
+    fn chat(client: u32, message: &str) -> Packet {
+        Packet::Chat(server_packets::Chat {
+            action: 3,
+            destination: 0,
+            client,
+            message: message.to_string(),
+            money: None,
+        })
+    }
+
+    fn router() -> Router {
+        Router::new()
+            .admin("Alice")
+            .command(Command::new("Rules", "Show the rules", |_, _| {
+                Ok(Reply::Broadcast("Be nice.".to_string()))
+            }))
+            .command(
+                Command::new("kick", "Kick a client", |ctx, invocation| {
+                    let client = invocation.arguments.integer("client").unwrap();
+                    ctx.rcon(&format!("kick {}", client))?;
+                    Ok(Reply::Private(format!(
+                        "{} kicked {}",
+                        invocation.sender.name, client
+                    )))
+                })
+                .argument(Argument::integer("client"))
+                .argument(Argument::rest("reason").optional())
+                .permission(Permission::Admins),
+            )
+    }
+
+    /// Handle the packets and return the output as written to the server.
+    fn handle(router: &mut Router, packets: &[Packet]) -> Vec<u8> {
+        let mut output = Vec::new();
+        {
+            let mut ctx = Context::new(&mut output);
+            for packet in packets {
+                router.handle_packet(&mut ctx, packet).unwrap();
+            }
+        }
+        output
+    }
+
+    #[test]
+    fn run_commands() {
+        let mut router = router();
+        let output = handle(
+            &mut router,
+            &[
+                client_info(2, "Alice", 0),
+                client_info(3, "Bob", SPECTATOR),
+                chat(3, "!RULES"),
+                chat(3, "!kick 2"),
+                chat(2, "!kick two"),
+                chat(2, "!kick 3 spamming chat"),
+                chat(2, "hello"),
+            ],
+        );
+
+        let mut expected = Vec::new();
+        expected
+            .write_packet(&client_packets::Chat::broadcast("Be nice."))
+            .unwrap();
+        expected
+            .write_packet(&client_packets::Chat::to_client(
+                3,
+                "You are not allowed to use !kick.",
+            ))
+            .unwrap();
+        expected
+            .write_packet(&client_packets::Chat::to_client(
+                2,
+                "Usage: !kick <client> [reason...]",
+            ))
+            .unwrap();
+        expected
+            .write_packet(&client_packets::Rcon { command: "kick 3" })
+            .unwrap();
+        expected
+            .write_packet(&client_packets::Chat::to_client(2, "Alice kicked 3"))
+            .unwrap();
+        assert_eq!(output, expected);
+    }
+
+    #[test]
+    fn help_lists_allowed_commands() {
+        let mut router = router();
+        let output = handle(
+            &mut router,
+            &[
+                client_info(3, "Bob", SPECTATOR),
+                chat(3, "!help"),
+                chat(3, "!help rules"),
+            ],
+        );
+        let mut expected = Vec::new();
+        for _ in 0..2 {
+            expected
+                .write_packet(&client_packets::Chat::to_client(
+                    3,
+                    "!Rules - Show the rules",
+                ))
+                .unwrap();
+        }
+        assert_eq!(output, expected);
+    }
+}
@@ -0,0 +1,122 @@
+//! Keeping track of the clients on a server. [`Clients`] remembers the latest
+//! client info for every connected client, as long as it is updated with all
+//! received packets. Register for `ClientInfo` updates and poll the clients
+//! once after joining to know about the clients that were already connected.
+
+use crate::packet::admin::server_packets::{ClientInfo, Packet};
+use std::collections::HashMap;
+
+/// The company id of clients that are spectating.
+pub const SPECTATOR: u8 = 255;
+
+/// The clients connected to a server.
+#[derive(Clone, Debug, Default)]
+pub struct Clients {
+    clients: HashMap<u32, ClientInfo>,
+}
+
+impl Clients {
+    pub fn new() -> Clients {
+        Clients::default()
+    }
+
+    /// Update the clients with a packet received from the server.
+    pub fn update(&mut self, packet: &Packet) {
+        match packet {
+            Packet::ClientInfo(info) => {
+                self.clients.insert(info.id, info.clone());
+            }
+            Packet::ClientUpdate(update) => {
+                if let Some(client) = self.clients.get_mut(&update.id) {
+                    client.name = update.name.clone();
+                    client.company_id = update.company_id;
+                }
+            }
+            Packet::ClientQuit(quit) => {
+                self.clients.remove(&quit.id);
+            }
+            Packet::ClientError(error) => {
+                self.clients.remove(&error.id);
+            }
+            _ => {}
+        }
+    }
+
+    /// The info of a client.
+    pub fn get(&self, client_id: u32) -> Option<&ClientInfo> {
+        self.clients.get(&client_id)
+    }
+
+    /// The name of a client.
+    pub fn name(&self, client_id: u32) -> Option<&str> {
+        self.get(client_id).map(|client| client.name.as_str())
+    }
+
+    /// The company a client is playing as, or `None` if the client is
+    /// spectating or unknown.
+    pub fn company(&self, client_id: u32) -> Option<u8> {
+        self.get(client_id)
+            .map(|client| client.company_id)
+            .filter(|&company_id| company_id != SPECTATOR)
+    }
+
+    /// Find a client by name.
+    pub fn find_by_name(&self, name: &str) -> Option<&ClientInfo> {
+        self.clients.values().find(|client| client.name == name)
+    }
+
+    /// The clients playing as a company.
+    pub fn in_company(&self, company_id: u8) -> impl Iterator<Item = &ClientInfo> {
+        self.clients
+            .values()
+            .filter(move |client| client.company_id == company_id)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &ClientInfo> {
+        self.clients.values()
+    }
+
+    pub fn len(&self) -> usize {
+        self.clients.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.clients.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::packet::admin::server_packets;
+    use crate::types::Date;
+
+    #[test]
+    fn track_clients() {
+        let mut clients = Clients::new();
+        clients.update(&Packet::ClientInfo(ClientInfo {
+            id: 2,
+            address: "192.0.2.1".to_string(),
+            name: "Player".to_string(),
+            language: 0,
+            date_joined: Date::from_ymd(1950, 0, 1).unwrap().into(),
+            company_id: SPECTATOR,
+        }));
+        assert_eq!(clients.name(2), Some("Player"));
+        assert_eq!(clients.company(2), None);
+
+        clients.update(&Packet::ClientUpdate(server_packets::ClientUpdate {
+            id: 2,
+            name: "Builder".to_string(),
+            company_id: 0,
+        }));
+        assert_eq!(
+            clients.find_by_name("Builder").map(|client| client.id),
+            Some(2)
+        );
+        assert_eq!(clients.company(2), Some(0));
+
+        clients.update(&Packet::ClientQuit(server_packets::ClientQuit { id: 2 }));
+        assert!(clients.is_empty());
+    }
+}
@@ -0,0 +1,226 @@
+//! Decoding logged DoCommands. The server sends the names of its commands in
+//! `CmdNames` packets when polled for `CmdNames`, and every executed command
+//! as a `CmdLogging` packet with only the id of the command. A
+//! [`CommandRegistry`] remembers the names and the map size from the
+//! `Welcome` packet, and decodes logged commands into named commands with
+//! tile coordinates.
+//!
+//! The server names commands after the functions executing them, such as
+//! `CmdBuildRailroadTrack`, not after the `CMD_BUILD_RAILROAD_TRACK`
+//! constants.
+//!
+//! The layout of the command parameters differs between versions of
+//! OpenTTD. For dragged commands whose other end is known to be passed in
+//! `p1` or `p2`, such as `CmdClearArea`, the area is decoded as well.
+
+use crate::packet::admin::server_packets::{CmdLogging, Packet};
+use crate::types::{MapSize, TileIndex};
+use std::collections::HashMap;
+use std::fmt::{self, Display};
+
+/// A parameter of a command.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum Parameter {
+    P1,
+    P2,
+}
+
+/// Commands that act on an area between the tile of the command and the tile
+/// in one of the parameters.
+const AREA_COMMANDS: &[(&str, Parameter)] = &[
+    ("CmdBuildBridge", Parameter::P1),
+    ("CmdBuildCanal", Parameter::P1),
+    ("CmdBuildLongRoad", Parameter::P1),
+    ("CmdBuildRailroadTrack", Parameter::P1),
+    ("CmdBuildSignalTrack", Parameter::P1),
+    ("CmdClearArea", Parameter::P1),
+    ("CmdConvertRail", Parameter::P1),
+    ("CmdLevelLand", Parameter::P1),
+    ("CmdPlantTree", Parameter::P2),
+    ("CmdRemoveLongRoad", Parameter::P1),
+    ("CmdRemoveRailroadTrack", Parameter::P1),
+    ("CmdRemoveSignalTrack", Parameter::P1),
+];
+
+/// A logged command with its name and tile coordinates.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct DecodedCommand {
+    /// The name of the command, such as `CmdBuildSingleRail`, if known.
+    pub name: Option<String>,
+    /// The tile of the command, if the map size is known.
+    pub tile: Option<TileIndex>,
+    /// The other corner of the area of a dragged command.
+    pub area_end: Option<TileIndex>,
+    /// The command as logged.
+    pub logged: CmdLogging,
+}
+
+impl Display for DecodedCommand {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "client #{} (company {}): ",
+            self.logged.client_id, self.logged.company_id
+        )?;
+        match &self.name {
+            Some(name) => f.write_str(name)?,
+            None => write!(f, "command {}", self.logged.command_id)?,
+        }
+        if let Some(tile) = self.tile {
+            write!(f, " at {}", tile)?;
+        }
+        if let Some(area_end) = self.area_end {
+            write!(f, " to {}", area_end)?;
+        }
+        Ok(())
+    }
+}
+
+/// The command names and map size of a server.
+#[derive(Clone, Debug, Default)]
+pub struct CommandRegistry {
+    names: HashMap<u16, String>,
+    ids: HashMap<String, u16>,
+    map_size: Option<MapSize>,
+}
+
+impl CommandRegistry {
+    pub fn new() -> CommandRegistry {
+        CommandRegistry::default()
+    }
+
+    /// Update the registry with a packet received from the server.
+    pub fn update(&mut self, packet: &Packet) {
+        match packet {
+            Packet::CmdNames(cmd_names) => {
+                for cmd_name in &cmd_names.names {
+                    self.names.insert(cmd_name.id, cmd_name.name.clone());
+                    self.ids.insert(cmd_name.name.clone(), cmd_name.id);
+                }
+            }
+            Packet::Welcome(welcome) => {
+                self.set_map_size(welcome.map_size());
+            }
+            _ => {}
+        }
+    }
+
+    /// Set the size of the map in tiles.
+    pub fn set_map_size(&mut self, map_size: MapSize) {
+        self.map_size = Some(map_size);
+    }
+
+    /// The name of a command.
+    pub fn name(&self, command_id: u16) -> Option<&str> {
+        self.names.get(&command_id).map(String::as_str)
+    }
+
+    /// The id of a command by name.
+    pub fn id(&self, name: &str) -> Option<u16> {
+        self.ids.get(name).copied()
+    }
+
+    /// The number of known command names.
+    pub fn len(&self) -> usize {
+        self.names.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.names.is_empty()
+    }
+
+    /// The tile with the given index. Returns `None` if the map size is
+    /// unknown or the tile is outside of the map.
+    pub fn tile(&self, index: u32) -> Option<TileIndex> {
+        TileIndex::new(index, self.map_size?).ok()
+    }
+
+    /// Decode a logged command.
+    pub fn decode(&self, logged: &CmdLogging) -> DecodedCommand {
+        let name = self.name(logged.command_id);
+        let area_end = name
+            .and_then(|name| {
+                AREA_COMMANDS
+                    .iter()
+                    .find(|(command, _)| *command == name)
+                    .map(|(_, parameter)| *parameter)
+            })
+            .and_then(|parameter| {
+                self.tile(match parameter {
+                    Parameter::P1 => logged.p1,
+                    Parameter::P2 => logged.p2,
+                })
+            });
+        DecodedCommand {
+            name: name.map(str::to_string),
+            tile: self.tile(logged.tile),
+            area_end,
+            logged: logged.clone(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::packet::admin::server_packets::{CmdName, CmdNames};
+
+    fn registry() -> CommandRegistry {
+        let mut registry = CommandRegistry::new();
+        let names = |names: &[(u16, &str)]| {
+            Packet::CmdNames(CmdNames {
+                names: names
+                    .iter()
+                    .map(|(id, name)| CmdName {
+                        id: *id,
+                        name: name.to_string(),
+                    })
+                    .collect(),
+            })
+        };
+        // Names as sent by the server, in multiple packets.
+        registry.update(&names(&[
+            (0, "CmdBuildRailroadTrack"),
+            (1, "CmdRemoveRailroadTrack"),
+        ]));
+        registry.update(&names(&[(3, "CmdRemoveSingleRail"), (15, "CmdClearArea")]));
+        registry.set_map_size(MapSize::new(256, 128));
+        registry
+    }
+
+    fn logged(command_id: u16, p1: u32, tile: u32) -> CmdLogging {
+        CmdLogging {
+            client_id: 4,
+            company_id: 0,
+            command_id,
+            p1,
+            p2: 0,
+            tile,
+            text: String::new(),
+            execution_frame: 1000,
+        }
+    }
+
+    #[test]
+    fn decode_commands() {
+        let registry = registry();
+        assert_eq!(registry.len(), 4);
+        assert_eq!(registry.id("CmdRemoveSingleRail"), Some(3));
+
+        let decoded = registry.decode(&logged(3, 2, 256 * 20 + 10));
+        assert_eq!(decoded.name.as_deref(), Some("CmdRemoveSingleRail"));
+        assert_eq!(decoded.tile.map(TileIndex::to_xy), Some((10, 20)));
+        assert_eq!(decoded.area_end, None);
+
+        let decoded = registry.decode(&logged(15, 256 * 25 + 12, 256 * 20 + 10));
+        assert_eq!(decoded.area_end.map(TileIndex::to_xy), Some((12, 25)));
+        assert_eq!(
+            decoded.to_string(),
+            "client #4 (company 0): CmdClearArea at (10, 20) to (12, 25)"
+        );
+
+        let decoded = registry.decode(&logged(99, 0, 256 * 128));
+        assert_eq!(decoded.tile, None);
+        assert_eq!(decoded.to_string(), "client #4 (company 0): command 99");
+    }
+}
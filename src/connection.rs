@@ -0,0 +1,193 @@
+//! Setting up an admin connection. [`Connection::connect`] joins a server
+//! and waits for the protocol and welcome packets, after which updates can be
+//! registered and packets exchanged.
+
+use crate::packet::admin::{client_packets, server_packets, AdminRead, AdminWrite};
+use crate::types::{AdminUpdateType, UpdateFrequencies};
+use std::fmt::{self, Display};
+use std::io::{Read, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+
+/// An error while joining a server.
+#[derive(Debug)]
+pub enum ConnectionError {
+    /// Reading or writing a packet failed.
+    Packet(crate::packet::serde::Error),
+    /// The server is full.
+    Full,
+    /// The address is banned.
+    Banned,
+    /// The server closed the connection because of an error.
+    Server { error_code: u8 },
+    /// The server sent a packet that was not expected while joining.
+    UnexpectedPacket(Box<server_packets::Packet>),
+}
+
+impl Display for ConnectionError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ConnectionError::Packet(err) => err.fmt(f),
+            ConnectionError::Full => f.write_str("the server is full"),
+            ConnectionError::Banned => f.write_str("the address is banned"),
+            ConnectionError::Server { error_code } => {
+                write!(f, "the server returned error {}", error_code)
+            }
+            ConnectionError::UnexpectedPacket(packet) => {
+                write!(f, "unexpected packet {:?}", packet)
+            }
+        }
+    }
+}
+
+impl std::error::Error for ConnectionError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ConnectionError::Packet(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<crate::packet::serde::Error> for ConnectionError {
+    fn from(err: crate::packet::serde::Error) -> ConnectionError {
+        ConnectionError::Packet(err)
+    }
+}
+
+impl From<std::io::Error> for ConnectionError {
+    fn from(err: std::io::Error) -> ConnectionError {
+        ConnectionError::Packet(err.into())
+    }
+}
+
+/// A joined admin connection.
+#[derive(Debug)]
+pub struct Connection<S = TcpStream> {
+    stream: S,
+    protocol: server_packets::Protocol,
+    welcome: server_packets::Welcome,
+}
+
+impl Connection<TcpStream> {
+    /// Connect to a server over TCP and join it.
+    pub fn connect<A: ToSocketAddrs>(
+        address: A,
+        join: &client_packets::Join,
+    ) -> Result<Connection<TcpStream>, ConnectionError> {
+        Connection::join(TcpStream::connect(address)?, join)
+    }
+}
+
+impl<S: Read + Write> Connection<S> {
+    /// Join a server over an existing stream.
+    pub fn join(
+        mut stream: S,
+        join: &client_packets::Join,
+    ) -> Result<Connection<S>, ConnectionError> {
+        stream.write_packet(join)?;
+        let mut protocol = None;
+        loop {
+            match stream.read_packet()? {
+                server_packets::Packet::Protocol(received) => protocol = Some(received),
+                server_packets::Packet::Welcome(welcome) => match protocol {
+                    Some(protocol) => {
+                        return Ok(Connection {
+                            stream,
+                            protocol,
+                            welcome,
+                        })
+                    }
+                    None => {
+                        return Err(ConnectionError::UnexpectedPacket(Box::new(
+                            server_packets::Packet::Welcome(welcome),
+                        )))
+                    }
+                },
+                server_packets::Packet::Full => return Err(ConnectionError::Full),
+                server_packets::Packet::Banned => return Err(ConnectionError::Banned),
+                server_packets::Packet::Error(error) => {
+                    return Err(ConnectionError::Server {
+                        error_code: error.error_code,
+                    })
+                }
+                packet => return Err(ConnectionError::UnexpectedPacket(Box::new(packet))),
+            }
+        }
+    }
+
+    /// The protocol as announced by the server.
+    pub fn protocol(&self) -> &server_packets::Protocol {
+        &self.protocol
+    }
+
+    /// The welcome packet sent by the server.
+    pub fn welcome(&self) -> &server_packets::Welcome {
+        &self.welcome
+    }
+
+    pub fn stream(&self) -> &S {
+        &self.stream
+    }
+
+    pub fn stream_mut(&mut self) -> &mut S {
+        &mut self.stream
+    }
+
+    pub fn into_inner(self) -> S {
+        self.stream
+    }
+
+    /// The frequencies the server allows for an update type.
+    pub fn allowed_frequencies(&self, update_type: AdminUpdateType) -> UpdateFrequencies {
+        self.protocol
+            .update_packets
+            .iter()
+            .find(|description| description.packet_type == update_type)
+            .map(|description| description.frequencies_allowed)
+            .unwrap_or_else(UpdateFrequencies::empty)
+    }
+
+    /// Register for updates of a type at the most frequent interval the
+    /// server allows. Returns false if the server does not send updates of
+    /// this type.
+    pub fn register_updates(
+        &mut self,
+        update_type: AdminUpdateType,
+    ) -> crate::packet::serde::Result<bool> {
+        let allowed = self.allowed_frequencies(update_type);
+        let frequency = [
+            UpdateFrequencies::AUTOMATIC,
+            UpdateFrequencies::DAILY,
+            UpdateFrequencies::WEEKLY,
+            UpdateFrequencies::MONTHLY,
+            UpdateFrequencies::QUARTERLY,
+            UpdateFrequencies::ANNUALLY,
+        ]
+        .iter()
+        .copied()
+        .find(|frequency| allowed.contains(*frequency));
+        match frequency {
+            Some(frequency) => {
+                self.send(&client_packets::UpdateFrequency {
+                    update_type,
+                    frequency,
+                })?;
+                Ok(true)
+            }
+            None => Ok(false),
+        }
+    }
+
+    /// Send a packet to the server.
+    pub fn send<T: client_packets::Packet>(
+        &mut self,
+        packet: &T,
+    ) -> crate::packet::serde::Result<()> {
+        self.stream.write_packet(packet)
+    }
+
+    /// Read the next packet from the server.
+    pub fn read_packet(&mut self) -> crate::packet::serde::Result<server_packets::Packet> {
+        AdminRead::read_packet(&mut self.stream)
+    }
+}
@@ -0,0 +1,438 @@
+//! Classifying and storing the console output of a server.
+//!
+//! [`ConsoleEvent::parse`] recognizes common console lines, such as clients
+//! joining and leaving, new companies, saves, desyncs, network errors and
+//! script errors. Two sinks store the output: [`LogSink`] passes every line
+//! to the `log` crate with the origin as target, and [`RotatingFile`] writes
+//! lines with a timestamp to files that are rotated by size. Subscribers of
+//! the `tracing` crate can receive the log records through `tracing-log`.
+//!
+//! Both sinks implement [`AdminHandler`], so they only need to be registered
+//! for `Console` updates.
+
+use crate::handler::{AdminHandler, Context};
+use crate::packet::admin::server_packets::Console;
+use crate::types::Date;
+use lazy_static::lazy_static;
+use regex::Regex;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+// The patterns follow the format strings of the OpenTTD source. Where they
+// changed, both the format of 1.x and of OpenTTD 12 and later are accepted.
+lazy_static! {
+    /// `AcceptClient` in `network/core/tcp_listen.h`, the same in 1.x and
+    /// 12+: `[%s] Client connected from %s on frame %d`.
+    static ref CONNECTED: Regex =
+        Regex::new(r"^\[server\] Client connected from (\S+) on frame \d+$").unwrap();
+    static ref JOINED: Regex =
+        Regex::new(r"^\*\*\* (.+) has joined the game(?: \(Client #(\d+)\))?$").unwrap();
+    static ref LEFT: Regex = Regex::new(r"^\*\*\* (.+) has left the game \((.*)\)$").unwrap();
+    static ref COMPANY_FOUNDED: Regex =
+        Regex::new(r"^\*\*\* (.+) has started a new company \(#(\d+)\)$").unwrap();
+    /// `ConSave` in `console_cmds.cpp`: `Map successfully saved to %s` in
+    /// 1.x and `Map successfully saved to '{}'.` in 12+.
+    static ref SAVED: Regex =
+        Regex::new(r"^Map successfully saved to (?:'(.+)'\.|(.+))$").unwrap();
+    static ref SCRIPT_ERROR: Regex = Regex::new(r"^\[(\d+)\] \[E\] (.*)$").unwrap();
+    /// A client reporting a desync, in the formats of OpenTTD 12 and later
+    /// and of older versions.
+    static ref DESYNC_REPORT: Regex = Regex::new(
+        r"reported an error and is closing its connection(?:: desync error| \(desync error\))$"
+    )
+    .unwrap();
+}
+
+/// The meaning of a line of console output.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum ConsoleEvent {
+    /// A client connected from an address, before joining.
+    ClientConnected {
+        address: String,
+    },
+    /// A client joined the game.
+    ClientJoined {
+        name: String,
+        client_id: Option<u32>,
+    },
+    /// A client left the game.
+    ClientLeft {
+        name: String,
+        reason: String,
+    },
+    /// A client started a new company.
+    CompanyFounded {
+        name: String,
+        company_id: u8,
+    },
+    /// The game was saved.
+    Saved {
+        file: String,
+    },
+    SaveFailed,
+    /// A client or the server desynced.
+    Desync,
+    /// A network error.
+    NetworkError,
+    /// An AI or GameScript reported an error.
+    ScriptError {
+        company_id: u8,
+        message: String,
+    },
+    /// A line that was not recognized.
+    Other,
+}
+
+impl ConsoleEvent {
+    /// Classify a line of console output.
+    pub fn parse(console: &Console) -> ConsoleEvent {
+        let text = console.text.as_str();
+        if let Some(captures) = CONNECTED.captures(text) {
+            return ConsoleEvent::ClientConnected {
+                address: captures[1].to_string(),
+            };
+        }
+        if let Some(captures) = JOINED.captures(text) {
+            return ConsoleEvent::ClientJoined {
+                name: captures[1].to_string(),
+                client_id: captures.get(2).and_then(|id| id.as_str().parse().ok()),
+            };
+        }
+        if let Some(captures) = LEFT.captures(text) {
+            return ConsoleEvent::ClientLeft {
+                name: captures[1].to_string(),
+                reason: captures[2].to_string(),
+            };
+        }
+        if let Some(captures) = COMPANY_FOUNDED.captures(text) {
+            // The console numbers companies from 1.
+            if let Some(company_id) = captures[2]
+                .parse::<u8>()
+                .ok()
+                .and_then(|id| id.checked_sub(1))
+            {
+                return ConsoleEvent::CompanyFounded {
+                    name: captures[1].to_string(),
+                    company_id,
+                };
+            }
+        }
+        if let Some(captures) = SAVED.captures(text) {
+            if let Some(file) = captures.get(1).or_else(|| captures.get(2)) {
+                return ConsoleEvent::Saved {
+                    file: file.as_str().to_string(),
+                };
+            }
+        }
+        // `ConSave` prints `Saving map failed` in 1.x and `Saving map failed.`
+        // in 12+.
+        if text == "Saving map failed" || text == "Saving map failed." {
+            return ConsoleEvent::SaveFailed;
+        }
+        if console.origin == "desync" || (console.origin == "net" && DESYNC_REPORT.is_match(text)) {
+            return ConsoleEvent::Desync;
+        }
+        if console.origin == "script" {
+            if let Some(captures) = SCRIPT_ERROR.captures(text) {
+                if let Ok(company_id) = captures[1].parse() {
+                    return ConsoleEvent::ScriptError {
+                        company_id,
+                        message: captures[2].to_string(),
+                    };
+                }
+            }
+        }
+        let lowercase = text.to_lowercase();
+        if console.origin == "net" && (lowercase.contains("error") || lowercase.contains("failed"))
+        {
+            return ConsoleEvent::NetworkError;
+        }
+        ConsoleEvent::Other
+    }
+
+    /// The log level that fits the event.
+    pub fn level(&self) -> log::Level {
+        match self {
+            ConsoleEvent::SaveFailed | ConsoleEvent::Desync | ConsoleEvent::ScriptError { .. } => {
+                log::Level::Error
+            }
+            ConsoleEvent::NetworkError => log::Level::Warn,
+            _ => log::Level::Info,
+        }
+    }
+}
+
+/// Passes console output to the `log` crate, with the origin as target.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct LogSink;
+
+impl LogSink {
+    pub fn log(&self, console: &Console) {
+        let level = ConsoleEvent::parse(console).level();
+        log::log!(target: &console.origin, level, "{}", console.text);
+    }
+}
+
+impl AdminHandler for LogSink {
+    fn on_console(
+        &mut self,
+        _ctx: &mut Context,
+        console: &Console,
+    ) -> crate::packet::admin::Result<()> {
+        self.log(console);
+        Ok(())
+    }
+}
+
+/// Writes console output to a file, which is rotated once it reaches a
+/// maximum size. Rotated files get the suffixes `.1`, `.2`, ..., with `.1`
+/// the most recent one.
+#[derive(Debug)]
+pub struct RotatingFile {
+    path: PathBuf,
+    max_bytes: u64,
+    keep: usize,
+    file: File,
+    written: u64,
+}
+
+impl RotatingFile {
+    /// Open or create the file at `path`, rotating it after `max_bytes` and
+    /// keeping `keep` rotated files.
+    pub fn open<P: AsRef<Path>>(path: P, max_bytes: u64, keep: usize) -> io::Result<RotatingFile> {
+        let path = path.as_ref().to_path_buf();
+        let file = OpenOptions::new().create(true).append(true).open(&path)?;
+        let written = file.metadata()?.len();
+        Ok(RotatingFile {
+            path,
+            max_bytes,
+            keep,
+            file,
+            written,
+        })
+    }
+
+    /// Write a line of console output, marked with the current time.
+    pub fn write(&mut self, console: &Console) -> io::Result<()> {
+        self.write_at(console, SystemTime::now())
+    }
+
+    /// Write a line of console output, marked with the given time.
+    pub fn write_at(&mut self, console: &Console, time: SystemTime) -> io::Result<()> {
+        let line = format!(
+            "{} [{}] {}\n",
+            timestamp(time),
+            console.origin,
+            console.text
+        );
+        if self.written > 0 && self.written + line.len() as u64 > self.max_bytes {
+            self.rotate()?;
+        }
+        self.file.write_all(line.as_bytes())?;
+        self.written += line.len() as u64;
+        Ok(())
+    }
+
+    fn rotated_path(&self, index: usize) -> PathBuf {
+        let mut path = self.path.clone().into_os_string();
+        path.push(format!(".{}", index));
+        path.into()
+    }
+
+    fn rotate(&mut self) -> io::Result<()> {
+        self.file.flush()?;
+        if self.keep == 0 {
+            fs::remove_file(&self.path)?;
+        } else {
+            for index in (1..self.keep).rev() {
+                let from = self.rotated_path(index);
+                if from.exists() {
+                    fs::rename(&from, self.rotated_path(index + 1))?;
+                }
+            }
+            fs::rename(&self.path, self.rotated_path(1))?;
+        }
+        self.file = File::create(&self.path)?;
+        self.written = 0;
+        Ok(())
+    }
+}
+
+impl AdminHandler for RotatingFile {
+    fn on_console(
+        &mut self,
+        _ctx: &mut Context,
+        console: &Console,
+    ) -> crate::packet::admin::Result<()> {
+        Ok(self.write(console)?)
+    }
+}
+
+/// Format a time as `YYYY-MM-DDTHH:MM:SSZ`.
+fn timestamp(time: SystemTime) -> String {
+    let seconds = time
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs())
+        .unwrap_or(0);
+    let epoch = Date::from_ymd(1970, 0, 1).unwrap().to_openttd_date();
+    let date = Date::from_openttd_date(epoch + (seconds / 86400) as u32).unwrap();
+    let seconds = seconds % 86400;
+    format!(
+        "{}T{:02}:{:02}:{:02}Z",
+        date,
+        seconds / 3600,
+        seconds / 60 % 60,
+        seconds % 60
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_util::TempDir;
+    use std::time::Duration;
+
+    fn console(origin: &str, text: &str) -> Console {
+        Console {
+            origin: origin.to_string(),
+            text: text.to_string(),
+        }
+    }
+
+    #[test]
+    fn classify_lines() {
+        // 1.x and 12+.
+        assert_eq!(
+            ConsoleEvent::parse(&console(
+                "net",
+                "[server] Client connected from 192.0.2.4 on frame 8123"
+            )),
+            ConsoleEvent::ClientConnected {
+                address: "192.0.2.4".to_string()
+            }
+        );
+        // 1.x and 12+.
+        assert_eq!(
+            ConsoleEvent::parse(&console(
+                "console",
+                "*** Alice has joined the game (Client #4)"
+            )),
+            ConsoleEvent::ClientJoined {
+                name: "Alice".to_string(),
+                client_id: Some(4)
+            }
+        );
+        // 1.x and 12+.
+        assert_eq!(
+            ConsoleEvent::parse(&console("console", "*** Alice has left the game (leaving)")),
+            ConsoleEvent::ClientLeft {
+                name: "Alice".to_string(),
+                reason: "leaving".to_string()
+            }
+        );
+        // 1.x and 12+.
+        assert_eq!(
+            ConsoleEvent::parse(&console(
+                "console",
+                "*** Alice has started a new company (#2)"
+            )),
+            ConsoleEvent::CompanyFounded {
+                name: "Alice".to_string(),
+                company_id: 1
+            }
+        );
+        // 1.x.
+        assert_eq!(
+            ConsoleEvent::parse(&console(
+                "console",
+                "Map successfully saved to save/game.sav"
+            )),
+            ConsoleEvent::Saved {
+                file: "save/game.sav".to_string()
+            }
+        );
+        // 12+.
+        assert_eq!(
+            ConsoleEvent::parse(&console("console", "Map successfully saved to 'game.sav'.")),
+            ConsoleEvent::Saved {
+                file: "game.sav".to_string()
+            }
+        );
+        // 1.x.
+        assert_eq!(
+            ConsoleEvent::parse(&console("console", "Saving map failed")),
+            ConsoleEvent::SaveFailed
+        );
+        // 12+.
+        assert_eq!(
+            ConsoleEvent::parse(&console("console", "Saving map failed.")),
+            ConsoleEvent::SaveFailed
+        );
+        // 1.x and 12+.
+        assert_eq!(
+            ConsoleEvent::parse(&console("desync", "sync_err: 00012f3a; 4c")),
+            ConsoleEvent::Desync
+        );
+        // 12+.
+        assert_eq!(
+            ConsoleEvent::parse(&console(
+                "net",
+                "'Bob' reported an error and is closing its connection: desync error"
+            )),
+            ConsoleEvent::Desync
+        );
+        // 1.x.
+        assert_eq!(
+            ConsoleEvent::parse(&console(
+                "net",
+                "'Bob' reported an error and is closing its connection (desync error)"
+            )),
+            ConsoleEvent::Desync
+        );
+        // Players talking about desyncs are not desyncs. 1.x and 12+.
+        assert_eq!(
+            ConsoleEvent::parse(&console("console", "[All] Bob: did the server desync?")),
+            ConsoleEvent::Other
+        );
+        // 1.x and 12+.
+        assert_eq!(
+            ConsoleEvent::parse(&console("script", "[15] [E] the index 'x' does not exist")),
+            ConsoleEvent::ScriptError {
+                company_id: 15,
+                message: "the index 'x' does not exist".to_string()
+            }
+        );
+        // 12+.
+        assert_eq!(
+            ConsoleEvent::parse(&console("net", "Send failed: Connection reset by peer")),
+            ConsoleEvent::NetworkError
+        );
+        assert_eq!(
+            ConsoleEvent::parse(&console("console", "Hello")),
+            ConsoleEvent::Other
+        );
+    }
+
+    #[test]
+    fn rotate_files() {
+        let directory = TempDir::new("console-log");
+        let path = directory.join("console.log");
+
+        // 1 day, 1 hour, 1 minute and 1 second after the epoch.
+        let time = UNIX_EPOCH + Duration::from_secs(90061);
+        let line = console("net", "abc");
+        let expected = "1970-01-02T01:01:01Z [net] abc\n";
+        let mut file = RotatingFile::open(&path, 2 * expected.len() as u64, 2).unwrap();
+        for _ in 0..7 {
+            file.write_at(&line, time).unwrap();
+        }
+
+        let read = |path: &Path| fs::read_to_string(path).unwrap();
+        assert_eq!(read(&path), expected);
+        assert_eq!(read(&directory.join("console.log.1")), expected.repeat(2));
+        assert_eq!(read(&directory.join("console.log.2")), expected.repeat(2));
+        assert!(!directory.join("console.log.3").exists());
+    }
+}
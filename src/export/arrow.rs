@@ -0,0 +1,223 @@
+//! Arrow record batches of exported records, with the `arrow` feature, and
+//! Parquet files, with the `parquet` feature.
+//!
+//! The columns are the same as in CSV: `date` as an Arrow date, which is
+//! null before the first `Date` packet, `company_id`, `company_name` and a
+//! 64-bit integer column for every field.
+
+use super::{Fields, Record};
+use crate::types::Date;
+use arrow_array::{ArrayRef, Date32Array, Int64Array, RecordBatch, StringArray, UInt8Array};
+use arrow_schema::{ArrowError, DataType, Field, Schema, SchemaRef};
+use std::sync::Arc;
+
+#[cfg(feature = "parquet")]
+pub use self::parquet_file::ParquetWriter;
+
+/// The schema of the record batches of `T`.
+pub fn schema<T: Fields>() -> SchemaRef {
+    let mut fields = vec![
+        Field::new("date", DataType::Date32, true),
+        Field::new("company_id", DataType::UInt8, false),
+        Field::new("company_name", DataType::Utf8, false),
+    ];
+    fields.extend(
+        T::NAMES
+            .iter()
+            .map(|name| Field::new(*name, DataType::Int64, false)),
+    );
+    Arc::new(Schema::new(fields))
+}
+
+/// Convert records to a record batch.
+pub fn record_batch<T: Fields>(records: &[Record<T>]) -> Result<RecordBatch, ArrowError> {
+    // Arrow counts days since 1970-01-01.
+    let epoch = i64::from(Date::from_ymd(1970, 0, 1).unwrap().to_openttd_date());
+    let dates: Date32Array = records
+        .iter()
+        .map(|record| {
+            record
+                .date
+                .map(|date| (i64::from(date.to_openttd_date()) - epoch) as i32)
+        })
+        .collect();
+    let company_ids: UInt8Array = records.iter().map(|record| record.company_id()).collect();
+    let names: StringArray = records
+        .iter()
+        .map(|record| Some(record.company_name.as_str()))
+        .collect();
+    let mut columns: Vec<ArrayRef> = vec![Arc::new(dates), Arc::new(company_ids), Arc::new(names)];
+    let values: Vec<Vec<i64>> = records
+        .iter()
+        .map(|record| record.fields.values())
+        .collect();
+    for index in 0..T::NAMES.len() {
+        let column: Int64Array = values.iter().map(|values| values[index]).collect();
+        columns.push(Arc::new(column));
+    }
+    RecordBatch::try_new(schema::<T>(), columns)
+}
+
+#[cfg(feature = "parquet")]
+mod parquet_file {
+    use super::{record_batch, schema};
+    use crate::export::{Fields, Record, RecordWriter};
+    use parquet::arrow::ArrowWriter;
+    use std::io::{self, Write};
+
+    /// Writes records to a Parquet file, in row groups of at most the batch
+    /// size.
+    #[derive(Debug)]
+    pub struct ParquetWriter<W: Write + Send, T> {
+        writer: Option<ArrowWriter<W>>,
+        output: Option<W>,
+        pending: Vec<Record<T>>,
+        batch_size: usize,
+    }
+
+    impl<W: Write + Send, T: Fields + Clone> ParquetWriter<W, T> {
+        pub fn new(writer: W) -> io::Result<ParquetWriter<W, T>> {
+            let writer =
+                ArrowWriter::try_new(writer, schema::<T>(), None).map_err(io::Error::other)?;
+            Ok(ParquetWriter {
+                writer: Some(writer),
+                output: None,
+                pending: Vec::new(),
+                batch_size: 1024,
+            })
+        }
+
+        /// The number of records to buffer before writing them, 1024 by
+        /// default.
+        pub fn batch_size(mut self, batch_size: usize) -> ParquetWriter<W, T> {
+            self.batch_size = batch_size.max(1);
+            self
+        }
+
+        /// Finish the file and return the writer.
+        pub fn into_inner(mut self) -> io::Result<W> {
+            self.finish()?;
+            Ok(self.output.take().unwrap())
+        }
+
+        fn write_pending(&mut self) -> io::Result<()> {
+            let writer = self
+                .writer
+                .as_mut()
+                .ok_or_else(|| io::Error::other("the Parquet file is finished"))?;
+            if !self.pending.is_empty() {
+                let batch = record_batch(&self.pending).map_err(io::Error::other)?;
+                writer.write(&batch).map_err(io::Error::other)?;
+                writer.flush().map_err(io::Error::other)?;
+                self.pending.clear();
+            }
+            Ok(())
+        }
+    }
+
+    impl<W: Write + Send, T: Fields + Clone> RecordWriter<T> for ParquetWriter<W, T> {
+        fn write(&mut self, record: &Record<T>) -> io::Result<()> {
+            if self.writer.is_none() {
+                return Err(io::Error::other("the Parquet file is finished"));
+            }
+            self.pending.push(record.clone());
+            if self.pending.len() >= self.batch_size {
+                self.write_pending()?;
+            }
+            Ok(())
+        }
+
+        fn finish(&mut self) -> io::Result<()> {
+            if self.writer.is_none() {
+                return Ok(());
+            }
+            self.write_pending()?;
+            let writer = self.writer.take().unwrap();
+            self.output = Some(writer.into_inner().map_err(io::Error::other)?);
+            Ok(())
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::packet::admin::server_packets::CompanyStats;
+    use arrow_array::Array;
+
+    fn stats(id: u8, trains: u16) -> CompanyStats {
+        CompanyStats {
+            id,
+            trains,
+            lorries: 0,
+            busses: 0,
+            planes: 0,
+            ships: 0,
+            train_stations: 0,
+            lorry_stations: 0,
+            bus_stops: 0,
+            airports_and_heliports: 0,
+            harbours: 0,
+        }
+    }
+
+    fn records() -> Vec<Record<CompanyStats>> {
+        vec![
+            Record {
+                date: None,
+                company_name: "Alice Transport".to_string(),
+                fields: stats(0, 1),
+            },
+            Record {
+                date: Some("1970-01-11".parse().unwrap()),
+                company_name: "Company #2".to_string(),
+                fields: stats(1, 4),
+            },
+        ]
+    }
+
+    #[test]
+    fn convert_records() {
+        let batch = record_batch(&records()).unwrap();
+        assert_eq!(batch.num_rows(), 2);
+        assert_eq!(batch.num_columns(), 13);
+        let dates = batch
+            .column(0)
+            .as_any()
+            .downcast_ref::<Date32Array>()
+            .unwrap();
+        assert!(dates.is_null(0));
+        assert_eq!(dates.value(1), 10);
+        let trains = batch
+            .column_by_name("trains")
+            .unwrap()
+            .as_any()
+            .downcast_ref::<Int64Array>()
+            .unwrap();
+        assert_eq!(trains.values().to_vec(), vec![1, 4]);
+    }
+
+    #[cfg(feature = "parquet")]
+    #[test]
+    fn write_parquet() {
+        use crate::export::RecordWriter;
+        use crate::test_util::TempDir;
+        use parquet::file::reader::{FileReader, SerializedFileReader};
+        use std::fs::File;
+
+        let directory = TempDir::new("export-parquet");
+        let path = directory.join("economy.parquet");
+        let mut writer = ParquetWriter::new(File::create(&path).unwrap())
+            .unwrap()
+            .batch_size(1);
+        for record in &records() {
+            writer.write(record).unwrap();
+        }
+        writer.finish().unwrap();
+        drop(writer);
+        let reader = SerializedFileReader::new(File::open(&path).unwrap()).unwrap();
+        let metadata = reader.metadata();
+        assert_eq!(metadata.num_row_groups(), 2);
+        assert_eq!(metadata.file_metadata().num_rows(), 2);
+    }
+}
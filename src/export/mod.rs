@@ -0,0 +1,459 @@
+//! Exporting the time series of company statistics for analysis. An
+//! [`Exporter`] turns every `CompanyEconomy` and `CompanyStats` update into a
+//! [`Record`] with the game date and the name of the company, and writes it
+//! to a [`RecordWriter`]: a [`CsvWriter`], or with the `parquet` feature a
+//! Parquet writer. The `arrow` feature converts records to Arrow record
+//! batches.
+//!
+//! Records are exported live by passing every packet to
+//! [`Exporter::update`], or from a session recorded with a
+//! [`Recorder`](crate::packet::admin::recording::Recorder) with
+//! [`Exporter::export_recording`]. Register for `CompanyInfo`,
+//! `CompanyEconomy`, `CompanyStats` and `Date` updates.
+//!
+//! ```no_run
+//! # use rust_openttd_admin::export::{CsvWriter, Exporter};
+//! # use std::fs::File;
+//! # fn main() -> std::io::Result<()> {
+//! let mut exporter = Exporter::new(
+//!     CsvWriter::new(File::create("economy.csv")?),
+//!     CsvWriter::new(File::create("stats.csv")?),
+//! );
+//! exporter.export_recording(File::open("session.rec")?)?;
+//! exporter.finish()?;
+//! # Ok(())
+//! # }
+//! ```
+
+#[cfg(feature = "arrow")]
+pub mod arrow;
+
+use crate::handler::{AdminHandler, Context};
+use crate::packet::admin::recording::{read_recording, Direction};
+use crate::packet::admin::server_packets::{CompanyEconomy, CompanyStats, Packet};
+use crate::packet::admin::{self, AdminRead};
+use crate::types::Date;
+use std::collections::HashMap;
+use std::io::{self, Read, Write};
+use std::marker::PhantomData;
+
+/// The statistics of a company in a time series.
+pub trait Fields {
+    /// The names of the fields, except the company id.
+    const NAMES: &'static [&'static str];
+
+    fn company_id(&self) -> u8;
+
+    /// The values of the fields, in the order of [`Fields::NAMES`]. Money
+    /// is converted to a signed number.
+    fn values(&self) -> Vec<i64>;
+}
+
+impl Fields for CompanyEconomy {
+    const NAMES: &'static [&'static str] = &[
+        "money",
+        "loan",
+        "income",
+        "delivered_cargo",
+        "company_value_last",
+        "performance_last",
+        "delivered_cargo_last",
+        "company_value_previous",
+        "performance_previous",
+        "delivered_previous",
+    ];
+
+    fn company_id(&self) -> u8 {
+        self.id
+    }
+
+    fn values(&self) -> Vec<i64> {
+        vec![
+            self.money as i64,
+            self.loan as i64,
+            self.income,
+            i64::from(self.delivered_cargo),
+            self.company_value_last as i64,
+            i64::from(self.performance_last),
+            i64::from(self.delivered_cargo_last),
+            self.company_value_previous as i64,
+            i64::from(self.performance_previous),
+            i64::from(self.delivered_previous),
+        ]
+    }
+}
+
+impl Fields for CompanyStats {
+    const NAMES: &'static [&'static str] = &[
+        "trains",
+        "lorries",
+        "busses",
+        "planes",
+        "ships",
+        "train_stations",
+        "lorry_stations",
+        "bus_stops",
+        "airports_and_heliports",
+        "harbours",
+    ];
+
+    fn company_id(&self) -> u8 {
+        self.id
+    }
+
+    fn values(&self) -> Vec<i64> {
+        [
+            self.trains,
+            self.lorries,
+            self.busses,
+            self.planes,
+            self.ships,
+            self.train_stations,
+            self.lorry_stations,
+            self.bus_stops,
+            self.airports_and_heliports,
+            self.harbours,
+        ]
+        .iter()
+        .map(|&value| i64::from(value))
+        .collect()
+    }
+}
+
+/// The statistics of a company at a game date.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Record<T> {
+    /// The latest date received, or `None` before the first `Date` packet.
+    pub date: Option<Date>,
+    pub company_name: String,
+    pub fields: T,
+}
+
+impl<T: Fields> Record<T> {
+    pub fn company_id(&self) -> u8 {
+        self.fields.company_id()
+    }
+}
+
+/// Writes the records of a time series.
+pub trait RecordWriter<T> {
+    fn write(&mut self, record: &Record<T>) -> io::Result<()>;
+
+    /// Write what is still buffered. No records can be written afterwards.
+    fn finish(&mut self) -> io::Result<()>;
+}
+
+/// Writes records as CSV, with the columns `date`, `company_id`,
+/// `company_name` and the fields. The header is written with the first
+/// record.
+#[derive(Debug)]
+pub struct CsvWriter<W, T> {
+    writer: W,
+    header_written: bool,
+    fields: PhantomData<T>,
+}
+
+impl<W: Write, T: Fields> CsvWriter<W, T> {
+    pub fn new(writer: W) -> CsvWriter<W, T> {
+        CsvWriter {
+            writer,
+            header_written: false,
+            fields: PhantomData,
+        }
+    }
+
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+}
+
+impl<W: Write, T: Fields> RecordWriter<T> for CsvWriter<W, T> {
+    fn write(&mut self, record: &Record<T>) -> io::Result<()> {
+        if !self.header_written {
+            self.header_written = true;
+            write!(self.writer, "date,company_id,company_name")?;
+            for name in T::NAMES {
+                write!(self.writer, ",{}", name)?;
+            }
+            writeln!(self.writer)?;
+        }
+        if let Some(date) = record.date {
+            write!(self.writer, "{}", date)?;
+        }
+        write!(
+            self.writer,
+            ",{},{}",
+            record.company_id(),
+            escape(&record.company_name)
+        )?;
+        for value in record.fields.values() {
+            write!(self.writer, ",{}", value)?;
+        }
+        writeln!(self.writer)
+    }
+
+    fn finish(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+}
+
+/// Quote a CSV field if needed. Fields that a spreadsheet would read as a
+/// formula, such as company names chosen by players, are prefixed with `'`.
+fn escape(field: &str) -> String {
+    let field = if field.starts_with(['=', '+', '-', '@', '\t', '\r']) {
+        format!("'{}", field)
+    } else {
+        field.to_string()
+    };
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field
+    }
+}
+
+/// Turns company updates into records and writes them.
+#[derive(Debug)]
+pub struct Exporter<E, S> {
+    economy: E,
+    stats: S,
+    date: Option<Date>,
+    names: HashMap<u8, String>,
+}
+
+impl<E, S> Exporter<E, S>
+where
+    E: RecordWriter<CompanyEconomy>,
+    S: RecordWriter<CompanyStats>,
+{
+    /// An exporter writing economy updates to `economy` and statistics
+    /// updates to `stats`.
+    pub fn new(economy: E, stats: S) -> Exporter<E, S> {
+        Exporter {
+            economy,
+            stats,
+            date: None,
+            names: HashMap::new(),
+        }
+    }
+
+    /// Write a record if the packet is a company update.
+    pub fn update(&mut self, packet: &Packet) -> io::Result<()> {
+        match packet {
+            Packet::Date(date) => self.date = Some(date.date),
+            Packet::Newgame => {
+                self.date = None;
+                self.names.clear();
+            }
+            Packet::CompanyInfo(info) => {
+                self.names.insert(info.id, info.name.clone());
+            }
+            Packet::CompanyUpdate(update) => {
+                self.names.insert(update.id, update.name.clone());
+            }
+            Packet::CompanyRemove(remove) => {
+                self.names.remove(&remove.id);
+            }
+            Packet::CompanyEconomy(economy) => {
+                let record = self.record(*economy);
+                self.economy.write(&record)?;
+            }
+            Packet::CompanyStats(stats) => {
+                let record = self.record(*stats);
+                self.stats.write(&record)?;
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    fn record<T: Fields>(&self, fields: T) -> Record<T> {
+        let company_id = fields.company_id();
+        Record {
+            date: self.date,
+            company_name: self
+                .names
+                .get(&company_id)
+                .cloned()
+                .unwrap_or_else(|| format!("Company #{}", u32::from(company_id) + 1)),
+            fields,
+        }
+    }
+
+    /// Export the packets received in a recording. A corrupt recording fails
+    /// with [`io::ErrorKind::InvalidData`].
+    pub fn export_recording<R: Read>(&mut self, recording: R) -> io::Result<()> {
+        for frame in read_recording(recording)? {
+            if frame.direction != Direction::Received {
+                continue;
+            }
+            let packet = (&frame.to_packet_bytes()?[..])
+                .read_packet()
+                .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+            self.update(&packet)?;
+        }
+        Ok(())
+    }
+
+    /// Finish both writers.
+    pub fn finish(&mut self) -> io::Result<()> {
+        self.economy.finish()?;
+        self.stats.finish()
+    }
+
+    pub fn into_inner(self) -> (E, S) {
+        (self.economy, self.stats)
+    }
+}
+
+impl<E, S> AdminHandler for Exporter<E, S>
+where
+    E: RecordWriter<CompanyEconomy>,
+    S: RecordWriter<CompanyStats>,
+{
+    fn on_packet(&mut self, _ctx: &mut Context, packet: &Packet) -> admin::Result<()> {
+        Ok(self.update(packet)?)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::packet::admin::server_packets;
+
+    fn packets() -> Vec<Packet> {
+        vec![
+            Packet::CompanyInfo(server_packets::CompanyInfo {
+                id: 0,
+                name: "Alice, \"Transport\"".to_string(),
+                manager: "Alice".to_string(),
+                color: 0,
+                password_protected: false,
+                inaugurated_year: 1950,
+                ai: false,
+            }),
+            Packet::Date(server_packets::Date {
+                date: "1950-02-01".parse().unwrap(),
+            }),
+            Packet::CompanyEconomy(CompanyEconomy {
+                id: 0,
+                money: 100_000,
+                loan: 300_000,
+                income: -2_000,
+                delivered_cargo: 10,
+                company_value_last: 50_000,
+                performance_last: 120,
+                delivered_cargo_last: 5,
+                company_value_previous: 40_000,
+                performance_previous: 100,
+                delivered_previous: 4,
+            }),
+            Packet::CompanyStats(CompanyStats {
+                id: 1,
+                trains: 3,
+                lorries: 2,
+                busses: 1,
+                planes: 0,
+                ships: 0,
+                train_stations: 4,
+                lorry_stations: 1,
+                bus_stops: 2,
+                airports_and_heliports: 0,
+                harbours: 0,
+            }),
+        ]
+    }
+
+    const ECONOMY_CSV: &str = "\
+date,company_id,company_name,money,loan,income,delivered_cargo,company_value_last,\
+performance_last,delivered_cargo_last,company_value_previous,performance_previous,\
+delivered_previous
+1950-02-01,0,\"Alice, \"\"Transport\"\"\",100000,300000,-2000,10,50000,120,5,40000,100,4
+";
+
+    const STATS_CSV: &str = "\
+date,company_id,company_name,trains,lorries,busses,planes,ships,train_stations,\
+lorry_stations,bus_stops,airports_and_heliports,harbours
+1950-02-01,1,Company #2,3,2,1,0,0,4,1,2,0,0
+";
+
+    type CsvExporter =
+        Exporter<CsvWriter<Vec<u8>, CompanyEconomy>, CsvWriter<Vec<u8>, CompanyStats>>;
+
+    fn csv_exporter() -> CsvExporter {
+        Exporter::new(CsvWriter::new(Vec::new()), CsvWriter::new(Vec::new()))
+    }
+
+    fn csv_output(exporter: CsvExporter) -> (String, String) {
+        let (economy, stats) = exporter.into_inner();
+        (
+            String::from_utf8(economy.into_inner()).unwrap(),
+            String::from_utf8(stats.into_inner()).unwrap(),
+        )
+    }
+
+    #[test]
+    fn export_csv() {
+        let mut exporter = csv_exporter();
+        for packet in &packets() {
+            exporter.update(packet).unwrap();
+        }
+        exporter.finish().unwrap();
+        assert_eq!(
+            csv_output(exporter),
+            (ECONOMY_CSV.to_string(), STATS_CSV.to_string())
+        );
+    }
+
+    #[test]
+    fn escape_formulas() {
+        assert_eq!(escape("=HYPERLINK(\"x\")"), "\"'=HYPERLINK(\"\"x\"\")\"");
+        assert_eq!(escape("@SUM(A1)"), "'@SUM(A1)");
+        assert_eq!(escape("-1+2"), "'-1+2");
+        assert_eq!(escape("Alice + Bob"), "Alice + Bob");
+    }
+
+    #[test]
+    fn removed_company() {
+        let mut exporter = csv_exporter();
+        let mut packets = packets();
+        packets.insert(
+            3,
+            Packet::CompanyRemove(server_packets::CompanyRemove { id: 0, reason: 0 }),
+        );
+        packets.push(packets[2].clone());
+        for packet in &packets {
+            exporter.update(packet).unwrap();
+        }
+        let (economy, _) = csv_output(exporter);
+        // The name of a removed company is forgotten.
+        assert_eq!(
+            economy.lines().nth(2),
+            Some("1950-02-01,0,Company #1,100000,300000,-2000,10,50000,120,5,40000,100,4")
+        );
+    }
+
+    #[test]
+    fn export_recording() {
+        let recording: &[u8] = include_bytes!("../packet/admin/test/fixtures/company_economy.rec");
+        let mut exporter = csv_exporter();
+        exporter.export_recording(recording).unwrap();
+        let (economy, stats) = csv_output(exporter);
+        // The economy update arrived before the first date.
+        assert_eq!(
+            economy.lines().nth(1),
+            Some(",0,Company #1,99642,100000,-358,0,0,0,0,0,0,0")
+        );
+        assert_eq!(stats, "");
+    }
+
+    #[test]
+    fn export_corrupt_recording() {
+        let mut recording = b"OTTDREC\x01".to_vec();
+        recording.extend_from_slice(&[0; 8]);
+        recording.extend_from_slice(&[0, 117, 0xff, 0xff]);
+        recording.resize(recording.len() + 0xffff, 0);
+        let err = csv_exporter().export_recording(&recording[..]).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+}
@@ -0,0 +1,215 @@
+//! Relating game dates to real time. A [`GameClock`] records when `Date`
+//! packets arrive, estimates how fast the game runs and predicts when a game
+//! date will be reached, for example to announce an event in advance.
+//!
+//! The server only sends a `Date` packet when the date changes, so a paused
+//! game is noticed by the absence of packets: if no date arrived for a few
+//! times the usual interval, the game is considered paused. This works for
+//! daily as well as monthly `Date` updates.
+
+use crate::packet::admin::server_packets::Packet;
+use crate::types::Date;
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+/// The number of game days per second at normal speed: a day lasts 74 ticks
+/// of 30 milliseconds.
+pub const NORMAL_DAYS_PER_SECOND: f64 = 1000.0 / (74.0 * 30.0);
+
+/// The game is considered paused when no date arrived for this many times
+/// the usual interval.
+const PAUSE_FACTOR: f64 = 3.0;
+
+/// The speed relative to normal above which the game is considered to be
+/// fast-forwarding.
+const FAST_FORWARD_SPEED: f64 = 1.5;
+
+/// Estimates the speed of the game from the dates received.
+#[derive(Clone, Debug)]
+pub struct GameClock {
+    samples: VecDeque<(Instant, Date)>,
+    max_samples: usize,
+}
+
+impl Default for GameClock {
+    fn default() -> GameClock {
+        GameClock::new()
+    }
+}
+
+impl GameClock {
+    /// A clock that estimates the speed from the last 8 dates.
+    pub fn new() -> GameClock {
+        GameClock::with_samples(8)
+    }
+
+    /// A clock that estimates the speed from the last `samples` dates, at
+    /// least 2.
+    pub fn with_samples(samples: usize) -> GameClock {
+        GameClock {
+            samples: VecDeque::new(),
+            max_samples: samples.max(2),
+        }
+    }
+
+    /// Update the clock with a packet received from the server.
+    pub fn update(&mut self, packet: &Packet) {
+        self.update_at(packet, Instant::now());
+    }
+
+    /// Like [`GameClock::update`], using `now` as the time the packet
+    /// arrived.
+    pub fn update_at(&mut self, packet: &Packet, now: Instant) {
+        match packet {
+            Packet::Date(date) => self.record(now, date.date),
+            Packet::Newgame => self.samples.clear(),
+            _ => {}
+        }
+    }
+
+    /// Record that the game reached `date` at `now`.
+    pub fn record(&mut self, now: Instant, date: Date) {
+        if let Some(&(last_time, last_date)) = self.samples.back() {
+            let resumed = self
+                .expected_interval()
+                .map(|interval| now.duration_since(last_time) > interval.mul_f64(PAUSE_FACTOR))
+                .unwrap_or(false);
+            // Start over when another game was loaded, and do not count a
+            // pause when estimating the speed.
+            if date < last_date || resumed {
+                self.samples.clear();
+            }
+        }
+        self.samples.push_back((now, date));
+        while self.samples.len() > self.max_samples {
+            self.samples.pop_front();
+        }
+    }
+
+    /// The latest date and when it arrived.
+    pub fn latest(&self) -> Option<(Instant, Date)> {
+        self.samples.back().copied()
+    }
+
+    /// The average time between two dates.
+    pub fn expected_interval(&self) -> Option<Duration> {
+        let (&(first, _), &(last, _)) = (self.samples.front()?, self.samples.back()?);
+        let intervals = self.samples.len() as u32 - 1;
+        if intervals == 0 {
+            None
+        } else {
+            Some(last.duration_since(first) / intervals)
+        }
+    }
+
+    /// The estimated number of game days per second while the game runs, or
+    /// `None` if there are too few dates.
+    pub fn days_per_second(&self) -> Option<f64> {
+        let (&(first_time, first_date), &(last_time, last_date)) =
+            (self.samples.front()?, self.samples.back()?);
+        let seconds = last_time.duration_since(first_time).as_secs_f64();
+        if seconds <= 0.0 {
+            return None;
+        }
+        Some(first_date.days_between(last_date) as f64 / seconds)
+    }
+
+    /// The speed of the game relative to normal speed.
+    pub fn speed(&self) -> Option<f64> {
+        self.days_per_second()
+            .map(|days_per_second| days_per_second / NORMAL_DAYS_PER_SECOND)
+    }
+
+    /// Returns true if the game runs faster than normal.
+    pub fn is_fast_forwarding(&self) -> bool {
+        self.speed()
+            .map(|speed| speed > FAST_FORWARD_SPEED)
+            .unwrap_or(false)
+    }
+
+    /// Returns true if no date arrived for a few times the usual interval.
+    pub fn is_paused(&self) -> bool {
+        self.is_paused_at(Instant::now())
+    }
+
+    /// Like [`GameClock::is_paused`], at the time `now`.
+    pub fn is_paused_at(&self, now: Instant) -> bool {
+        match (self.latest(), self.expected_interval()) {
+            (Some((last_time, _)), Some(interval)) => {
+                now.saturating_duration_since(last_time) > interval.mul_f64(PAUSE_FACTOR)
+            }
+            _ => false,
+        }
+    }
+
+    /// The estimated date at `now`. While paused, this is the latest date.
+    pub fn estimated_date_at(&self, now: Instant) -> Option<Date> {
+        let (last_time, last_date) = self.latest()?;
+        let days_per_second = match self.days_per_second() {
+            Some(days_per_second) if !self.is_paused_at(now) => days_per_second,
+            _ => return Some(last_date),
+        };
+        let days = now.saturating_duration_since(last_time).as_secs_f64() * days_per_second;
+        last_date.checked_add_days(days as u32)
+    }
+
+    /// Predict when the game reaches `date`, assuming it keeps running at the
+    /// estimated speed. Returns `None` if the speed is unknown or the game
+    /// does not advance, and the time of the latest date if `date` has
+    /// already been reached.
+    pub fn predict(&self, date: Date) -> Option<Instant> {
+        let (last_time, last_date) = self.latest()?;
+        let days = last_date.days_between(date);
+        if days <= 0 {
+            return Some(last_time);
+        }
+        let days_per_second = self.days_per_second().filter(|&speed| speed > 0.0)?;
+        Some(last_time + Duration::from_secs_f64(days as f64 / days_per_second))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn estimate_speed() {
+        let start = Instant::now();
+        let first: Date = "1950-01-01".parse().unwrap();
+        let mut clock = GameClock::new();
+        assert_eq!(clock.days_per_second(), None);
+
+        // A day every 2.22 seconds is the normal speed.
+        let day = Duration::from_millis(2220);
+        for days in 0..5 {
+            clock.record(start + day * days, first + days);
+        }
+        assert!((clock.speed().unwrap() - 1.0).abs() < 1e-6);
+        assert!(!clock.is_fast_forwarding());
+        let last = start + day * 4;
+        assert!(!clock.is_paused_at(last + day * 2));
+        let later = last + day * 2 + Duration::from_millis(10);
+        assert_eq!(clock.estimated_date_at(later), Some(first + 6));
+        let predicted = clock.predict(first + 14).unwrap();
+        let error = (predicted - last).as_secs_f64() - (day * 10).as_secs_f64();
+        assert!(error.abs() < 1e-3);
+
+        // No date for a while: the game is paused.
+        assert!(clock.is_paused_at(last + day * 4));
+        assert_eq!(clock.estimated_date_at(last + day * 4), Some(first + 4));
+
+        // After the pause the game runs 4 times as fast; the pause itself is
+        // not counted.
+        let resumed = last + day * 100;
+        clock.record(resumed, first + 5);
+        for days in 1..4 {
+            clock.record(resumed + day / 4 * days, first + 5 + days);
+        }
+        assert!((clock.speed().unwrap() - 4.0).abs() < 1e-6);
+        assert!(clock.is_fast_forwarding());
+
+        // A new game starts over.
+        clock.record(resumed + day * 2, first);
+        assert_eq!(clock.days_per_second(), None);
+    }
+}
@@ -0,0 +1,347 @@
+//! Typed messages for the GameScript. The admin port passes JSON between
+//! admin connections and the GameScript; this module converts it from and to
+//! types implementing `Serialize` and `DeserializeOwned`. It is available
+//! with the `json` feature.
+//!
+//! For a request/response pattern, [`Bridge`] adds a correlation id to every
+//! request object. The GameScript should copy the id into its response, so
+//! the response can be matched with the request:
+//!
+//! ```json
+//! {"id": 3, "action": "create_goal", "company": 0, "text": "Connect two cities"}
+//! {"id": 3, "goal": 12}
+//! ```
+
+use crate::connection::Connection;
+use crate::packet::admin::server_packets::{self, Packet};
+use crate::packet::admin::{client_packets, AdminWrite};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::collections::HashSet;
+use std::fmt::{self, Display};
+use std::io::{Read, Write};
+
+/// The maximum length of the JSON that OpenTTD 1.x servers accept from an
+/// admin connection: NETWORK_GAMESCRIPT_JSON_LENGTH, which is COMPAT_MTU - 3,
+/// without the terminating zero. Longer messages are truncated by the
+/// server. Newer servers accept longer messages; use
+/// [`to_json_with_limit`], [`send_with_limit`] or [`Bridge::max_length`] to
+/// send them.
+pub const MAX_JSON_LENGTH: usize = 1456;
+
+/// An error while exchanging messages with the GameScript.
+#[derive(Debug)]
+pub enum GamescriptError {
+    /// The message could not be converted from or to JSON.
+    Json(serde_json::Error),
+    /// The JSON is longer than the limit, [`MAX_JSON_LENGTH`] by default.
+    TooLong { length: usize, max_length: usize },
+    /// A request is not a JSON object, so no id can be added.
+    NotAnObject,
+    /// Reading or writing a packet failed.
+    Packet(crate::packet::serde::Error),
+}
+
+impl Display for GamescriptError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            GamescriptError::Json(err) => err.fmt(f),
+            GamescriptError::TooLong { length, max_length } => write!(
+                f,
+                "the JSON is {} bytes long, but at most {} bytes are allowed",
+                length, max_length
+            ),
+            GamescriptError::NotAnObject => f.write_str("a request should be a JSON object"),
+            GamescriptError::Packet(err) => err.fmt(f),
+        }
+    }
+}
+
+impl std::error::Error for GamescriptError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            GamescriptError::Json(err) => Some(err),
+            GamescriptError::Packet(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<serde_json::Error> for GamescriptError {
+    fn from(err: serde_json::Error) -> GamescriptError {
+        GamescriptError::Json(err)
+    }
+}
+
+impl From<crate::packet::serde::Error> for GamescriptError {
+    fn from(err: crate::packet::serde::Error) -> GamescriptError {
+        GamescriptError::Packet(err)
+    }
+}
+
+pub type Result<T> = std::result::Result<T, GamescriptError>;
+
+/// Convert a message to JSON, checking that it is at most
+/// [`MAX_JSON_LENGTH`] bytes long so every server will accept it.
+pub fn to_json<T: Serialize>(message: &T) -> Result<String> {
+    to_json_with_limit(message, MAX_JSON_LENGTH)
+}
+
+/// Convert a message to JSON of at most `max_length` bytes.
+pub fn to_json_with_limit<T: Serialize>(message: &T, max_length: usize) -> Result<String> {
+    let json = serde_json::to_string(message)?;
+    if json.len() > max_length {
+        Err(GamescriptError::TooLong {
+            length: json.len(),
+            max_length,
+        })
+    } else {
+        Ok(json)
+    }
+}
+
+/// Send a message to the GameScript.
+pub fn send<W: Write, T: Serialize>(writer: &mut W, message: &T) -> Result<()> {
+    send_with_limit(writer, message, MAX_JSON_LENGTH)
+}
+
+/// Send a message of at most `max_length` bytes of JSON to the GameScript.
+pub fn send_with_limit<W: Write, T: Serialize>(
+    writer: &mut W,
+    message: &T,
+    max_length: usize,
+) -> Result<()> {
+    let json = to_json_with_limit(message, max_length)?;
+    writer.write_packet(&client_packets::Gamescript { json: &json })?;
+    Ok(())
+}
+
+/// Read a message sent by the GameScript.
+pub fn parse<T: DeserializeOwned>(gamescript: &server_packets::Gamescript) -> Result<T> {
+    Ok(serde_json::from_str(&gamescript.json)?)
+}
+
+/// Matches responses of the GameScript with requests, using a correlation
+/// id in the JSON objects.
+#[derive(Clone, Debug)]
+pub struct Bridge {
+    id_field: String,
+    max_length: usize,
+    next_id: u64,
+    pending: HashSet<u64>,
+}
+
+impl Default for Bridge {
+    fn default() -> Bridge {
+        Bridge::new()
+    }
+}
+
+impl Bridge {
+    /// A bridge that puts the correlation id in the `id` field.
+    pub fn new() -> Bridge {
+        Bridge::with_id_field("id")
+    }
+
+    /// A bridge that puts the correlation id in the given field, for
+    /// GameScripts that already use `id` for something else.
+    pub fn with_id_field(id_field: &str) -> Bridge {
+        Bridge {
+            id_field: id_field.to_string(),
+            max_length: MAX_JSON_LENGTH,
+            next_id: 0,
+            pending: HashSet::new(),
+        }
+    }
+
+    /// Allow requests of up to `max_length` bytes of JSON, instead of
+    /// [`MAX_JSON_LENGTH`], for servers that accept longer messages.
+    pub fn max_length(mut self, max_length: usize) -> Bridge {
+        self.max_length = max_length;
+        self
+    }
+
+    /// Convert a request to JSON with a new correlation id. Returns the id
+    /// and the JSON to send.
+    pub fn request<T: Serialize>(&mut self, request: &T) -> Result<(u64, String)> {
+        let mut value = serde_json::to_value(request)?;
+        let id = self.next_id;
+        value
+            .as_object_mut()
+            .ok_or(GamescriptError::NotAnObject)?
+            .insert(self.id_field.clone(), id.into());
+        let json = to_json_with_limit(&value, self.max_length)?;
+        self.next_id += 1;
+        self.pending.insert(id);
+        Ok((id, json))
+    }
+
+    /// Send a request to the GameScript and return its correlation id.
+    pub fn send_request<W: Write, T: Serialize>(
+        &mut self,
+        writer: &mut W,
+        request: &T,
+    ) -> Result<u64> {
+        let (id, json) = self.request(request)?;
+        writer.write_packet(&client_packets::Gamescript { json: &json })?;
+        Ok(id)
+    }
+
+    /// Returns the id of the pending request a message of the GameScript
+    /// responds to, without consuming the response.
+    pub fn response_id(&self, gamescript: &server_packets::Gamescript) -> Option<u64> {
+        // Not every message of the GameScript has to be JSON we understand.
+        let value = serde_json::from_str(&gamescript.json).ok()?;
+        self.pending_id(&value)
+    }
+
+    fn pending_id(&self, value: &serde_json::Value) -> Option<u64> {
+        value
+            .get(&self.id_field)
+            .and_then(serde_json::Value::as_u64)
+            .filter(|id| self.pending.contains(id))
+    }
+
+    /// Match a message of the GameScript with a pending request. Returns
+    /// `None` if the message is not a response to a pending request. The
+    /// request is no longer pending afterwards, even if the response cannot
+    /// be converted to `T`.
+    pub fn response<T: DeserializeOwned>(
+        &mut self,
+        gamescript: &server_packets::Gamescript,
+    ) -> Result<Option<(u64, T)>> {
+        let value: serde_json::Value = match serde_json::from_str(&gamescript.json) {
+            Ok(value) => value,
+            Err(_) => return Ok(None),
+        };
+        let id = match self.pending_id(&value) {
+            Some(id) => id,
+            None => return Ok(None),
+        };
+        self.pending.remove(&id);
+        Ok(Some((id, serde_json::from_value(value)?)))
+    }
+
+    /// Returns true if no responses are outstanding.
+    pub fn is_idle(&self) -> bool {
+        self.pending.is_empty()
+    }
+}
+
+/// Send a request and wait for the response. Other packets received in the
+/// meantime are passed to `other`, including responses to other pending
+/// requests, which stay pending.
+pub fn call<S, Req, Resp, F>(
+    connection: &mut Connection<S>,
+    bridge: &mut Bridge,
+    request: &Req,
+    mut other: F,
+) -> Result<Resp>
+where
+    S: Read + Write,
+    Req: Serialize,
+    Resp: DeserializeOwned,
+    F: FnMut(Packet),
+{
+    let (id, json) = bridge.request(request)?;
+    connection.send(&client_packets::Gamescript { json: &json })?;
+    loop {
+        match connection.read_packet()? {
+            Packet::Gamescript(gamescript) if bridge.response_id(&gamescript) == Some(id) => {
+                if let Some((_, response)) = bridge.response(&gamescript)? {
+                    return Ok(response);
+                }
+            }
+            packet => other(packet),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use serde_derive::{Deserialize, Serialize};
+
+    #[derive(Serialize)]
+    struct CreateGoal<'a> {
+        action: &'a str,
+        company: u8,
+        text: &'a str,
+    }
+
+    #[derive(Deserialize, Debug, Eq, PartialEq)]
+    struct GoalCreated {
+        goal: u32,
+    }
+
+    #[test]
+    fn request_response() {
+        let mut bridge = Bridge::new();
+        let (id, json) = bridge
+            .request(&CreateGoal {
+                action: "create_goal",
+                company: 0,
+                text: "Connect two cities",
+            })
+            .unwrap();
+        assert_eq!(id, 0);
+        assert_eq!(
+            serde_json::from_str::<serde_json::Value>(&json).unwrap(),
+            serde_json::json!({
+                "id": 0,
+                "action": "create_goal",
+                "company": 0,
+                "text": "Connect two cities"
+            })
+        );
+
+        let message = |json: &str| server_packets::Gamescript {
+            json: json.to_string(),
+        };
+        assert_eq!(
+            bridge
+                .response::<GoalCreated>(&message(r#"{"event": "goal_completed"}"#))
+                .unwrap(),
+            None
+        );
+        assert_eq!(
+            bridge.response_id(&message(r#"{"id": 0, "goal": 12}"#)),
+            Some(0)
+        );
+        assert_eq!(
+            bridge.response_id(&message(r#"{"id": 1, "goal": 12}"#)),
+            None
+        );
+        assert!(!bridge.is_idle());
+        assert_eq!(
+            bridge
+                .response::<GoalCreated>(&message(r#"{"id": 0, "goal": 12}"#))
+                .unwrap(),
+            Some((0, GoalCreated { goal: 12 }))
+        );
+        assert!(bridge.is_idle());
+    }
+
+    #[test]
+    fn size_limit() {
+        let text = "a".repeat(MAX_JSON_LENGTH);
+        match send(&mut Vec::new(), &text) {
+            Err(GamescriptError::TooLong { length, max_length }) => {
+                assert_eq!(length, MAX_JSON_LENGTH + 2);
+                assert_eq!(max_length, MAX_JSON_LENGTH);
+            }
+            result => panic!("unexpected result {:?}", result),
+        }
+        assert!(send(&mut Vec::new(), &"a".repeat(MAX_JSON_LENGTH - 2)).is_ok());
+        assert!(send_with_limit(&mut Vec::new(), &text, 8000).is_ok());
+
+        let mut bridge = Bridge::new().max_length(8000);
+        let text = "a".repeat(5000);
+        let request = CreateGoal {
+            action: "create_goal",
+            company: 0,
+            text: &text,
+        };
+        assert!(bridge.request(&request).is_ok());
+    }
+}
@@ -0,0 +1,623 @@
+//! Detecting griefing from logged commands. A [`Detector`] watches the
+//! `CmdLogging` updates and raises an [`Alert`] when a client:
+//!
+//! - demolishes many tiles built by another company;
+//! - removes or sells a lot in a short time;
+//! - removes a lot of rail in a small area in a short time;
+//! - removes or demolishes anything right after switching from spectating to
+//!   a company.
+//!
+//! The admin port does not tell who owns a tile, so the detector remembers
+//! which company last built on a tile and only knows about tiles built while
+//! it was watching. Everything is forgotten when a new game starts, and the
+//! tiles of a company when it is removed.
+//!
+//! Register for `CmdNames`, `CmdLogging` and `ClientInfo` updates, and poll
+//! the command names once after joining. Every alert is kept and logged
+//! using the `log` crate; clients can optionally be kicked right away.
+
+use crate::clients::{Clients, SPECTATOR};
+use crate::command_log::{CommandRegistry, DecodedCommand};
+use crate::handler::{AdminHandler, Context};
+use crate::packet::admin::server_packets::Packet;
+use crate::packet::admin::Result;
+use crate::rcon::{Command, Target};
+use crate::types::{Date, TileIndex};
+use std::collections::{HashMap, VecDeque};
+use std::fmt::{self, Display};
+use std::time::{Duration, Instant};
+
+/// Commands without a client (by AIs and GameScripts) and commands of the
+/// server itself have client ids up to this one.
+const SERVER_CLIENT_ID: u32 = 1;
+
+/// Commands that demolish whatever is on a tile or an area.
+const DEMOLISH_COMMANDS: &[&str] = &["CmdLandscapeClear", "CmdClearArea"];
+
+/// Commands that remove rail.
+const RAIL_REMOVAL_COMMANDS: &[&str] = &["CmdRemoveSingleRail", "CmdRemoveRailroadTrack"];
+
+/// Returns true for commands that remove or sell something.
+fn is_removal(name: &str) -> bool {
+    name.starts_with("CmdRemove")
+        || name.starts_with("CmdSell")
+        || DEMOLISH_COMMANDS.contains(&name)
+}
+
+/// What a client is suspected of.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Suspicion {
+    /// The client demolished tiles built by other companies.
+    DemolishedOthers { tiles: usize },
+    /// The client removed or sold a lot in a short time.
+    MassRemoval { commands: usize },
+    /// The client removed a lot of rail in a small area.
+    RailRemoval { commands: usize },
+    /// The client removed something right after leaving the spectators.
+    NewPlayer { command: String },
+}
+
+impl Display for Suspicion {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Suspicion::DemolishedOthers { tiles } => {
+                write!(f, "demolished {} tiles of other companies", tiles)
+            }
+            Suspicion::MassRemoval { commands } => {
+                write!(f, "{} removals in a short time", commands)
+            }
+            Suspicion::RailRemoval { commands } => {
+                write!(f, "removed rail {} times in a small area", commands)
+            }
+            Suspicion::NewPlayer { command } => {
+                write!(f, "{} right after joining a company", command)
+            }
+        }
+    }
+}
+
+/// A client suspected of griefing.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Alert {
+    /// The game date, if known.
+    pub date: Option<Date>,
+    pub client_id: u32,
+    pub name: String,
+    pub company_id: u8,
+    /// The tile of the command that raised the alert.
+    pub tile: Option<TileIndex>,
+    pub suspicion: Suspicion,
+}
+
+impl Alert {
+    /// The console command that kicks the client.
+    pub fn rcon_command(&self) -> Command {
+        let reason = self.suspicion.to_string();
+        Command::kick(Target::Client(self.client_id), Some(&reason))
+    }
+}
+
+impl Display for Alert {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if let Some(date) = self.date {
+            write!(f, "{} ", date)?;
+        }
+        write!(
+            f,
+            "client #{} {:?} (company {})",
+            self.client_id, self.name, self.company_id
+        )?;
+        if let Some(tile) = self.tile {
+            write!(f, " at {}", tile)?;
+        }
+        write!(f, ": {}", self.suspicion)
+    }
+}
+
+/// The recent commands of a client.
+#[derive(Clone, Debug, Default)]
+struct Activity {
+    demolished: VecDeque<Instant>,
+    removals: VecDeque<Instant>,
+    rail_removals: VecDeque<(Instant, Option<TileIndex>)>,
+}
+
+impl Activity {
+    fn expire(&mut self, since: Instant) {
+        while self.demolished.front().is_some_and(|&time| time < since) {
+            self.demolished.pop_front();
+        }
+        while self.removals.front().is_some_and(|&time| time < since) {
+            self.removals.pop_front();
+        }
+        while self
+            .rail_removals
+            .front()
+            .is_some_and(|&(time, _)| time < since)
+        {
+            self.rail_removals.pop_front();
+        }
+    }
+}
+
+/// Watches logged commands for griefing.
+#[derive(Clone, Debug)]
+pub struct Detector {
+    window: Duration,
+    demolish_threshold: Option<usize>,
+    removal_threshold: Option<usize>,
+    rail_removal: Option<(usize, u32)>,
+    new_player_grace: Option<Duration>,
+    kick: bool,
+    registry: CommandRegistry,
+    clients: Clients,
+    date: Option<Date>,
+    owners: HashMap<u32, u8>,
+    activity: HashMap<u32, Activity>,
+    joined_company: HashMap<u32, Instant>,
+    alerts: Vec<Alert>,
+}
+
+impl Default for Detector {
+    fn default() -> Detector {
+        Detector::new()
+    }
+}
+
+impl Detector {
+    /// A detector without any rules, counting commands over the last minute.
+    pub fn new() -> Detector {
+        Detector {
+            window: Duration::from_secs(60),
+            demolish_threshold: None,
+            removal_threshold: None,
+            rail_removal: None,
+            new_player_grace: None,
+            kick: false,
+            registry: CommandRegistry::new(),
+            clients: Clients::new(),
+            date: None,
+            owners: HashMap::new(),
+            activity: HashMap::new(),
+            joined_company: HashMap::new(),
+            alerts: Vec::new(),
+        }
+    }
+
+    /// Count the commands of a client over this interval.
+    pub fn window(mut self, window: Duration) -> Detector {
+        self.window = window;
+        self
+    }
+
+    /// Alert when a client demolishes this many tiles built by other
+    /// companies.
+    pub fn demolished_tiles(mut self, tiles: usize) -> Detector {
+        self.demolish_threshold = Some(tiles);
+        self
+    }
+
+    /// Alert when a client removes or sells something this many times.
+    pub fn removals(mut self, commands: usize) -> Detector {
+        self.removal_threshold = Some(commands);
+        self
+    }
+
+    /// Alert when a client removes rail this many times within `distance`
+    /// tiles of the last removal.
+    pub fn rail_removals(mut self, commands: usize, distance: u32) -> Detector {
+        self.rail_removal = Some((commands, distance));
+        self
+    }
+
+    /// Alert when a client removes or demolishes anything within `grace` of
+    /// switching from spectating to a company.
+    pub fn new_players(mut self, grace: Duration) -> Detector {
+        self.new_player_grace = Some(grace);
+        self
+    }
+
+    /// Kick clients when raising an alert.
+    pub fn kick(mut self) -> Detector {
+        self.kick = true;
+        self
+    }
+
+    /// Every alert raised so far.
+    pub fn alerts(&self) -> &[Alert] {
+        &self.alerts
+    }
+
+    /// The commands known to the detector.
+    pub fn registry(&self) -> &CommandRegistry {
+        &self.registry
+    }
+
+    /// Check a packet and kick the suspected clients, if enabled.
+    pub fn handle_packet(&mut self, ctx: &mut Context, packet: &Packet) -> Result<()> {
+        for alert in self.update_at(packet, Instant::now()) {
+            if self.kick {
+                ctx.send(&alert.rcon_command().packet())?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Check a packet that arrived at `now` and return the alerts.
+    pub fn update_at(&mut self, packet: &Packet, now: Instant) -> Vec<Alert> {
+        let mut alerts = Vec::new();
+        self.registry.update(packet);
+        match packet {
+            Packet::Date(date) => self.date = Some(date.date),
+            Packet::ClientUpdate(update) => {
+                let was_spectating =
+                    self.clients.get(update.id).map(|client| client.company_id) == Some(SPECTATOR);
+                if was_spectating && update.company_id != SPECTATOR {
+                    self.joined_company.insert(update.id, now);
+                }
+                self.clients.update(packet);
+            }
+            Packet::ClientQuit(quit) => {
+                self.forget(quit.id);
+                self.clients.update(packet);
+            }
+            Packet::ClientError(error) => {
+                self.forget(error.id);
+                self.clients.update(packet);
+            }
+            Packet::Newgame => {
+                self.owners.clear();
+                self.activity.clear();
+                self.joined_company.clear();
+                self.clients.update(packet);
+            }
+            Packet::CompanyRemove(remove) => {
+                self.owners.retain(|_, owner| *owner != remove.id);
+                self.clients.update(packet);
+            }
+            Packet::CmdLogging(logged) if logged.client_id > SERVER_CLIENT_ID => {
+                let command = self.registry.decode(logged);
+                self.check(&command, now, &mut alerts);
+            }
+            _ => self.clients.update(packet),
+        }
+        for alert in &alerts {
+            log::warn!("{}", alert);
+        }
+        self.alerts.extend(alerts.iter().cloned());
+        alerts
+    }
+
+    fn check(&mut self, command: &DecodedCommand, now: Instant, alerts: &mut Vec<Alert>) {
+        let name = match &command.name {
+            Some(name) => name.as_str(),
+            None => return,
+        };
+        let client_id = command.logged.client_id;
+        let company_id = command.logged.company_id;
+        if name.starts_with("CmdBuild") {
+            // Dragged builds are taken to cover their whole rectangle. For
+            // straight tracks, roads and bridges that is the dragged line;
+            // diagonal tracks only cover part of it.
+            if let Some((start, end)) = area(command) {
+                let (x1, y1) = start.to_xy();
+                let (x2, y2) = end.to_xy();
+                let width = start.map_size().width;
+                for y in y1.min(y2)..=y1.max(y2) {
+                    for x in x1.min(x2)..=x1.max(x2) {
+                        self.owners.insert(y * width + x, company_id);
+                    }
+                }
+            }
+            return;
+        }
+        if !is_removal(name) {
+            return;
+        }
+
+        let demolished = if DEMOLISH_COMMANDS.contains(&name) {
+            self.demolish(command)
+        } else {
+            0
+        };
+        let since = now.checked_sub(self.window).unwrap_or(now);
+        let activity = self.activity.entry(client_id).or_default();
+        activity.expire(since);
+        // `std::iter::repeat_n` needs Rust 1.82.
+        #[allow(clippy::manual_repeat_n)]
+        activity
+            .demolished
+            .extend(std::iter::repeat(now).take(demolished));
+        activity.removals.push_back(now);
+        if RAIL_REMOVAL_COMMANDS.contains(&name) {
+            activity.rail_removals.push_back((now, command.tile));
+        }
+
+        let mut suspicions = Vec::new();
+        if let Some(threshold) = self.demolish_threshold {
+            if demolished > 0 && activity.demolished.len() >= threshold {
+                suspicions.push(Suspicion::DemolishedOthers {
+                    tiles: activity.demolished.len(),
+                });
+                activity.demolished.clear();
+            }
+        }
+        if let Some(threshold) = self.removal_threshold {
+            if activity.removals.len() >= threshold {
+                suspicions.push(Suspicion::MassRemoval {
+                    commands: activity.removals.len(),
+                });
+                activity.removals.clear();
+            }
+        }
+        if let (Some((threshold, distance)), Some(tile)) = (self.rail_removal, command.tile) {
+            let nearby = activity
+                .rail_removals
+                .iter()
+                .filter(|(_, other)| {
+                    other.is_some_and(|other| tile.manhattan_distance(other) <= distance)
+                })
+                .count();
+            if nearby >= threshold {
+                suspicions.push(Suspicion::RailRemoval { commands: nearby });
+                activity.rail_removals.clear();
+            }
+        }
+        if let (Some(grace), Some(&joined)) =
+            (self.new_player_grace, self.joined_company.get(&client_id))
+        {
+            if now.duration_since(joined) <= grace {
+                suspicions.push(Suspicion::NewPlayer {
+                    command: name.to_string(),
+                });
+            }
+            // Only alert once for every switch to a company.
+            self.joined_company.remove(&client_id);
+        }
+
+        let client_name = self.clients.name(client_id).unwrap_or_default();
+        alerts.extend(suspicions.into_iter().map(|suspicion| Alert {
+            date: self.date,
+            client_id,
+            name: client_name.to_string(),
+            company_id,
+            tile: command.tile,
+            suspicion,
+        }));
+    }
+
+    /// Forget the owners of demolished tiles, and return the number of
+    /// them that belonged to other companies.
+    fn demolish(&mut self, command: &DecodedCommand) -> usize {
+        let (start, end) = match area(command) {
+            Some(area) => area,
+            None => return 0,
+        };
+        let (x1, y1) = start.to_xy();
+        let (x2, y2) = end.to_xy();
+        let width = start.map_size().width;
+        let demolished: Vec<(u32, u8)> = self
+            .owners
+            .iter()
+            .filter(|(&index, _)| {
+                let (x, y) = (index % width, index / width);
+                x >= x1.min(x2) && x <= x1.max(x2) && y >= y1.min(y2) && y <= y1.max(y2)
+            })
+            .map(|(&index, &owner)| (index, owner))
+            .collect();
+        let company_id = command.logged.company_id;
+        let mut others = 0;
+        for (index, owner) in demolished {
+            self.owners.remove(&index);
+            if owner != company_id {
+                others += 1;
+            }
+        }
+        others
+    }
+
+    fn forget(&mut self, client_id: u32) {
+        self.activity.remove(&client_id);
+        self.joined_company.remove(&client_id);
+    }
+}
+
+/// The corners of the rectangle a command affects, which is a single tile
+/// for commands that are not dragged.
+fn area(command: &DecodedCommand) -> Option<(TileIndex, TileIndex)> {
+    let start = command.tile?;
+    Some((start, command.area_end.unwrap_or(start)))
+}
+
+impl AdminHandler for Detector {
+    fn on_packet(&mut self, ctx: &mut Context, packet: &Packet) -> Result<()> {
+        self.handle_packet(ctx, packet)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::packet::admin::server_packets::{self, CmdLogging, CmdName, CmdNames};
+    use crate::types::MapSize;
+
+    const MAP: MapSize = MapSize {
+        width: 64,
+        height: 64,
+    };
+
+    fn detector(detector: Detector) -> Detector {
+        let mut detector = detector;
+        let now = Instant::now();
+        // Names as sent by the server.
+        let names = [
+            (0, "CmdBuildRailroadTrack"),
+            (1, "CmdRemoveSingleRail"),
+            (2, "CmdClearArea"),
+            (3, "CmdLandscapeClear"),
+            (4, "CmdSellVehicle"),
+        ];
+        detector.update_at(
+            &Packet::CmdNames(CmdNames {
+                names: names
+                    .iter()
+                    .map(|&(id, name)| CmdName {
+                        id,
+                        name: name.to_string(),
+                    })
+                    .collect(),
+            }),
+            now,
+        );
+        detector.registry.set_map_size(MAP);
+        for (id, name, company_id) in &[(3, "Griefer", SPECTATOR), (4, "Builder", 1)] {
+            detector.update_at(
+                &Packet::ClientInfo(server_packets::ClientInfo {
+                    id: *id,
+                    address: format!("192.0.2.{}", id),
+                    name: name.to_string(),
+                    language: 0,
+                    date_joined: Date::from_ymd(1950, 0, 1).unwrap().into(),
+                    company_id: *company_id,
+                }),
+                now,
+            );
+        }
+        detector
+    }
+
+    fn tile(x: u32, y: u32) -> u32 {
+        TileIndex::from_xy(x, y, MAP).unwrap().index()
+    }
+
+    fn command(client_id: u32, company_id: u8, command_id: u16, xy: (u32, u32), p1: u32) -> Packet {
+        Packet::CmdLogging(CmdLogging {
+            client_id,
+            company_id,
+            command_id,
+            p1,
+            p2: 0,
+            tile: TileIndex::from_xy(xy.0, xy.1, MAP).unwrap().index(),
+            text: String::new(),
+            execution_frame: 0,
+        })
+    }
+
+    fn suspicions(alerts: Vec<Alert>) -> Vec<Suspicion> {
+        alerts.into_iter().map(|alert| alert.suspicion).collect()
+    }
+
+    #[test]
+    fn demolish_others_after_joining() {
+        let now = Instant::now();
+        let mut detector = detector(
+            Detector::new()
+                .demolished_tiles(3)
+                .new_players(Duration::from_secs(30))
+                .kick(),
+        );
+        for x in 0..4 {
+            detector.update_at(&command(4, 1, 0, (x, 5), tile(x, 5)), now);
+        }
+        detector.update_at(
+            &Packet::ClientUpdate(server_packets::ClientUpdate {
+                id: 3,
+                name: "Griefer".to_string(),
+                company_id: 0,
+            }),
+            now,
+        );
+
+        // Clearing a single tile of another company right after joining.
+        let alerts = detector.update_at(&command(3, 0, 3, (0, 5), 0), now);
+        assert_eq!(
+            alerts[0].to_string(),
+            "client #3 \"Griefer\" (company 0) at (0, 5): \
+             CmdLandscapeClear right after joining a company"
+        );
+        assert_eq!(
+            alerts[0].rcon_command().to_string(),
+            "kick 3 \"CmdLandscapeClear right after joining a company\""
+        );
+
+        // Clearing an area with the remaining tiles.
+        let end = tile(3, 6);
+        let alerts = detector.update_at(&command(3, 0, 2, (1, 4), end), now);
+        assert_eq!(
+            suspicions(alerts),
+            vec![Suspicion::DemolishedOthers { tiles: 4 }]
+        );
+        assert_eq!(detector.alerts().len(), 2);
+    }
+
+    #[test]
+    fn dragged_builds() {
+        let now = Instant::now();
+        let mut detector = detector(Detector::new().demolished_tiles(3));
+        let end = tile(5, 8);
+        detector.update_at(&command(4, 1, 0, (5, 5), end), now);
+        // Clearing the tiles between the ends of the track.
+        let end = tile(5, 7);
+        assert_eq!(
+            suspicions(detector.update_at(&command(3, 0, 2, (5, 4), end), now)),
+            vec![Suspicion::DemolishedOthers { tiles: 3 }]
+        );
+    }
+
+    #[test]
+    fn forget_owners() {
+        let now = Instant::now();
+        let mut detector = detector(Detector::new().demolished_tiles(1));
+        detector.update_at(&command(4, 1, 0, (0, 5), tile(0, 5)), now);
+        detector.update_at(&command(4, 2, 0, (1, 5), tile(1, 5)), now);
+        detector.update_at(
+            &Packet::CompanyRemove(server_packets::CompanyRemove { id: 1, reason: 0 }),
+            now,
+        );
+        assert!(detector
+            .update_at(&command(3, 0, 3, (0, 5), 0), now)
+            .is_empty());
+
+        detector.update_at(&Packet::Newgame, now);
+        assert!(detector
+            .update_at(&command(3, 0, 3, (1, 5), 0), now)
+            .is_empty());
+        assert!(detector.alerts().is_empty());
+    }
+
+    #[test]
+    fn mass_and_rail_removal() {
+        let now = Instant::now();
+        let mut detector = detector(
+            Detector::new()
+                .removals(5)
+                .rail_removals(3, 4)
+                .window(Duration::from_secs(10)),
+        );
+        // Removals spread over time and far apart are fine.
+        for second in 0..6 {
+            let now = now + Duration::from_secs(second * 5);
+            let alerts = detector.update_at(&command(4, 1, 1, (second as u32 * 10, 0), 0), now);
+            assert!(alerts.is_empty());
+        }
+
+        let now = now + Duration::from_secs(60);
+        assert!(detector
+            .update_at(&command(4, 1, 1, (20, 20), 0), now)
+            .is_empty());
+        assert!(detector
+            .update_at(&command(4, 1, 4, (0, 0), 0), now)
+            .is_empty());
+        assert!(detector
+            .update_at(&command(4, 1, 1, (21, 22), 0), now)
+            .is_empty());
+        assert_eq!(
+            suspicions(detector.update_at(&command(4, 1, 1, (20, 23), 0), now)),
+            vec![Suspicion::RailRemoval { commands: 3 }]
+        );
+        assert_eq!(
+            suspicions(detector.update_at(&command(4, 1, 4, (0, 0), 0), now)),
+            vec![Suspicion::MassRemoval { commands: 5 }]
+        );
+    }
+}
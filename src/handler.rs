@@ -0,0 +1,334 @@
+//! Event handling for bots. Implement [`AdminHandler`] for the packets you
+//! are interested in and pass it to [`run`], which reads packets from a
+//! [`Connection`] and calls the matching method. Every method receives a
+//! [`Context`] that can send packets back to the server.
+
+use crate::connection::Connection;
+use crate::packet::admin::server_packets::{self, Packet};
+use crate::packet::admin::{client_packets, AdminWrite, Result};
+use crate::types;
+use std::io::{Read, Write};
+
+/// Sends packets back to the server from within a handler.
+pub struct Context<'a> {
+    writer: &'a mut dyn Write,
+    stopped: bool,
+}
+
+impl<'a> Context<'a> {
+    /// A context writing packets to `writer`.
+    pub fn new(writer: &'a mut dyn Write) -> Context<'a> {
+        Context {
+            writer,
+            stopped: false,
+        }
+    }
+
+    /// Send a packet to the server.
+    pub fn send<T: client_packets::Packet>(&mut self, packet: &T) -> Result<()> {
+        (&mut *self.writer).write_packet(packet)
+    }
+
+    /// Broadcast a chat message.
+    pub fn say(&mut self, message: &str) -> Result<()> {
+        self.send(&client_packets::Chat::broadcast(message))
+    }
+
+    /// Send a private chat message to a client.
+    pub fn private_message(&mut self, client_id: u32, message: &str) -> Result<()> {
+        self.send(&client_packets::Chat::to_client(client_id, message))
+    }
+
+    /// Execute a command on the server console.
+    pub fn rcon(&mut self, command: &str) -> Result<()> {
+        self.send(&client_packets::Rcon { command })
+    }
+
+    /// Stop handling packets after the current one.
+    pub fn stop(&mut self) {
+        self.stopped = true;
+    }
+
+    /// Returns true if [`Context::stop`] was called.
+    pub fn is_stopped(&self) -> bool {
+        self.stopped
+    }
+}
+
+/// Handles packets received from the server. All methods do nothing by
+/// default.
+#[allow(unused_variables)]
+pub trait AdminHandler {
+    /// Called for every packet, before the method for the specific packet.
+    fn on_packet(&mut self, ctx: &mut Context, packet: &Packet) -> Result<()> {
+        Ok(())
+    }
+
+    /// Called once when the connection is set up.
+    fn on_welcome(&mut self, ctx: &mut Context, welcome: &server_packets::Welcome) -> Result<()> {
+        Ok(())
+    }
+
+    /// The connection was closed because of an error.
+    fn on_error(&mut self, ctx: &mut Context, error_code: u8) -> Result<()> {
+        Ok(())
+    }
+
+    fn on_newgame(&mut self, ctx: &mut Context) -> Result<()> {
+        Ok(())
+    }
+
+    fn on_shutdown(&mut self, ctx: &mut Context) -> Result<()> {
+        Ok(())
+    }
+
+    fn on_date(&mut self, ctx: &mut Context, date: types::Date) -> Result<()> {
+        Ok(())
+    }
+
+    fn on_client_join(&mut self, ctx: &mut Context, client_id: u32) -> Result<()> {
+        Ok(())
+    }
+
+    fn on_client_info(
+        &mut self,
+        ctx: &mut Context,
+        info: &server_packets::ClientInfo,
+    ) -> Result<()> {
+        Ok(())
+    }
+
+    fn on_client_update(
+        &mut self,
+        ctx: &mut Context,
+        update: &server_packets::ClientUpdate,
+    ) -> Result<()> {
+        Ok(())
+    }
+
+    fn on_client_quit(&mut self, ctx: &mut Context, client_id: u32) -> Result<()> {
+        Ok(())
+    }
+
+    fn on_client_error(
+        &mut self,
+        ctx: &mut Context,
+        error: &server_packets::ClientError,
+    ) -> Result<()> {
+        Ok(())
+    }
+
+    fn on_company_new(&mut self, ctx: &mut Context, company_id: u32) -> Result<()> {
+        Ok(())
+    }
+
+    fn on_company_info(
+        &mut self,
+        ctx: &mut Context,
+        info: &server_packets::CompanyInfo,
+    ) -> Result<()> {
+        Ok(())
+    }
+
+    fn on_company_update(
+        &mut self,
+        ctx: &mut Context,
+        update: &server_packets::CompanyUpdate,
+    ) -> Result<()> {
+        Ok(())
+    }
+
+    fn on_company_remove(
+        &mut self,
+        ctx: &mut Context,
+        remove: &server_packets::CompanyRemove,
+    ) -> Result<()> {
+        Ok(())
+    }
+
+    fn on_company_economy(
+        &mut self,
+        ctx: &mut Context,
+        economy: &server_packets::CompanyEconomy,
+    ) -> Result<()> {
+        Ok(())
+    }
+
+    fn on_company_stats(
+        &mut self,
+        ctx: &mut Context,
+        stats: &server_packets::CompanyStats,
+    ) -> Result<()> {
+        Ok(())
+    }
+
+    fn on_chat(&mut self, ctx: &mut Context, chat: &server_packets::Chat) -> Result<()> {
+        Ok(())
+    }
+
+    fn on_rcon(&mut self, ctx: &mut Context, rcon: &server_packets::Rcon) -> Result<()> {
+        Ok(())
+    }
+
+    fn on_rcon_end(&mut self, ctx: &mut Context, rcon_end: &server_packets::RconEnd) -> Result<()> {
+        Ok(())
+    }
+
+    fn on_console(&mut self, ctx: &mut Context, console: &server_packets::Console) -> Result<()> {
+        Ok(())
+    }
+
+    fn on_cmd_names(
+        &mut self,
+        ctx: &mut Context,
+        cmd_names: &server_packets::CmdNames,
+    ) -> Result<()> {
+        Ok(())
+    }
+
+    fn on_cmd_logging(
+        &mut self,
+        ctx: &mut Context,
+        cmd_logging: &server_packets::CmdLogging,
+    ) -> Result<()> {
+        Ok(())
+    }
+
+    fn on_gamescript(
+        &mut self,
+        ctx: &mut Context,
+        gamescript: &server_packets::Gamescript,
+    ) -> Result<()> {
+        Ok(())
+    }
+
+    fn on_pong(&mut self, ctx: &mut Context, id: u32) -> Result<()> {
+        Ok(())
+    }
+
+    /// A packet of a type this crate does not know.
+    fn on_unknown(&mut self, ctx: &mut Context, packet_type: u8, buffer: &[u8]) -> Result<()> {
+        Ok(())
+    }
+}
+
+/// Call the handler methods for a packet.
+pub fn dispatch<H: AdminHandler + ?Sized>(
+    handler: &mut H,
+    ctx: &mut Context,
+    packet: &Packet,
+) -> Result<()> {
+    handler.on_packet(ctx, packet)?;
+    match packet {
+        Packet::Full | Packet::Banned | Packet::Protocol(_) => Ok(()),
+        Packet::Error(error) => handler.on_error(ctx, error.error_code),
+        Packet::Welcome(welcome) => handler.on_welcome(ctx, welcome),
+        Packet::Newgame => handler.on_newgame(ctx),
+        Packet::Shutdown => handler.on_shutdown(ctx),
+        Packet::Date(date) => handler.on_date(ctx, date.date),
+        Packet::ClientJoin(join) => handler.on_client_join(ctx, join.id),
+        Packet::ClientInfo(info) => handler.on_client_info(ctx, info),
+        Packet::ClientUpdate(update) => handler.on_client_update(ctx, update),
+        Packet::ClientQuit(quit) => handler.on_client_quit(ctx, quit.id),
+        Packet::ClientError(error) => handler.on_client_error(ctx, error),
+        Packet::CompanyNew(company) => handler.on_company_new(ctx, company.id),
+        Packet::CompanyInfo(info) => handler.on_company_info(ctx, info),
+        Packet::CompanyUpdate(update) => handler.on_company_update(ctx, update),
+        Packet::CompanyRemove(remove) => handler.on_company_remove(ctx, remove),
+        Packet::CompanyEconomy(economy) => handler.on_company_economy(ctx, economy),
+        Packet::CompanyStats(stats) => handler.on_company_stats(ctx, stats),
+        Packet::Chat(chat) => handler.on_chat(ctx, chat),
+        Packet::Rcon(rcon) => handler.on_rcon(ctx, rcon),
+        Packet::Console(console) => handler.on_console(ctx, console),
+        Packet::CmdNames(cmd_names) => handler.on_cmd_names(ctx, cmd_names),
+        Packet::CmdLogging(cmd_logging) => handler.on_cmd_logging(ctx, cmd_logging),
+        Packet::Gamescript(gamescript) => handler.on_gamescript(ctx, gamescript),
+        Packet::RconEnd(rcon_end) => handler.on_rcon_end(ctx, rcon_end),
+        Packet::Pong(pong) => handler.on_pong(ctx, pong.id),
+        Packet::UnknownPacket {
+            packet_type,
+            buffer,
+        } => handler.on_unknown(ctx, *packet_type, buffer),
+    }
+}
+
+/// Handle packets from a connection until the server shuts down, closes the
+/// connection because of an error, or the handler calls [`Context::stop`].
+pub fn run<S: Read + Write, H: AdminHandler + ?Sized>(
+    connection: &mut Connection<S>,
+    handler: &mut H,
+) -> Result<()> {
+    let welcome = connection.welcome().clone();
+    {
+        let mut ctx = Context::new(connection.stream_mut());
+        handler.on_welcome(&mut ctx, &welcome)?;
+        if ctx.is_stopped() {
+            return Ok(());
+        }
+    }
+    loop {
+        let packet = connection.read_packet()?;
+        let mut ctx = Context::new(connection.stream_mut());
+        dispatch(handler, &mut ctx, &packet)?;
+        let closed = matches!(packet, Packet::Shutdown | Packet::Error(_));
+        if closed || ctx.is_stopped() {
+            return Ok(());
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::packet::admin::client_packets::Join;
+    use crate::test_util::MockStream;
+
+    /// Answers every chat message with a private message.
+    struct EchoBot {
+        welcomed: bool,
+    }
+
+    impl AdminHandler for EchoBot {
+        fn on_welcome(&mut self, _: &mut Context, _: &server_packets::Welcome) -> Result<()> {
+            self.welcomed = true;
+            Ok(())
+        }
+
+        fn on_chat(&mut self, ctx: &mut Context, chat: &server_packets::Chat) -> Result<()> {
+            ctx.private_message(chat.client, &chat.message)
+        }
+    }
+
+    #[test]
+    fn run_handler() {
+        #[rustfmt::skip]
+        let input: &'static [u8] = &[
+            // Protocol, version 1 without update packets
+            5, 0, 103, 1, 0,
+            // Welcome to server "S" with a 64x64 map
+            23, 0, 104, b'S', 0, b'V', 0, 1, b'M', 0, 1, 0, 0, 0, 0, 0, 0, 0, 0, 64, 0, 64, 0,
+            // Chat "hi" from client 2
+            12, 0, 119, 3, 0, 2, 0, 0, 0, b'h', b'i', 0,
+            // Shutdown
+            3, 0, 106,
+        ];
+        let stream = MockStream::new(input);
+        let join = Join {
+            password: "",
+            name: "bot",
+            version: "1",
+        };
+        let mut connection = Connection::join(stream, &join).unwrap();
+        assert_eq!(connection.welcome().server_name, "S");
+        let mut bot = EchoBot { welcomed: false };
+        run(&mut connection, &mut bot).unwrap();
+        assert!(bot.welcomed);
+
+        let mut expected = Vec::new();
+        expected.write_packet(&join).unwrap();
+        expected
+            .write_packet(&client_packets::Chat::to_client(2, "hi"))
+            .unwrap();
+        assert_eq!(connection.into_inner().output, expected);
+    }
+}
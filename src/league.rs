@@ -0,0 +1,399 @@
+//! Timed competitions between companies. A [`League`] ranks the companies
+//! by their `CompanyEconomy` updates, broadcasts the standings on a
+//! [`Schedule`] and announces the winner once the end date is reached,
+//! optionally pausing the game. The final [`Results`] can be exported as
+//! JSON with the `json` feature.
+//!
+//! Register for `CompanyInfo`, `CompanyEconomy` and `Date` updates. Economy
+//! updates are sent monthly at most, so poll them shortly before the end for
+//! the most recent values.
+
+use crate::handler::{AdminHandler, Context};
+use crate::packet::admin::server_packets::{CompanyEconomy, Packet};
+use crate::packet::admin::Result;
+use crate::rcon::Command;
+use crate::scheduler::Schedule;
+use crate::types::Date;
+use serde_derive::Serialize;
+use std::collections::HashMap;
+use std::fmt::{self, Display};
+
+/// What companies are ranked by.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Criterion {
+    /// The company value of the last quarter.
+    CompanyValue,
+    /// The performance rating of the last quarter.
+    Performance,
+    /// The cargo delivered in the last quarter.
+    DeliveredCargo,
+}
+
+impl Criterion {
+    /// The score of a company.
+    pub fn score(self, economy: &CompanyEconomy) -> i64 {
+        match self {
+            Criterion::CompanyValue => economy.company_value_last as i64,
+            Criterion::Performance => i64::from(economy.performance_last),
+            Criterion::DeliveredCargo => i64::from(economy.delivered_cargo_last),
+        }
+    }
+}
+
+impl Display for Criterion {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Criterion::CompanyValue => "company value",
+            Criterion::Performance => "performance",
+            Criterion::DeliveredCargo => "delivered cargo",
+        })
+    }
+}
+
+/// The place of a company in the rankings.
+#[derive(Clone, Debug, Eq, PartialEq, Serialize)]
+pub struct Standing {
+    /// The rank, starting at 1. Companies with the same score share a rank.
+    pub rank: usize,
+    pub company_id: u8,
+    pub name: String,
+    pub score: i64,
+}
+
+impl Display for Standing {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}. {}: {}", self.rank, self.name, self.score)
+    }
+}
+
+/// The final standings of a competition.
+#[derive(Clone, Debug, Eq, PartialEq, Serialize)]
+pub struct Results {
+    pub criterion: Criterion,
+    /// The date the competition ended, written as `YYYY-MM-DD`.
+    #[serde(serialize_with = "serialize_ymd")]
+    pub date: Date,
+    pub standings: Vec<Standing>,
+}
+
+fn serialize_ymd<S>(date: &Date, serializer: S) -> std::result::Result<S::Ok, S::Error>
+where
+    S: serde::Serializer,
+{
+    serializer.collect_str(date)
+}
+
+impl Results {
+    /// The companies with the highest score.
+    pub fn winners(&self) -> impl Iterator<Item = &Standing> {
+        self.standings.iter().filter(|standing| standing.rank == 1)
+    }
+
+    /// The results as JSON.
+    #[cfg(feature = "json")]
+    pub fn to_json(&self) -> serde_json::Result<String> {
+        serde_json::to_string_pretty(self)
+    }
+}
+
+/// A competition between the companies of a server.
+#[derive(Clone, Debug)]
+pub struct League {
+    criterion: Criterion,
+    end: Date,
+    standings_schedule: Option<Schedule>,
+    top: usize,
+    pause_at_end: bool,
+    names: HashMap<u8, String>,
+    economy: HashMap<u8, CompanyEconomy>,
+    date: Option<Date>,
+    results: Option<Results>,
+}
+
+impl League {
+    /// A competition ranking companies by `criterion` that ends at `end`.
+    pub fn new(criterion: Criterion, end: Date) -> League {
+        League {
+            criterion,
+            end,
+            standings_schedule: None,
+            top: 5,
+            pause_at_end: false,
+            names: HashMap::new(),
+            economy: HashMap::new(),
+            date: None,
+            results: None,
+        }
+    }
+
+    /// Broadcast the standings on a schedule. [`Schedule::At`] broadcasts
+    /// them once.
+    pub fn announce_standings(mut self, schedule: Schedule) -> League {
+        self.standings_schedule = Some(schedule);
+        self
+    }
+
+    /// Broadcast only the first `top` companies, 5 by default.
+    pub fn top(mut self, top: usize) -> League {
+        self.top = top;
+        self
+    }
+
+    /// Pause the game when the competition ends.
+    pub fn pause_at_end(mut self) -> League {
+        self.pause_at_end = true;
+        self
+    }
+
+    pub fn end(&self) -> Date {
+        self.end
+    }
+
+    /// The final results, once the competition ended.
+    pub fn results(&self) -> Option<&Results> {
+        self.results.as_ref()
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.results.is_some()
+    }
+
+    /// The current rankings, from the highest score to the lowest.
+    pub fn rankings(&self) -> Vec<Standing> {
+        let mut scores: Vec<(u8, i64)> = self
+            .economy
+            .values()
+            .map(|economy| (economy.id, self.criterion.score(economy)))
+            .collect();
+        scores
+            .sort_by(|(a_id, a_score), (b_id, b_score)| b_score.cmp(a_score).then(a_id.cmp(b_id)));
+        let mut standings: Vec<Standing> = Vec::with_capacity(scores.len());
+        for (index, (company_id, score)) in scores.into_iter().enumerate() {
+            let rank = match standings.last() {
+                Some(previous) if previous.score == score => previous.rank,
+                _ => index + 1,
+            };
+            standings.push(Standing {
+                rank,
+                company_id,
+                name: self.name(company_id),
+                score,
+            });
+        }
+        standings
+    }
+
+    /// The name of a company, or its number if the name is unknown.
+    fn name(&self, company_id: u8) -> String {
+        self.names
+            .get(&company_id)
+            .cloned()
+            .unwrap_or_else(|| format!("Company #{}", u32::from(company_id) + 1))
+    }
+
+    /// Update the rankings with a packet received from the server. Returns
+    /// the messages to broadcast and whether the competition just ended.
+    pub fn update(&mut self, packet: &Packet) -> (Vec<String>, bool) {
+        if self.is_finished() {
+            return (Vec::new(), false);
+        }
+        match packet {
+            Packet::CompanyInfo(info) => {
+                self.names.insert(info.id, info.name.clone());
+            }
+            Packet::CompanyUpdate(update) => {
+                self.names.insert(update.id, update.name.clone());
+            }
+            Packet::CompanyEconomy(economy) => {
+                self.economy.insert(economy.id, *economy);
+            }
+            Packet::CompanyRemove(remove) => {
+                self.economy.remove(&remove.id);
+            }
+            Packet::Date(date) => return self.update_date(date.date),
+            _ => {}
+        }
+        (Vec::new(), false)
+    }
+
+    fn update_date(&mut self, date: Date) -> (Vec<String>, bool) {
+        let previous = self.date.replace(date);
+        let mut messages = Vec::new();
+        if self.reaches_end(previous, date) {
+            let standings = self.rankings();
+            let winners: Vec<&str> = standings
+                .iter()
+                .filter(|standing| standing.rank == 1)
+                .map(|standing| standing.name.as_str())
+                .collect();
+            messages.push(if winners.is_empty() {
+                "The competition has ended without a winner.".to_string()
+            } else {
+                format!(
+                    "The competition has ended! Winner by {}: {}",
+                    self.criterion,
+                    winners.join(", ")
+                )
+            });
+            messages.extend(standings.iter().take(self.top).map(Standing::to_string));
+            self.results = Some(Results {
+                criterion: self.criterion,
+                date,
+                standings,
+            });
+            return (messages, true);
+        }
+        if let Some(schedule) = self.standings_schedule {
+            if schedule.is_due(previous, date) {
+                if !schedule.is_recurring() {
+                    self.standings_schedule = None;
+                }
+                messages.push(format!(
+                    "Standings by {} on {}, ending {}:",
+                    self.criterion, date, self.end
+                ));
+                messages.extend(
+                    self.rankings()
+                        .iter()
+                        .take(self.top)
+                        .map(Standing::to_string),
+                );
+            }
+        }
+        (messages, false)
+    }
+
+    /// Returns true if the game reached the end date with this update. When
+    /// the first date is already past the end, for example because the bot
+    /// started late, the competition does not end, as the rankings would be
+    /// incomplete.
+    fn reaches_end(&self, previous: Option<Date>, date: Date) -> bool {
+        match previous {
+            Some(previous) if previous <= date => previous < self.end && self.end <= date,
+            // The first date, or another game was loaded.
+            _ => date == self.end,
+        }
+    }
+
+    /// Update the rankings with a packet, broadcast the standings when due
+    /// and pause the game at the end, if enabled.
+    pub fn handle_packet(&mut self, ctx: &mut Context, packet: &Packet) -> Result<()> {
+        let (messages, ended) = self.update(packet);
+        for message in &messages {
+            ctx.say(message)?;
+        }
+        if ended && self.pause_at_end {
+            ctx.send(&Command::pause().packet())?;
+        }
+        Ok(())
+    }
+}
+
+impl AdminHandler for League {
+    fn on_packet(&mut self, ctx: &mut Context, packet: &Packet) -> Result<()> {
+        self.handle_packet(ctx, packet)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::packet::admin::server_packets;
+    use crate::test_util::{date, economy};
+
+    #[test]
+    fn competition() {
+        let mut league = League::new(Criterion::CompanyValue, "1951-01-01".parse().unwrap())
+            .announce_standings(Schedule::Quarterly)
+            .top(2)
+            .pause_at_end();
+        league.update(&Packet::CompanyInfo(server_packets::CompanyInfo {
+            id: 0,
+            name: "Alice Transport".to_string(),
+            manager: "Alice".to_string(),
+            color: 0,
+            password_protected: false,
+            inaugurated_year: 1950,
+            ai: false,
+        }));
+        league.update(&economy(0, 5000));
+        league.update(&economy(1, 8000));
+        league.update(&economy(2, 5000));
+
+        assert_eq!(league.update(&date("1950-09-15")), (vec![], false));
+        let (messages, ended) = league.update(&date("1950-10-01"));
+        assert_eq!(
+            messages,
+            vec![
+                "Standings by company value on 1950-10-01, ending 1951-01-01:",
+                "1. Company #2: 8000",
+                "2. Alice Transport: 5000",
+            ]
+        );
+        assert!(!ended);
+
+        league.update(&economy(0, 9000));
+        let mut output = Vec::new();
+        let mut ctx = Context::new(&mut output);
+        league.handle_packet(&mut ctx, &date("1951-01-01")).unwrap();
+        let results = league.results().unwrap();
+        assert_eq!(
+            results
+                .winners()
+                .map(|standing| standing.name.as_str())
+                .collect::<Vec<_>>(),
+            vec!["Alice Transport"]
+        );
+        assert_eq!(results.standings[2].rank, 3);
+        assert!(output.ends_with(b"pause\0"));
+        assert!(league.update(&date("1951-02-01")).0.is_empty());
+    }
+
+    #[test]
+    fn standings_once() {
+        let mut league = League::new(Criterion::CompanyValue, "1952-01-01".parse().unwrap())
+            .announce_standings(Schedule::At("1951-01-01".parse().unwrap()));
+        league.update(&economy(0, 5000));
+        assert!(league.update(&date("1950-12-01")).0.is_empty());
+        assert_eq!(
+            league.update(&date("1951-02-01")).0,
+            vec![
+                "Standings by company value on 1951-02-01, ending 1952-01-01:",
+                "1. Company #1: 5000",
+            ]
+        );
+        assert!(league.update(&date("1951-03-01")).0.is_empty());
+    }
+
+    #[test]
+    fn start_after_end() {
+        let mut league = League::new(Criterion::CompanyValue, "1951-01-01".parse().unwrap());
+        league.update(&economy(0, 5000));
+        assert_eq!(league.update(&date("1951-03-01")), (vec![], false));
+        assert_eq!(league.update(&date("1951-04-01")), (vec![], false));
+        assert!(!league.is_finished());
+    }
+
+    #[cfg(feature = "json")]
+    #[test]
+    fn export_json() {
+        let mut league = League::new(Criterion::Performance, "1950-02-01".parse().unwrap());
+        league.update(&economy(3, 0));
+        league.update(&economy(1, 0));
+        league.update(&date("1950-02-01"));
+        let json: serde_json::Value =
+            serde_json::from_str(&league.results().unwrap().to_json().unwrap()).unwrap();
+        assert_eq!(
+            json,
+            serde_json::json!({
+                "criterion": "performance",
+                "date": "1950-02-01",
+                "standings": [
+                    {"rank": 1, "company_id": 1, "name": "Company #2", "score": 0},
+                    {"rank": 1, "company_id": 3, "name": "Company #4", "score": 0},
+                ]
+            })
+        );
+    }
+}
@@ -1,2 +1,22 @@
+pub mod chat_commands;
+pub mod clients;
+pub mod command_log;
+pub mod connection;
+pub mod console_log;
+pub mod export;
+pub mod game_clock;
+#[cfg(feature = "json")]
+pub mod gamescript;
+pub mod griefing;
+pub mod handler;
+pub mod league;
+pub mod metrics;
+pub mod moderation;
 pub mod packet;
+pub mod rcon;
+pub mod scheduler;
+#[cfg(feature = "sqlite")]
+pub mod sqlite;
+#[cfg(test)]
+mod test_util;
 pub mod types;
@@ -0,0 +1,347 @@
+//! Prometheus metrics fed by admin updates. [`Metrics`] keeps the latest
+//! company economy and statistics, the connected clients, the game date and
+//! the admin connection latency, and renders them in the Prometheus text
+//! format. [`serve`] answers `/metrics` requests over HTTP.
+//!
+//! To receive the updates, register for `CompanyEconomy`, `CompanyStats`,
+//! `ClientInfo` and `Date` updates, and pass every packet to
+//! [`Metrics::update`]. Latency is measured by announcing every ping with
+//! [`Metrics::ping_sent`].
+
+use crate::packet::admin::server_packets::{self, Packet};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fmt::Write as _;
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::TcpListener;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// Pings without a pong after this long are taken to be lost. This is the
+/// default scrape timeout of Prometheus.
+const PING_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// The latest known values of an OpenTTD server.
+#[derive(Clone, Debug, Default)]
+pub struct Metrics {
+    economy: BTreeMap<u8, server_packets::CompanyEconomy>,
+    stats: BTreeMap<u8, server_packets::CompanyStats>,
+    clients: HashSet<u32>,
+    year: Option<u32>,
+    pings: HashMap<u32, Instant>,
+    latency: Option<Duration>,
+}
+
+impl Metrics {
+    pub fn new() -> Metrics {
+        Metrics::default()
+    }
+
+    /// Update the metrics with a packet received from the server.
+    pub fn update(&mut self, packet: &Packet) {
+        self.update_at(packet, Instant::now());
+    }
+
+    /// Like [`Metrics::update`], using `now` as the time the packet arrived.
+    pub fn update_at(&mut self, packet: &Packet, now: Instant) {
+        match packet {
+            Packet::Date(date) => self.year = Some(date.date.to_ymd().0),
+            Packet::Newgame => {
+                self.economy.clear();
+                self.stats.clear();
+                self.clients.clear();
+                self.pings.clear();
+            }
+            Packet::ClientInfo(info) => {
+                self.clients.insert(info.id);
+            }
+            Packet::ClientJoin(join) => {
+                self.clients.insert(join.id);
+            }
+            Packet::ClientQuit(quit) => {
+                self.clients.remove(&quit.id);
+            }
+            Packet::ClientError(error) => {
+                self.clients.remove(&error.id);
+            }
+            Packet::CompanyEconomy(economy) => {
+                self.economy.insert(economy.id, *economy);
+            }
+            Packet::CompanyStats(stats) => {
+                self.stats.insert(stats.id, *stats);
+            }
+            Packet::CompanyRemove(remove) => {
+                self.economy.remove(&remove.id);
+                self.stats.remove(&remove.id);
+            }
+            Packet::Pong(pong) => {
+                if let Some(sent) = self.pings.remove(&pong.id) {
+                    self.latency = Some(now.duration_since(sent));
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Announce that a ping with the given id was sent at `now`.
+    pub fn ping_sent(&mut self, id: u32, now: Instant) {
+        self.pings
+            .retain(|_, sent| now.saturating_duration_since(*sent) < PING_TIMEOUT);
+        self.pings.insert(id, now);
+    }
+
+    /// Render the metrics in the Prometheus text format.
+    pub fn render(&self) -> String {
+        let mut output = String::new();
+        let mut gauge = |name: &str, help: &str, values: Vec<(String, String)>| {
+            if values.is_empty() {
+                return;
+            }
+            writeln!(output, "# HELP {} {}", name, help).unwrap();
+            writeln!(output, "# TYPE {} gauge", name).unwrap();
+            for (labels, value) in values {
+                writeln!(output, "{}{} {}", name, labels, value).unwrap();
+            }
+        };
+        let economy = |f: &dyn Fn(&server_packets::CompanyEconomy) -> String| {
+            self.economy
+                .values()
+                .map(|economy| (format!("{{company=\"{}\"}}", economy.id), f(economy)))
+                .collect::<Vec<_>>()
+        };
+        let by_type = |f: &dyn Fn(&server_packets::CompanyStats) -> [(&str, u16); 5]| {
+            self.stats
+                .values()
+                .flat_map(|stats| {
+                    f(stats)
+                        .iter()
+                        .map(move |(kind, count)| {
+                            (
+                                format!("{{company=\"{}\",type=\"{}\"}}", stats.id, kind),
+                                count.to_string(),
+                            )
+                        })
+                        .collect::<Vec<_>>()
+                })
+                .collect::<Vec<_>>()
+        };
+
+        gauge(
+            "openttd_company_money",
+            "Money of the company.",
+            economy(&|economy| (economy.money as i64).to_string()),
+        );
+        gauge(
+            "openttd_company_loan",
+            "Loan of the company.",
+            economy(&|economy| (economy.loan as i64).to_string()),
+        );
+        gauge(
+            "openttd_company_income",
+            "Income of the company.",
+            economy(&|economy| economy.income.to_string()),
+        );
+        gauge(
+            "openttd_company_value",
+            "Company value (last quarter).",
+            economy(&|economy| (economy.company_value_last as i64).to_string()),
+        );
+        gauge(
+            "openttd_company_performance",
+            "Performance rating (last quarter).",
+            economy(&|economy| economy.performance_last.to_string()),
+        );
+        gauge(
+            "openttd_company_delivered_cargo",
+            "Delivered cargo (this quarter).",
+            economy(&|economy| economy.delivered_cargo.to_string()),
+        );
+        gauge(
+            "openttd_company_vehicles",
+            "Number of vehicles of the company.",
+            by_type(&|stats| {
+                [
+                    ("train", stats.trains),
+                    ("lorry", stats.lorries),
+                    ("bus", stats.busses),
+                    ("plane", stats.planes),
+                    ("ship", stats.ships),
+                ]
+            }),
+        );
+        gauge(
+            "openttd_company_stations",
+            "Number of stations of the company.",
+            by_type(&|stats| {
+                [
+                    ("train", stats.train_stations),
+                    ("lorry", stats.lorry_stations),
+                    ("bus", stats.bus_stops),
+                    ("airport", stats.airports_and_heliports),
+                    ("harbour", stats.harbours),
+                ]
+            }),
+        );
+        gauge(
+            "openttd_clients",
+            "Number of connected clients.",
+            vec![(String::new(), self.clients.len().to_string())],
+        );
+        gauge(
+            "openttd_game_year",
+            "Current year in the game.",
+            self.year
+                .iter()
+                .map(|year| (String::new(), year.to_string()))
+                .collect(),
+        );
+        gauge(
+            "openttd_admin_latency_seconds",
+            "Round trip time of the last ping on the admin connection.",
+            self.latency
+                .iter()
+                .map(|latency| (String::new(), latency.as_secs_f64().to_string()))
+                .collect(),
+        );
+        output
+    }
+}
+
+/// How long to wait for the request of a scraper.
+const READ_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// How long to wait after accepting a connection failed, for example because
+/// too many files are open.
+const ACCEPT_RETRY_DELAY: Duration = Duration::from_millis(100);
+
+/// Serve the metrics at `/metrics` over HTTP. This handles one request at a
+/// time, giving up on clients that do not send a request within a few
+/// seconds. Errors accepting a connection are logged, so this does not
+/// return.
+pub fn serve(listener: TcpListener, metrics: Arc<Mutex<Metrics>>) -> io::Result<()> {
+    for stream in listener.incoming() {
+        let mut stream = match stream {
+            Ok(stream) => stream,
+            Err(err) => {
+                log::warn!("accepting a metrics connection failed: {}", err);
+                std::thread::sleep(ACCEPT_RETRY_DELAY);
+                continue;
+            }
+        };
+        let mut request_line = String::new();
+        if stream.set_read_timeout(Some(READ_TIMEOUT)).is_err()
+            || BufReader::new(&stream)
+                .read_line(&mut request_line)
+                .is_err()
+        {
+            continue;
+        }
+        let mut parts = request_line.split_whitespace();
+        let response = match (parts.next(), parts.next()) {
+            (Some("GET"), Some("/metrics")) => {
+                let body = metrics.lock().unwrap().render();
+                format!(
+                    "HTTP/1.1 200 OK\r\n\
+                     Content-Type: text/plain; version=0.0.4\r\n\
+                     Content-Length: {}\r\n\
+                     Connection: close\r\n\r\n{}",
+                    body.len(),
+                    body
+                )
+            }
+            _ => "HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n"
+                .to_string(),
+        };
+        // A client that went away should not stop the server.
+        let _ = stream.write_all(response.as_bytes());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn render_company_metrics() {
+        let mut metrics = Metrics::new();
+        metrics.update(&Packet::CompanyEconomy(server_packets::CompanyEconomy {
+            id: 0,
+            money: 99642,
+            loan: 100000,
+            income: -358,
+            delivered_cargo: 0,
+            company_value_last: 0,
+            performance_last: 0,
+            delivered_cargo_last: 0,
+            company_value_previous: 0,
+            performance_previous: 0,
+            delivered_previous: 0,
+        }));
+        metrics.update(&Packet::ClientJoin(server_packets::ClientJoin { id: 3 }));
+        let output = metrics.render();
+        assert!(output.contains("openttd_company_money{company=\"0\"} 99642\n"));
+        assert!(output.contains("openttd_company_income{company=\"0\"} -358\n"));
+        assert!(output.contains("openttd_clients 1\n"));
+        assert!(!output.contains("openttd_admin_latency_seconds"));
+    }
+
+    #[test]
+    fn negative_money() {
+        let mut metrics = Metrics::new();
+        metrics.update(&Packet::CompanyEconomy(server_packets::CompanyEconomy {
+            id: 1,
+            money: -25_000i64 as u64,
+            loan: 300_000,
+            income: -1_000,
+            delivered_cargo: 0,
+            company_value_last: -5_000i64 as u64,
+            performance_last: 0,
+            delivered_cargo_last: 0,
+            company_value_previous: 0,
+            performance_previous: 0,
+            delivered_previous: 0,
+        }));
+        let output = metrics.render();
+        assert!(output.contains("openttd_company_money{company=\"1\"} -25000\n"));
+        assert!(output.contains("openttd_company_value{company=\"1\"} -5000\n"));
+    }
+
+    #[test]
+    fn new_game() {
+        let mut metrics = Metrics::new();
+        let now = Instant::now();
+        metrics.update(&Packet::ClientJoin(server_packets::ClientJoin { id: 3 }));
+        metrics.ping_sent(1, now);
+        metrics.update(&Packet::Newgame);
+        assert!(metrics.render().contains("openttd_clients 0\n"));
+        // The answer to a ping sent before the new game is ignored.
+        metrics.update_at(&Packet::Pong(server_packets::Pong { id: 1 }), now);
+        assert!(!metrics.render().contains("openttd_admin_latency_seconds"));
+    }
+
+    #[test]
+    fn latency_from_pong() {
+        let mut metrics = Metrics::new();
+        let sent = Instant::now();
+        metrics.ping_sent(7, sent);
+        metrics.update_at(
+            &Packet::Pong(server_packets::Pong { id: 7 }),
+            sent + Duration::from_millis(250),
+        );
+        assert!(metrics
+            .render()
+            .contains("openttd_admin_latency_seconds 0.25\n"));
+    }
+
+    #[test]
+    fn forget_lost_pings() {
+        let mut metrics = Metrics::new();
+        let sent = Instant::now();
+        metrics.ping_sent(7, sent);
+        metrics.ping_sent(8, sent + Duration::from_secs(5));
+        assert_eq!(metrics.pings.len(), 2);
+        metrics.ping_sent(9, sent + PING_TIMEOUT);
+        assert_eq!(metrics.pings.len(), 2);
+        assert!(!metrics.pings.contains_key(&7));
+    }
+}
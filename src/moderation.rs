@@ -0,0 +1,589 @@
+//! Automatic moderation of clients. A [`Moderator`] watches client and chat
+//! updates and kicks or bans clients that break its rules:
+//!
+//! - clients with a name matching one of the given patterns are kicked;
+//! - clients flooding the chat are warned once, and kicked if they go on;
+//! - spectators are kicked after spectating for a number of game days, or
+//!   for some time in real time. In wallclock mode the calendar may be
+//!   frozen, so only the latter works there;
+//! - after a number of offences from the same address, the address is banned
+//!   instead. Kicks and client errors such as a wrong password count as
+//!   offences.
+//!
+//! Register for `ClientInfo`, `Chat` and `Date` updates so the moderator sees
+//! the events it needs. Every decision is kept in the audit log and logged
+//! using the `log` crate.
+
+use crate::clients::{Clients, SPECTATOR};
+use crate::handler::{AdminHandler, Context};
+use crate::packet::admin::policy::ChatLimit;
+use crate::packet::admin::server_packets::Packet;
+use crate::packet::admin::Result;
+use crate::rcon::{Command, Target};
+use crate::types::Date;
+use regex::Regex;
+use std::collections::{HashMap, HashSet};
+use std::fmt::{self, Display};
+use std::time::{Duration, Instant};
+
+/// The client id of the server itself, which is never moderated.
+const SERVER_CLIENT_ID: u32 = 1;
+
+/// Client errors (see NetworkErrorCode) that count as an offence.
+const OFFENDING_ERRORS: &[u8] = &[
+    4,  // NETWORK_ERROR_ILLEGAL_PACKET
+    6,  // NETWORK_ERROR_NOT_AUTHORIZED
+    10, // NETWORK_ERROR_WRONG_PASSWORD
+    13, // NETWORK_ERROR_CHEATER
+    15, // NETWORK_ERROR_TOO_MANY_COMMANDS
+];
+
+/// What the moderator does about a client.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Action {
+    /// Send the client a private warning.
+    Warn,
+    Kick,
+    /// Ban the address of the client.
+    Ban,
+}
+
+/// Why the moderator acted.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Reason {
+    /// The name of the client matches a forbidden pattern.
+    Name { pattern: String },
+    /// The client sends too many chat messages.
+    Flood,
+    /// The client has been spectating for too many game days.
+    IdleSpectator { days: u32 },
+    /// The client has been spectating for too long in real time.
+    IdleSpectatorTime { time: Duration },
+    /// The client was disconnected because of an error (see
+    /// NetworkErrorCode).
+    ClientError { error: u8 },
+}
+
+impl Display for Reason {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Reason::Name { pattern } => write!(f, "name matches {}", pattern),
+            Reason::Flood => f.write_str("flooding the chat"),
+            Reason::IdleSpectator { days } => write!(f, "spectating for {} days", days),
+            Reason::IdleSpectatorTime { time } => {
+                write!(f, "spectating for {} minutes", time.as_secs() / 60)
+            }
+            Reason::ClientError { error } => write!(f, "client error {}", error),
+        }
+    }
+}
+
+/// A decision of the moderator, as kept in the audit log.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Decision {
+    /// The game date, if known.
+    pub date: Option<Date>,
+    pub client_id: u32,
+    pub name: String,
+    pub address: String,
+    pub action: Action,
+    pub reason: Reason,
+}
+
+impl Decision {
+    /// The console command that carries out the decision, if any.
+    pub fn rcon_command(&self) -> Option<Command> {
+        let reason = self.reason.to_string();
+        let target = if self.address.is_empty() {
+            Target::Client(self.client_id)
+        } else {
+            Target::Address(&self.address)
+        };
+        match self.action {
+            Action::Warn => None,
+            Action::Kick => Some(Command::kick(Target::Client(self.client_id), Some(&reason))),
+            Action::Ban => Some(Command::ban(target, Some(&reason))),
+        }
+    }
+}
+
+impl Display for Decision {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if let Some(date) = self.date {
+            write!(f, "{} ", date)?;
+        }
+        let action = match self.action {
+            Action::Warn => "warn",
+            Action::Kick => "kick",
+            Action::Ban => "ban",
+        };
+        write!(
+            f,
+            "{} client #{} {:?} ({}): {}",
+            action, self.client_id, self.name, self.address, self.reason
+        )
+    }
+}
+
+/// Since when a client is spectating, in game and in real time.
+#[derive(Clone, Copy, Debug)]
+struct Spectating {
+    /// The game date, once known.
+    date: Option<Date>,
+    time: Instant,
+}
+
+/// Applies moderation rules to the clients of a server.
+#[derive(Clone, Debug, Default)]
+pub struct Moderator {
+    name_patterns: Vec<Regex>,
+    ban_after: Option<u32>,
+    chat_limit: Option<(usize, Duration)>,
+    idle_spectator_days: Option<u32>,
+    idle_spectator_time: Option<Duration>,
+    clients: Clients,
+    date: Option<Date>,
+    spectating_since: HashMap<u32, Spectating>,
+    chat: HashMap<u32, ChatLimit>,
+    warned: HashSet<u32>,
+    offences: HashMap<String, u32>,
+    audit_log: Vec<Decision>,
+}
+
+impl Moderator {
+    /// A moderator without any rules.
+    pub fn new() -> Moderator {
+        Moderator::default()
+    }
+
+    /// Kick clients with a name matching the pattern.
+    pub fn kick_names(mut self, pattern: Regex) -> Moderator {
+        self.name_patterns.push(pattern);
+        self
+    }
+
+    /// Ban the address of a client once it has offended this many times.
+    pub fn ban_after(mut self, offences: u32) -> Moderator {
+        self.ban_after = Some(offences);
+        self
+    }
+
+    /// Allow each client at most `max_messages` chat messages per
+    /// `interval`. The server cannot mute clients, so clients that exceed
+    /// the limit are warned once and kicked the next time.
+    pub fn limit_chat(mut self, max_messages: usize, interval: Duration) -> Moderator {
+        self.chat_limit = Some((max_messages, interval));
+        self
+    }
+
+    /// Kick clients that have been spectating for this many game days.
+    pub fn kick_idle_spectators(mut self, days: u32) -> Moderator {
+        self.idle_spectator_days = Some(days);
+        self
+    }
+
+    /// Kick clients that have been spectating for this long in real time.
+    /// Unlike game days, this also works when the calendar is frozen in
+    /// wallclock mode. It is checked whenever a packet arrives.
+    pub fn kick_idle_spectators_after(mut self, time: Duration) -> Moderator {
+        self.idle_spectator_time = Some(time);
+        self
+    }
+
+    /// Every decision made so far.
+    pub fn audit_log(&self) -> &[Decision] {
+        &self.audit_log
+    }
+
+    /// The number of offences from an address.
+    pub fn offences(&self, address: &str) -> u32 {
+        self.offences.get(address).copied().unwrap_or(0)
+    }
+
+    /// Apply the rules to a packet and carry out the decisions.
+    pub fn handle_packet(&mut self, ctx: &mut Context, packet: &Packet) -> Result<()> {
+        for decision in self.update_at(packet, Instant::now()) {
+            match decision.rcon_command() {
+                Some(command) => ctx.send(&command.packet())?,
+                None => ctx.private_message(
+                    decision.client_id,
+                    "Please slow down, or you will be kicked.",
+                )?,
+            }
+        }
+        Ok(())
+    }
+
+    /// Apply the rules to a packet that arrived at `now` and return the
+    /// decisions, without carrying them out.
+    pub fn update_at(&mut self, packet: &Packet, now: Instant) -> Vec<Decision> {
+        let mut decisions = Vec::new();
+        let previous_name = match packet {
+            Packet::ClientUpdate(update) => self.clients.name(update.id).map(str::to_string),
+            _ => None,
+        };
+        match packet {
+            Packet::Date(date) => {
+                self.date = Some(date.date);
+                // Clients that arrived before the first date start spectating
+                // now.
+                for spectating in self.spectating_since.values_mut() {
+                    spectating.date.get_or_insert(date.date);
+                }
+                self.check_idle_days(&mut decisions);
+            }
+            Packet::ClientInfo(info) => {
+                // The info is sent again whenever clients are polled; only
+                // check new clients.
+                let is_new = self.clients.get(info.id).is_none();
+                self.clients.update(packet);
+                self.update_spectating(info.id, info.company_id, now);
+                if is_new {
+                    self.check_name(info.id, &mut decisions);
+                }
+            }
+            Packet::ClientUpdate(update) => {
+                self.clients.update(packet);
+                self.update_spectating(update.id, update.company_id, now);
+                if previous_name.as_deref() != Some(update.name.as_str()) {
+                    self.check_name(update.id, &mut decisions);
+                }
+            }
+            Packet::Chat(chat) if chat.client != SERVER_CLIENT_ID => {
+                if let Some((max_messages, interval)) = self.chat_limit {
+                    let allowed = self
+                        .chat
+                        .entry(chat.client)
+                        .or_insert_with(|| ChatLimit::new(max_messages, interval))
+                        .try_send(now);
+                    if !allowed {
+                        if self.warned.insert(chat.client) {
+                            let decision = self.decision(chat.client, Action::Warn, Reason::Flood);
+                            decisions.push(decision);
+                        } else {
+                            self.punish(chat.client, Reason::Flood, &mut decisions);
+                        }
+                    }
+                }
+            }
+            Packet::ClientError(error) => {
+                if OFFENDING_ERRORS.contains(&error.error) {
+                    let address = self
+                        .clients
+                        .get(error.id)
+                        .map(|client| client.address.clone())
+                        .unwrap_or_default();
+                    if self.offend(&address) {
+                        let reason = Reason::ClientError { error: error.error };
+                        let decision = self.decision(error.id, Action::Ban, reason);
+                        decisions.push(decision);
+                    }
+                }
+                self.forget(error.id);
+                self.clients.update(packet);
+            }
+            Packet::ClientQuit(quit) => {
+                self.forget(quit.id);
+                self.clients.update(packet);
+            }
+            _ => {}
+        }
+        self.check_idle_time(now, &mut decisions);
+        for decision in &decisions {
+            log::info!("{}", decision);
+        }
+        self.audit_log.extend(decisions.iter().cloned());
+        decisions
+    }
+
+    /// Track since when a client is spectating. The date a client joined is
+    /// not used, as it is an economy date in wallclock mode.
+    fn update_spectating(&mut self, client_id: u32, company_id: u8, now: Instant) {
+        if company_id != SPECTATOR {
+            self.spectating_since.remove(&client_id);
+        } else {
+            let date = self.date;
+            self.spectating_since
+                .entry(client_id)
+                .or_insert(Spectating { date, time: now });
+        }
+    }
+
+    fn check_name(&mut self, client_id: u32, decisions: &mut Vec<Decision>) {
+        if client_id == SERVER_CLIENT_ID {
+            return;
+        }
+        let name = match self.clients.name(client_id) {
+            Some(name) => name,
+            None => return,
+        };
+        if let Some(pattern) = self
+            .name_patterns
+            .iter()
+            .find(|pattern| pattern.is_match(name))
+        {
+            let reason = Reason::Name {
+                pattern: pattern.as_str().to_string(),
+            };
+            self.punish(client_id, reason, decisions);
+        }
+    }
+
+    fn check_idle_days(&mut self, decisions: &mut Vec<Decision>) {
+        let (days, today) = match (self.idle_spectator_days, self.date) {
+            (Some(days), Some(today)) => (days, today),
+            _ => return,
+        };
+        let idle = self.idle_spectators(|spectating| {
+            spectating
+                .date
+                .is_some_and(|since| since.days_between(today) >= i64::from(days))
+        });
+        for client_id in idle {
+            self.punish(client_id, Reason::IdleSpectator { days }, decisions);
+        }
+    }
+
+    fn check_idle_time(&mut self, now: Instant, decisions: &mut Vec<Decision>) {
+        let time = match self.idle_spectator_time {
+            Some(time) => time,
+            None => return,
+        };
+        let idle = self
+            .idle_spectators(|spectating| now.saturating_duration_since(spectating.time) >= time);
+        for client_id in idle {
+            self.punish(client_id, Reason::IdleSpectatorTime { time }, decisions);
+        }
+    }
+
+    /// Stop tracking the spectators matching `is_idle` and return them.
+    fn idle_spectators(&mut self, is_idle: impl Fn(&Spectating) -> bool) -> Vec<u32> {
+        let mut idle: Vec<u32> = self
+            .spectating_since
+            .iter()
+            .filter(|(&client_id, spectating)| client_id != SERVER_CLIENT_ID && is_idle(spectating))
+            .map(|(&client_id, _)| client_id)
+            .collect();
+        idle.sort_unstable();
+        // Only act once; the client will quit soon.
+        for client_id in &idle {
+            self.spectating_since.remove(client_id);
+        }
+        idle
+    }
+
+    /// Kick a client, or ban it if it offended too often.
+    fn punish(&mut self, client_id: u32, reason: Reason, decisions: &mut Vec<Decision>) {
+        let address = self
+            .clients
+            .get(client_id)
+            .map(|client| client.address.clone())
+            .unwrap_or_default();
+        let action = if self.offend(&address) {
+            Action::Ban
+        } else {
+            Action::Kick
+        };
+        let decision = self.decision(client_id, action, reason);
+        decisions.push(decision);
+    }
+
+    /// Count an offence from an address. Returns true if it should be banned.
+    fn offend(&mut self, address: &str) -> bool {
+        if address.is_empty() {
+            return false;
+        }
+        let offences = self.offences.entry(address.to_string()).or_insert(0);
+        *offences += 1;
+        self.ban_after
+            .map(|ban_after| *offences >= ban_after)
+            .unwrap_or(false)
+    }
+
+    fn decision(&self, client_id: u32, action: Action, reason: Reason) -> Decision {
+        let (name, address) = match self.clients.get(client_id) {
+            Some(client) => (client.name.clone(), client.address.clone()),
+            None => (String::new(), String::new()),
+        };
+        Decision {
+            date: self.date,
+            client_id,
+            name,
+            address,
+            action,
+            reason,
+        }
+    }
+
+    fn forget(&mut self, client_id: u32) {
+        self.spectating_since.remove(&client_id);
+        self.chat.remove(&client_id);
+        self.warned.remove(&client_id);
+    }
+}
+
+impl AdminHandler for Moderator {
+    fn on_packet(&mut self, ctx: &mut Context, packet: &Packet) -> Result<()> {
+        self.handle_packet(ctx, packet)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::packet::admin::server_packets;
+
+    fn client_info(id: u32, name: &str, company_id: u8, date_joined: Date) -> Packet {
+        Packet::ClientInfo(server_packets::ClientInfo {
+            id,
+            address: format!("192.0.2.{}", id),
+            name: name.to_string(),
+            language: 0,
+            date_joined: date_joined.into(),
+            company_id,
+        })
+    }
+
+    fn chat(client: u32) -> Packet {
+        Packet::Chat(server_packets::Chat {
+            action: 3,
+            destination: 0,
+            client,
+            message: "spam".to_string(),
+            money: None,
+        })
+    }
+
+    fn actions(decisions: Vec<Decision>) -> Vec<(Action, Option<String>)> {
+        decisions
+            .into_iter()
+            .map(|decision| {
+                let command = decision.rcon_command().map(|command| command.to_string());
+                (decision.action, command)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn kick_names_and_ban_repeated_offences() {
+        let start = Date::from_ymd(1950, 0, 1).unwrap();
+        let now = Instant::now();
+        let mut moderator = Moderator::new()
+            .kick_names(Regex::new("(?i)admin").unwrap())
+            .ban_after(2);
+
+        let decisions = moderator.update_at(&client_info(3, "Admin", 0, start), now);
+        assert_eq!(
+            actions(decisions),
+            vec![(
+                Action::Kick,
+                Some("kick 3 \"name matches (?i)admin\"".to_string())
+            )]
+        );
+        // Polling the clients again does not count another offence.
+        assert!(moderator
+            .update_at(&client_info(3, "Admin", 0, start), now)
+            .is_empty());
+        assert_eq!(moderator.offences("192.0.2.3"), 1);
+        moderator.update_at(
+            &Packet::ClientQuit(server_packets::ClientQuit { id: 3 }),
+            now,
+        );
+
+        // The same address comes back with another name and renames itself.
+        moderator.update_at(&client_info(3, "Player", 0, start), now);
+        let decisions = moderator.update_at(
+            &Packet::ClientUpdate(server_packets::ClientUpdate {
+                id: 3,
+                name: "The Admin".to_string(),
+                company_id: 0,
+            }),
+            now,
+        );
+        assert_eq!(
+            actions(decisions),
+            vec![(
+                Action::Ban,
+                Some("ban 192.0.2.3 \"name matches (?i)admin\"".to_string())
+            )]
+        );
+        assert_eq!(moderator.offences("192.0.2.3"), 2);
+        assert_eq!(moderator.audit_log().len(), 2);
+    }
+
+    #[test]
+    fn flood_protection() {
+        let start = Date::from_ymd(1950, 0, 1).unwrap();
+        let now = Instant::now();
+        let mut moderator = Moderator::new().limit_chat(2, Duration::from_secs(10));
+        moderator.update_at(&client_info(4, "Spammer", 0, start), now);
+
+        let mut decisions = Vec::new();
+        for second in 0..4 {
+            decisions.extend(moderator.update_at(&chat(4), now + Duration::from_secs(second)));
+        }
+        assert_eq!(
+            actions(decisions),
+            vec![
+                (Action::Warn, None),
+                (
+                    Action::Kick,
+                    Some("kick 4 \"flooding the chat\"".to_string())
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn kick_idle_spectators() {
+        let start = Date::from_ymd(1950, 0, 1).unwrap();
+        let now = Instant::now();
+        let mut moderator = Moderator::new().kick_idle_spectators(30);
+        let date = |day| {
+            Packet::Date(server_packets::Date {
+                date: Date::from_openttd_date(start.to_openttd_date() + day).unwrap(),
+            })
+        };
+        moderator.update_at(&date(0), now);
+        // In wallclock mode the join date is an economy date, which may lie
+        // long before the calendar date.
+        let economy_date = Date::from_ymd(1, 0, 1).unwrap();
+        moderator.update_at(&client_info(5, "Watcher", SPECTATOR, economy_date), now);
+        moderator.update_at(&client_info(6, "Player", 0, start), now);
+
+        assert!(moderator.update_at(&date(29), now).is_empty());
+        assert_eq!(
+            actions(moderator.update_at(&date(30), now)),
+            vec![(
+                Action::Kick,
+                Some("kick 5 \"spectating for 30 days\"".to_string())
+            )]
+        );
+        assert!(moderator.update_at(&date(31), now).is_empty());
+    }
+
+    #[test]
+    fn kick_idle_spectators_after() {
+        let start = Date::from_ymd(1950, 0, 1).unwrap();
+        let now = Instant::now();
+        let minutes = |minutes: u64| now + Duration::from_secs(minutes * 60);
+        let mut moderator =
+            Moderator::new().kick_idle_spectators_after(Duration::from_secs(30 * 60));
+        // In wallclock mode the calendar may be frozen, so the date does not
+        // change.
+        let date = Packet::Date(server_packets::Date { date: start });
+        moderator.update_at(&date, now);
+        moderator.update_at(&client_info(5, "Watcher", SPECTATOR, start), now);
+        moderator.update_at(&client_info(6, "Player", 0, start), now);
+
+        assert!(moderator.update_at(&date, minutes(29)).is_empty());
+        assert_eq!(
+            actions(moderator.update_at(&date, minutes(30))),
+            vec![(
+                Action::Kick,
+                Some("kick 5 \"spectating for 30 minutes\"".to_string())
+            )]
+        );
+        assert!(moderator.update_at(&date, minutes(31)).is_empty());
+    }
+}
@@ -76,6 +76,37 @@ impl Packet for Chat<'_> {
     const PACKET_TYPE: u8 = 4;
 }
 
+/// NETWORK_ACTION_CHAT
+const NETWORK_ACTION_CHAT: u8 = 3;
+/// NETWORK_ACTION_CHAT_CLIENT
+const NETWORK_ACTION_CHAT_CLIENT: u8 = 5;
+/// DESTTYPE_BROADCAST
+const DESTTYPE_BROADCAST: u8 = 0;
+/// DESTTYPE_CLIENT
+const DESTTYPE_CLIENT: u8 = 2;
+
+impl<'a> Chat<'a> {
+    /// A chat message to all clients.
+    pub fn broadcast(message: &'a str) -> Chat<'a> {
+        Chat {
+            action: NETWORK_ACTION_CHAT,
+            destination_type: DESTTYPE_BROADCAST,
+            destination_id: 0,
+            message,
+        }
+    }
+
+    /// A private chat message to a single client.
+    pub fn to_client(client_id: u32, message: &'a str) -> Chat<'a> {
+        Chat {
+            action: NETWORK_ACTION_CHAT_CLIENT,
+            destination_type: DESTTYPE_CLIENT,
+            destination_id: client_id,
+            message,
+        }
+    }
+}
+
 /// Execute a command on the servers console.
 #[derive(Serialize, Deserialize, Copy, Clone, Debug, Eq, PartialEq)]
 pub struct Rcon<'a> {
@@ -0,0 +1,349 @@
+//! A stable JSON representation of server packets, for piping events to
+//! other systems. It is available with the `json` feature.
+//!
+//! Packets are objects tagged with their type in the `"type"` field, next to
+//! the fields of the packet:
+//!
+//! ```json
+//! {"type": "Chat", "action": 3, "destination": 0, "client": 2, "message": "hi", "money": null}
+//! {"type": "Newgame"}
+//! {"type": "UnknownPacket", "packet_type": 200, "buffer": "0a0b"}
+//! ```
+//!
+//! - Dates are written as `YYYY-MM-DD`, except for economy dates such as the
+//!   date a client joined, which are written as the number of days since
+//!   year 0.
+//! - Update types are written by name, e.g. `"CompanyEconomy"`.
+//! - Update frequencies are written as a list of names, e.g.
+//!   `["Poll", "Automatic"]`.
+//! - The buffer of an unknown packet is written as a hexadecimal string.
+
+use super::server_packets::{
+    Chat, ClientError, ClientInfo, ClientJoin, ClientQuit, ClientUpdate, CmdLogging, CmdNames,
+    CompanyEconomy, CompanyInfo, CompanyNew, CompanyRemove, CompanyStats, CompanyUpdate, Console,
+    Date, Error, Gamescript, Packet, Pong, Protocol, Rcon, RconEnd, UpdatePacketDescription,
+    Welcome,
+};
+use crate::types;
+use serde_derive::{Deserialize, Serialize};
+
+/// Convert a packet to a JSON string.
+pub fn to_string(packet: &Packet) -> serde_json::Result<String> {
+    let mut json = Vec::new();
+    PacketDef::serialize(packet, &mut serde_json::Serializer::new(&mut json))?;
+    // serde_json only writes valid UTF-8.
+    Ok(String::from_utf8(json).unwrap())
+}
+
+/// Convert a packet to a JSON value.
+pub fn to_value(packet: &Packet) -> serde_json::Result<serde_json::Value> {
+    PacketDef::serialize(packet, serde_json::value::Serializer)
+}
+
+/// Read a packet from its JSON representation.
+pub fn from_str(json: &str) -> serde_json::Result<Packet> {
+    let mut deserializer = serde_json::Deserializer::from_str(json);
+    let packet = PacketDef::deserialize(&mut deserializer)?;
+    deserializer.end()?;
+    Ok(packet)
+}
+
+/// The JSON representation of [`Packet`]. It mirrors the packet, but is
+/// tagged by its type and writes dates, update types and buffers in a
+/// readable way. The serde implementations of the packets themselves are
+/// left alone, so that they stay the same for other formats.
+#[derive(Serialize, Deserialize)]
+#[serde(remote = "Packet", tag = "type")]
+enum PacketDef {
+    Full,
+    Banned,
+    Error(Error),
+    Protocol(#[serde(with = "ProtocolDef")] Protocol),
+    Welcome(#[serde(with = "WelcomeDef")] Welcome),
+    Newgame,
+    Shutdown,
+    Date(#[serde(with = "DateDef")] Date),
+    ClientJoin(ClientJoin),
+    ClientInfo(ClientInfo),
+    ClientUpdate(ClientUpdate),
+    ClientQuit(ClientQuit),
+    ClientError(ClientError),
+    CompanyNew(CompanyNew),
+    CompanyInfo(CompanyInfo),
+    CompanyUpdate(CompanyUpdate),
+    CompanyRemove(CompanyRemove),
+    CompanyEconomy(CompanyEconomy),
+    CompanyStats(CompanyStats),
+    Chat(Chat),
+    Rcon(Rcon),
+    Console(Console),
+    CmdNames(CmdNames),
+    CmdLogging(CmdLogging),
+    Gamescript(Gamescript),
+    RconEnd(RconEnd),
+    Pong(Pong),
+    UnknownPacket {
+        packet_type: u8,
+        #[serde(with = "hex_buffer")]
+        buffer: Vec<u8>,
+    },
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(remote = "Protocol")]
+struct ProtocolDef {
+    version: u8,
+    #[serde(with = "update_packets")]
+    update_packets: Vec<UpdatePacketDescription>,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(remote = "UpdatePacketDescription")]
+struct UpdatePacketDescriptionDef {
+    #[serde(with = "update_type")]
+    packet_type: types::AdminUpdateType,
+    #[serde(with = "frequencies")]
+    frequencies_allowed: types::UpdateFrequencies,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(remote = "Welcome")]
+struct WelcomeDef {
+    server_name: String,
+    openttd_version: String,
+    is_dedicated: bool,
+    map_name: String,
+    map_seed: u32,
+    map_landscape: u8,
+    #[serde(with = "ymd")]
+    map_start_date: types::Date,
+    map_width: u16,
+    map_height: u16,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(remote = "Date")]
+struct DateDef {
+    #[serde(with = "ymd")]
+    date: types::Date,
+}
+
+/// Writes the update packet descriptions of [`Protocol`] with
+/// [`UpdatePacketDescriptionDef`].
+mod update_packets {
+    use super::{UpdatePacketDescription, UpdatePacketDescriptionDef};
+    use serde::{Deserialize, Deserializer, Serializer};
+    use serde_derive::{Deserialize, Serialize};
+
+    #[derive(Serialize, Deserialize)]
+    struct Description(#[serde(with = "UpdatePacketDescriptionDef")] UpdatePacketDescription);
+
+    pub fn serialize<S>(
+        descriptions: &[UpdatePacketDescription],
+        serializer: S,
+    ) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.collect_seq(
+            descriptions
+                .iter()
+                .map(|description| Description(*description)),
+        )
+    }
+
+    pub fn deserialize<'de, D>(deserializer: D) -> Result<Vec<UpdatePacketDescription>, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        Ok(Vec::<Description>::deserialize(deserializer)?
+            .into_iter()
+            .map(|Description(description)| description)
+            .collect())
+    }
+}
+
+/// Writes a date as `YYYY-MM-DD`.
+mod ymd {
+    use crate::types::Date;
+    use serde::{de, Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S>(date: &Date, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.collect_str(date)
+    }
+
+    pub fn deserialize<'de, D>(deserializer: D) -> Result<Date, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        String::deserialize(deserializer).and_then(|date| date.parse().map_err(de::Error::custom))
+    }
+}
+
+/// Writes an update type by name.
+mod update_type {
+    use crate::types::AdminUpdateType;
+    use serde::de::{self, Deserialize, Deserializer, Unexpected};
+    use serde::Serializer;
+
+    pub fn serialize<S>(update_type: &AdminUpdateType, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.serialize_str(update_type.name())
+    }
+
+    pub fn deserialize<'de, D>(deserializer: D) -> Result<AdminUpdateType, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        String::deserialize(deserializer).and_then(|name| {
+            AdminUpdateType::from_name(&name).ok_or_else(|| {
+                de::Error::invalid_value(Unexpected::Str(&name), &"a variant of AdminUpdateType")
+            })
+        })
+    }
+}
+
+/// Writes update frequencies as a list of names.
+mod frequencies {
+    use crate::types::UpdateFrequencies;
+    use serde::{de, Deserialize, Deserializer, Serializer};
+
+    const NAMES: [(UpdateFrequencies, &str); 7] = [
+        (UpdateFrequencies::POLL, "Poll"),
+        (UpdateFrequencies::DAILY, "Daily"),
+        (UpdateFrequencies::WEEKLY, "Weekly"),
+        (UpdateFrequencies::MONTHLY, "Monthly"),
+        (UpdateFrequencies::QUARTERLY, "Quarterly"),
+        (UpdateFrequencies::ANNUALLY, "Annually"),
+        (UpdateFrequencies::AUTOMATIC, "Automatic"),
+    ];
+
+    pub fn serialize<S>(frequencies: &UpdateFrequencies, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.collect_seq(
+            NAMES
+                .iter()
+                .filter(|(frequency, _)| frequencies.contains(*frequency))
+                .map(|(_, name)| name),
+        )
+    }
+
+    pub fn deserialize<'de, D>(deserializer: D) -> Result<UpdateFrequencies, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        Vec::<String>::deserialize(deserializer)?.iter().try_fold(
+            UpdateFrequencies::empty(),
+            |frequencies, name| {
+                NAMES
+                    .iter()
+                    .find(|(_, frequency_name)| frequency_name == name)
+                    .map(|(frequency, _)| frequencies | *frequency)
+                    .ok_or_else(|| de::Error::custom("unknown update frequency"))
+            },
+        )
+    }
+}
+
+/// Writes a buffer as a hexadecimal string.
+mod hex_buffer {
+    use serde::{de, Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S>(buffer: &[u8], serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let hex: String = buffer.iter().map(|b| format!("{:02x}", b)).collect();
+        serializer.serialize_str(&hex)
+    }
+
+    pub fn deserialize<'de, D>(deserializer: D) -> Result<Vec<u8>, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let hex = String::deserialize(deserializer)?;
+        if hex.len() % 2 != 0 || !hex.is_ascii() {
+            return Err(de::Error::custom("invalid hexadecimal buffer"));
+        }
+        (0..hex.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&hex[i..i + 2], 16))
+            .collect::<Result<_, _>>()
+            .map_err(de::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::packet::admin::server_packets;
+    use crate::types;
+
+    #[test]
+    fn client_info_json() {
+        let packet = Packet::ClientInfo(server_packets::ClientInfo {
+            id: 2,
+            address: "127.0.0.1".to_string(),
+            name: "Player".to_string(),
+            language: 0,
+            date_joined: types::Date::from_ymd(1950, 0, 1).unwrap().into(),
+            company_id: 255,
+        });
+        let json = to_string(&packet).unwrap();
+        assert_eq!(
+            json,
+            r#"{"type":"ClientInfo","id":2,"address":"127.0.0.1","name":"Player","language":0,"date_joined":712223,"company_id":255}"#
+        );
+        assert_eq!(from_str(&json).unwrap(), packet);
+    }
+
+    #[test]
+    fn date_json() {
+        let packet = Packet::Date(server_packets::Date {
+            date: types::Date::from_ymd(1950, 0, 1).unwrap(),
+        });
+        let json = to_string(&packet).unwrap();
+        assert_eq!(json, r#"{"type":"Date","date":"1950-01-01"}"#);
+        assert_eq!(from_str(&json).unwrap(), packet);
+        assert_eq!(to_value(&packet).unwrap()["date"], "1950-01-01");
+    }
+
+    #[test]
+    fn protocol_json() {
+        let packet = Packet::Protocol(server_packets::Protocol {
+            version: 1,
+            update_packets: vec![server_packets::UpdatePacketDescription {
+                packet_type: types::AdminUpdateType::ClientInfo,
+                frequencies_allowed: types::UpdateFrequencies::POLL
+                    | types::UpdateFrequencies::AUTOMATIC,
+            }],
+        });
+        let json = to_string(&packet).unwrap();
+        assert_eq!(
+            json,
+            r#"{"type":"Protocol","version":1,"update_packets":[{"packet_type":"ClientInfo","frequencies_allowed":["Poll","Automatic"]}]}"#
+        );
+        assert_eq!(from_str(&json).unwrap(), packet);
+    }
+
+    #[test]
+    fn unknown_packet_json() {
+        let packet = Packet::UnknownPacket {
+            packet_type: 200,
+            buffer: vec![10, 11],
+        };
+        let json = to_string(&packet).unwrap();
+        assert_eq!(
+            json,
+            r#"{"type":"UnknownPacket","packet_type":200,"buffer":"0a0b"}"#
+        );
+        assert_eq!(from_str(&json).unwrap(), packet);
+    }
+}
@@ -5,6 +5,11 @@
 //! [`server_packets`](crate::packet::admin::server_packets).
 
 pub mod client_packets;
+#[cfg(feature = "json")]
+pub mod json;
+pub mod pcap;
+pub mod policy;
+pub mod recording;
 pub mod server_packets;
 
 #[cfg(test)]
@@ -12,6 +17,9 @@ mod test;
 
 pub use crate::packet::serde::{PacketRead, PacketWrite, Result};
 
+/// The maximum size of a packet, including the length and the packet type.
+pub(crate) const MAX_PACKET_SIZE: usize = 32767;
+
 /// Provides the function [`AdminRead::read_packet`]. It is implemented for any type implementing std::io::Read via PacketRead.
 pub trait AdminRead {
     fn read_packet(&mut self) -> Result<server_packets::Packet>;
@@ -0,0 +1,359 @@
+//! Export of recorded admin sessions as pcapng, so they can be inspected in
+//! Wireshark and other standard tooling. The packets are wrapped in
+//! synthetic IPv4 and TCP headers between a client at `127.0.0.2` and a
+//! server at `127.0.0.1` on the admin port. [`write_dissector`] writes a
+//! Wireshark Lua dissector that names the packet types.
+
+use super::recording::{Direction, Frame};
+use byteorder::{BigEndian, ByteOrder, LittleEndian, WriteBytesExt};
+use std::io::{self, Write};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// The default port of the admin interface.
+pub const ADMIN_PORT: u16 = 3977;
+
+const CLIENT_ADDRESS: [u8; 4] = [127, 0, 0, 2];
+const SERVER_ADDRESS: [u8; 4] = [127, 0, 0, 1];
+const CLIENT_PORT: u16 = 49152;
+
+const BLOCK_SECTION_HEADER: u32 = 0x0A0D_0D0A;
+const BLOCK_INTERFACE_DESCRIPTION: u32 = 0x0000_0001;
+const BLOCK_ENHANCED_PACKET: u32 = 0x0000_0006;
+const LINKTYPE_RAW: u16 = 101;
+
+const TCP_FIN: u8 = 0x01;
+const TCP_SYN: u8 = 0x02;
+const TCP_PSH: u8 = 0x08;
+const TCP_ACK: u8 = 0x10;
+
+/// Returns the name of a packet type in the `PacketAdminType` enum of the
+/// OpenTTD source (`network/core/tcp_admin.h`), or `None` if the packet type
+/// is unknown to this crate.
+pub fn packet_type_name(packet_type: u8) -> Option<&'static str> {
+    Some(match packet_type {
+        0 => "ADMIN_PACKET_ADMIN_JOIN",
+        1 => "ADMIN_PACKET_ADMIN_QUIT",
+        2 => "ADMIN_PACKET_ADMIN_UPDATE_FREQUENCY",
+        3 => "ADMIN_PACKET_ADMIN_POLL",
+        4 => "ADMIN_PACKET_ADMIN_CHAT",
+        5 => "ADMIN_PACKET_ADMIN_RCON",
+        6 => "ADMIN_PACKET_ADMIN_GAMESCRIPT",
+        7 => "ADMIN_PACKET_ADMIN_PING",
+        100 => "ADMIN_PACKET_SERVER_FULL",
+        101 => "ADMIN_PACKET_SERVER_BANNED",
+        102 => "ADMIN_PACKET_SERVER_ERROR",
+        103 => "ADMIN_PACKET_SERVER_PROTOCOL",
+        104 => "ADMIN_PACKET_SERVER_WELCOME",
+        105 => "ADMIN_PACKET_SERVER_NEWGAME",
+        106 => "ADMIN_PACKET_SERVER_SHUTDOWN",
+        107 => "ADMIN_PACKET_SERVER_DATE",
+        108 => "ADMIN_PACKET_SERVER_CLIENT_JOIN",
+        109 => "ADMIN_PACKET_SERVER_CLIENT_INFO",
+        110 => "ADMIN_PACKET_SERVER_CLIENT_UPDATE",
+        111 => "ADMIN_PACKET_SERVER_CLIENT_QUIT",
+        112 => "ADMIN_PACKET_SERVER_CLIENT_ERROR",
+        113 => "ADMIN_PACKET_SERVER_COMPANY_NEW",
+        114 => "ADMIN_PACKET_SERVER_COMPANY_INFO",
+        115 => "ADMIN_PACKET_SERVER_COMPANY_UPDATE",
+        116 => "ADMIN_PACKET_SERVER_COMPANY_REMOVE",
+        117 => "ADMIN_PACKET_SERVER_COMPANY_ECONOMY",
+        118 => "ADMIN_PACKET_SERVER_COMPANY_STATS",
+        119 => "ADMIN_PACKET_SERVER_CHAT",
+        120 => "ADMIN_PACKET_SERVER_RCON",
+        121 => "ADMIN_PACKET_SERVER_CONSOLE",
+        122 => "ADMIN_PACKET_SERVER_CMD_NAMES",
+        123 => "ADMIN_PACKET_SERVER_CMD_LOGGING",
+        124 => "ADMIN_PACKET_SERVER_GAMESCRIPT",
+        125 => "ADMIN_PACKET_SERVER_RCON_END",
+        126 => "ADMIN_PACKET_SERVER_PONG",
+        _ => return None,
+    })
+}
+
+/// Write a pcapng block with the given type and body.
+fn write_block<W: Write>(writer: &mut W, block_type: u32, body: &[u8]) -> io::Result<()> {
+    let padding = (4 - body.len() % 4) % 4;
+    let length = (12 + body.len() + padding) as u32;
+    writer.write_u32::<LittleEndian>(block_type)?;
+    writer.write_u32::<LittleEndian>(length)?;
+    writer.write_all(body)?;
+    writer.write_all(&[0u8; 3][..padding])?;
+    writer.write_u32::<LittleEndian>(length)
+}
+
+/// The ones' complement checksum used by IPv4 and TCP.
+fn checksum(chunks: &[&[u8]]) -> u16 {
+    let mut sum = 0u32;
+    for chunk in chunks {
+        for pair in chunk.chunks(2) {
+            let high = u32::from(pair[0]) << 8;
+            let low = pair.get(1).copied().map(u32::from).unwrap_or(0);
+            sum += high | low;
+        }
+    }
+    while sum > 0xFFFF {
+        sum = (sum & 0xFFFF) + (sum >> 16);
+    }
+    !(sum as u16)
+}
+
+/// Tracks the synthetic TCP connection.
+struct TcpState {
+    client_seq: u32,
+    server_seq: u32,
+}
+
+impl TcpState {
+    /// Build an IPv4 packet containing a TCP segment.
+    fn segment(&mut self, from_client: bool, flags: u8, payload: &[u8]) -> Vec<u8> {
+        let (source, destination, source_port, destination_port, seq, ack) = if from_client {
+            (
+                CLIENT_ADDRESS,
+                SERVER_ADDRESS,
+                CLIENT_PORT,
+                ADMIN_PORT,
+                self.client_seq,
+                self.server_seq,
+            )
+        } else {
+            (
+                SERVER_ADDRESS,
+                CLIENT_ADDRESS,
+                ADMIN_PORT,
+                CLIENT_PORT,
+                self.server_seq,
+                self.client_seq,
+            )
+        };
+        let mut advance = payload.len() as u32;
+        if flags & (TCP_SYN | TCP_FIN) != 0 {
+            advance += 1;
+        }
+        if from_client {
+            self.client_seq = self.client_seq.wrapping_add(advance);
+        } else {
+            self.server_seq = self.server_seq.wrapping_add(advance);
+        }
+
+        let mut tcp = vec![0u8; 20];
+        BigEndian::write_u16(&mut tcp[0..2], source_port);
+        BigEndian::write_u16(&mut tcp[2..4], destination_port);
+        BigEndian::write_u32(&mut tcp[4..8], seq);
+        BigEndian::write_u32(&mut tcp[8..12], if flags & TCP_ACK != 0 { ack } else { 0 });
+        tcp[12] = 5 << 4;
+        tcp[13] = flags;
+        BigEndian::write_u16(&mut tcp[14..16], 0xFFFF);
+        tcp.extend_from_slice(payload);
+        let mut pseudo_header = [0u8; 12];
+        pseudo_header[0..4].copy_from_slice(&source);
+        pseudo_header[4..8].copy_from_slice(&destination);
+        pseudo_header[9] = 6;
+        BigEndian::write_u16(&mut pseudo_header[10..12], tcp.len() as u16);
+        let tcp_checksum = checksum(&[&pseudo_header, &tcp]);
+        BigEndian::write_u16(&mut tcp[16..18], tcp_checksum);
+
+        let mut ip = vec![0u8; 20];
+        ip[0] = 0x45;
+        BigEndian::write_u16(&mut ip[2..4], (20 + tcp.len()) as u16);
+        ip[6] = 0x40;
+        ip[8] = 64;
+        ip[9] = 6;
+        ip[12..16].copy_from_slice(&source);
+        ip[16..20].copy_from_slice(&destination);
+        let ip_checksum = checksum(&[&ip]);
+        BigEndian::write_u16(&mut ip[10..12], ip_checksum);
+        ip.extend_from_slice(&tcp);
+        ip
+    }
+}
+
+/// Writes admin packets as a pcapng capture.
+pub struct PcapWriter<W: Write> {
+    writer: W,
+    start: SystemTime,
+    tcp: TcpState,
+}
+
+impl<W: Write> PcapWriter<W> {
+    /// Start a capture. Frame timestamps are relative to `start`. A synthetic
+    /// TCP handshake is written at the start time.
+    pub fn new(mut writer: W, start: SystemTime) -> io::Result<PcapWriter<W>> {
+        let mut section_header = Vec::new();
+        section_header.write_u32::<LittleEndian>(0x1A2B_3C4D)?;
+        section_header.write_u16::<LittleEndian>(1)?;
+        section_header.write_u16::<LittleEndian>(0)?;
+        section_header.write_i64::<LittleEndian>(-1)?;
+        write_block(&mut writer, BLOCK_SECTION_HEADER, &section_header)?;
+
+        let mut interface = Vec::new();
+        interface.write_u16::<LittleEndian>(LINKTYPE_RAW)?;
+        interface.write_u16::<LittleEndian>(0)?;
+        interface.write_u32::<LittleEndian>(0)?;
+        write_block(&mut writer, BLOCK_INTERFACE_DESCRIPTION, &interface)?;
+
+        let mut pcap = PcapWriter {
+            writer,
+            start,
+            tcp: TcpState {
+                client_seq: 0,
+                server_seq: 0,
+            },
+        };
+        pcap.write_segment(Duration::from_secs(0), true, TCP_SYN, &[])?;
+        pcap.write_segment(Duration::from_secs(0), false, TCP_SYN | TCP_ACK, &[])?;
+        pcap.write_segment(Duration::from_secs(0), true, TCP_ACK, &[])?;
+        Ok(pcap)
+    }
+
+    fn write_segment(
+        &mut self,
+        timestamp: Duration,
+        from_client: bool,
+        flags: u8,
+        payload: &[u8],
+    ) -> io::Result<()> {
+        let data = self.tcp.segment(from_client, flags, payload);
+        let micros = (self.start + timestamp)
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_micros() as u64;
+        let mut body = Vec::with_capacity(data.len() + 20);
+        body.write_u32::<LittleEndian>(0)?;
+        body.write_u32::<LittleEndian>((micros >> 32) as u32)?;
+        body.write_u32::<LittleEndian>(micros as u32)?;
+        body.write_u32::<LittleEndian>(data.len() as u32)?;
+        body.write_u32::<LittleEndian>(data.len() as u32)?;
+        body.extend_from_slice(&data);
+        write_block(&mut self.writer, BLOCK_ENHANCED_PACKET, &body)
+    }
+
+    /// Write a recorded packet.
+    pub fn write_frame(&mut self, frame: &Frame) -> io::Result<()> {
+        self.write_segment(
+            frame.timestamp,
+            frame.direction == Direction::Sent,
+            TCP_PSH | TCP_ACK,
+            &frame.to_packet_bytes()?,
+        )
+    }
+
+    /// Returns the underlying writer.
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+}
+
+/// Write recorded frames as a pcapng capture.
+pub fn write_pcapng<W: Write>(writer: W, start: SystemTime, frames: &[Frame]) -> io::Result<W> {
+    let mut pcap = PcapWriter::new(writer, start)?;
+    for frame in frames {
+        pcap.write_frame(frame)?;
+    }
+    Ok(pcap.into_inner())
+}
+
+/// Write a Wireshark Lua dissector for the admin protocol on [`ADMIN_PORT`].
+pub fn write_dissector<W: Write>(mut writer: W) -> io::Result<()> {
+    writeln!(
+        writer,
+        "-- OpenTTD admin protocol dissector\n\
+         local openttd_admin = Proto(\"openttd_admin\", \"OpenTTD Admin\")\n\
+         local packet_types = {{"
+    )?;
+    for packet_type in 0..=u8::MAX {
+        if let Some(name) = packet_type_name(packet_type) {
+            writeln!(writer, "    [{}] = \"{}\",", packet_type, name)?;
+        }
+    }
+    writeln!(
+        writer,
+        "}}\n\
+         local f_length = ProtoField.uint16(\"openttd_admin.length\", \"Length\", base.DEC)\n\
+         local f_type = ProtoField.uint8(\"openttd_admin.type\", \"Type\", base.DEC, packet_types)\n\
+         local f_data = ProtoField.bytes(\"openttd_admin.data\", \"Data\")\n\
+         local e_malformed = ProtoExpert.new(\"openttd_admin.malformed\",\n\
+         \x20   \"Packet length is shorter than its header\", expert.group.MALFORMED,\n\
+         \x20   expert.severity.ERROR)\n\
+         openttd_admin.fields = {{ f_length, f_type, f_data }}\n\
+         openttd_admin.experts = {{ e_malformed }}\n\
+         \n\
+         function openttd_admin.dissector(buffer, pinfo, tree)\n\
+         \x20   local offset = 0\n\
+         \x20   while offset < buffer:len() do\n\
+         \x20       if buffer:len() - offset < 2 then\n\
+         \x20           pinfo.desegment_offset = offset\n\
+         \x20           pinfo.desegment_len = DESEGMENT_ONE_MORE_SEGMENT\n\
+         \x20           return\n\
+         \x20       end\n\
+         \x20       local length = buffer(offset, 2):le_uint()\n\
+         \x20       if length < 3 then\n\
+         \x20           -- The rest of the stream cannot be split into packets.\n\
+         \x20           local subtree = tree:add(openttd_admin, buffer(offset))\n\
+         \x20           subtree:add_le(f_length, buffer(offset, 2))\n\
+         \x20           subtree:add_proto_expert_info(e_malformed)\n\
+         \x20           return\n\
+         \x20       end\n\
+         \x20       if buffer:len() - offset < length then\n\
+         \x20           pinfo.desegment_offset = offset\n\
+         \x20           pinfo.desegment_len = length - (buffer:len() - offset)\n\
+         \x20           return\n\
+         \x20       end\n\
+         \x20       pinfo.cols.protocol = \"OTTD-ADMIN\"\n\
+         \x20       local packet_type = buffer(offset + 2, 1):uint()\n\
+         \x20       local subtree = tree:add(openttd_admin, buffer(offset, length),\n\
+         \x20           packet_types[packet_type] or \"UNKNOWN\")\n\
+         \x20       subtree:add_le(f_length, buffer(offset, 2))\n\
+         \x20       subtree:add(f_type, buffer(offset + 2, 1))\n\
+         \x20       if length > 3 then\n\
+         \x20           subtree:add(f_data, buffer(offset + 3, length - 3))\n\
+         \x20       end\n\
+         \x20       offset = offset + length\n\
+         \x20   end\n\
+         end\n\
+         \n\
+         DissectorTable.get(\"tcp.port\"):add({}, openttd_admin)",
+        ADMIN_PORT
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn ip_checksum_validates() {
+        let mut tcp = TcpState {
+            client_seq: 0,
+            server_seq: 0,
+        };
+        let packet = tcp.segment(true, TCP_PSH | TCP_ACK, &[3, 0, 1]);
+        assert_eq!(checksum(&[&packet[..20]]), 0);
+        assert_eq!(tcp.client_seq, 3);
+    }
+
+    #[test]
+    fn blocks_are_aligned() {
+        let frames = vec![Frame {
+            timestamp: Duration::from_millis(5),
+            direction: Direction::Received,
+            packet_type: 126,
+            buffer: vec![1, 0, 0, 0],
+        }];
+        let output = write_pcapng(Vec::new(), UNIX_EPOCH, &frames).unwrap();
+        let mut offset = 0;
+        let mut blocks = 0;
+        while offset < output.len() {
+            let length = LittleEndian::read_u32(&output[offset + 4..]) as usize;
+            assert_eq!(length % 4, 0);
+            assert_eq!(
+                LittleEndian::read_u32(&output[offset + length - 4..]) as usize,
+                length
+            );
+            offset += length;
+            blocks += 1;
+        }
+        assert_eq!(offset, output.len());
+        // Section header, interface, handshake and the frame.
+        assert_eq!(blocks, 6);
+    }
+}
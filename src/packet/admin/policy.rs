@@ -0,0 +1,475 @@
+//! Access policies for client packets. When the admin port is shared with
+//! third party tools, a [`Policy`] decides per downstream credential which
+//! client packets may be passed on to the server. It can restrict packet
+//! types, whitelist rcon commands and rate limit chat.
+//!
+//! A [`PolicyWriter`] enforces a policy on the packets written through it.
+//! Every denied packet is logged as a warning.
+
+use super::{client_packets, MAX_PACKET_SIZE};
+use crate::packet::serde::{from_bytes, PacketWrite, WritablePacket};
+use bitflags::*;
+use byteorder::{LittleEndian, WriteBytesExt};
+use regex::Regex;
+use std::collections::{HashMap, VecDeque};
+use std::fmt::{self, Display};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+bitflags! {
+    /// A set of client packet types.
+    pub struct ClientPacketTypes: u8 {
+        const JOIN = 0x01;
+        const QUIT = 0x02;
+        const UPDATE_FREQUENCY = 0x04;
+        const POLL = 0x08;
+        const CHAT = 0x10;
+        const RCON = 0x20;
+        const GAMESCRIPT = 0x40;
+        const PING = 0x80;
+        /// Packets that only read information from the server.
+        const READ_ONLY = Self::JOIN.bits
+            | Self::QUIT.bits
+            | Self::UPDATE_FREQUENCY.bits
+            | Self::POLL.bits
+            | Self::PING.bits;
+    }
+}
+
+impl ClientPacketTypes {
+    /// Returns the flag belonging to a client packet type, or `None` if the
+    /// packet type is unknown.
+    pub fn from_packet_type(packet_type: u8) -> Option<ClientPacketTypes> {
+        match packet_type {
+            0 => Some(ClientPacketTypes::JOIN),
+            1 => Some(ClientPacketTypes::QUIT),
+            2 => Some(ClientPacketTypes::UPDATE_FREQUENCY),
+            3 => Some(ClientPacketTypes::POLL),
+            4 => Some(ClientPacketTypes::CHAT),
+            5 => Some(ClientPacketTypes::RCON),
+            6 => Some(ClientPacketTypes::GAMESCRIPT),
+            7 => Some(ClientPacketTypes::PING),
+            _ => None,
+        }
+    }
+}
+
+/// A rule that allows an rcon command.
+#[derive(Clone, Debug)]
+pub enum RconRule {
+    /// Allow commands starting with these words, for example `"say"` allows
+    /// `say "hi"` but not `saveconfig`. An empty prefix allows every command.
+    Prefix(String),
+    /// Allow commands matching this regular expression. The expression must
+    /// match the whole command, without surrounding whitespace; use
+    /// [`RconRule::regex`] to anchor a pattern.
+    Regex(Regex),
+}
+
+impl RconRule {
+    /// A rule allowing commands that match `pattern` as a whole.
+    pub fn regex(pattern: &str) -> Result<RconRule, regex::Error> {
+        Regex::new(&format!("^(?:{})$", pattern)).map(RconRule::Regex)
+    }
+
+    /// Returns true if the command is allowed by this rule.
+    pub fn matches(&self, command: &str) -> bool {
+        match self {
+            RconRule::Prefix(prefix) => match command.trim_start().strip_prefix(prefix.as_str()) {
+                Some(rest) => {
+                    prefix.is_empty() || rest.is_empty() || rest.starts_with(char::is_whitespace)
+                }
+                None => false,
+            },
+            RconRule::Regex(regex) => {
+                let command = command.trim();
+                regex
+                    .find(command)
+                    .is_some_and(|found| found.start() == 0 && found.end() == command.len())
+            }
+        }
+    }
+}
+
+/// Limits the number of chat messages within a sliding time window.
+#[derive(Clone, Debug)]
+pub(crate) struct ChatLimit {
+    max_messages: usize,
+    interval: Duration,
+    sent: VecDeque<Instant>,
+}
+
+impl ChatLimit {
+    pub(crate) fn new(max_messages: usize, interval: Duration) -> ChatLimit {
+        ChatLimit {
+            max_messages,
+            interval,
+            sent: VecDeque::new(),
+        }
+    }
+
+    /// Records a message at `now` if the limit allows it.
+    pub(crate) fn try_send(&mut self, now: Instant) -> bool {
+        while let Some(&oldest) = self.sent.front() {
+            if now.duration_since(oldest) >= self.interval {
+                self.sent.pop_front();
+            } else {
+                break;
+            }
+        }
+        if self.sent.len() < self.max_messages {
+            self.sent.push_back(now);
+            true
+        } else {
+            false
+        }
+    }
+}
+
+/// The reason a client packet was denied.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Denied {
+    /// The packet type is not allowed.
+    PacketType { packet_type: u8 },
+    /// The rcon command is not whitelisted.
+    RconCommand { command: String },
+    /// Too many chat messages were sent.
+    ChatRateLimited,
+    /// The packet could not be decoded.
+    Malformed { packet_type: u8 },
+}
+
+impl Display for Denied {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Denied::PacketType { packet_type } => {
+                write!(f, "packet type {} is not allowed", packet_type)
+            }
+            Denied::RconCommand { command } => {
+                write!(f, "rcon command {:?} is not allowed", command)
+            }
+            Denied::ChatRateLimited => f.write_str("chat rate limit exceeded"),
+            Denied::Malformed { packet_type } => {
+                write!(f, "packet of type {} is malformed", packet_type)
+            }
+        }
+    }
+}
+
+impl std::error::Error for Denied {}
+
+/// Decides which client packets are allowed.
+///
+/// By default rcon commands are denied even if [`ClientPacketTypes::RCON`] is
+/// allowed; use [`Policy::allow_rcon`] to whitelist commands.
+///
+/// Clones of a policy share its chat limit, so all connections using the
+/// policy of a credential are limited together.
+#[derive(Clone, Debug)]
+pub struct Policy {
+    packet_types: ClientPacketTypes,
+    rcon_rules: Vec<RconRule>,
+    chat_limit: Option<Arc<Mutex<ChatLimit>>>,
+}
+
+impl Policy {
+    /// A policy allowing the given packet types.
+    pub fn new(packet_types: ClientPacketTypes) -> Policy {
+        Policy {
+            packet_types,
+            rcon_rules: Vec::new(),
+            chat_limit: None,
+        }
+    }
+
+    /// A policy that only allows reading information from the server.
+    pub fn read_only() -> Policy {
+        Policy::new(ClientPacketTypes::READ_ONLY)
+    }
+
+    /// A policy that allows every packet and every rcon command.
+    pub fn allow_all() -> Policy {
+        Policy::new(ClientPacketTypes::all()).allow_rcon(RconRule::Prefix(String::new()))
+    }
+
+    /// Whitelist rcon commands matching a rule. This also allows the rcon
+    /// packet type.
+    pub fn allow_rcon(mut self, rule: RconRule) -> Policy {
+        self.packet_types |= ClientPacketTypes::RCON;
+        self.rcon_rules.push(rule);
+        self
+    }
+
+    /// Allow at most `max_messages` chat messages per `interval`.
+    pub fn limit_chat(mut self, max_messages: usize, interval: Duration) -> Policy {
+        self.chat_limit = Some(Arc::new(Mutex::new(ChatLimit::new(max_messages, interval))));
+        self
+    }
+
+    /// Check whether a packet, as given by its type and data buffer, is
+    /// allowed.
+    pub fn check(&mut self, packet_type: u8, buffer: &[u8]) -> Result<(), Denied> {
+        self.check_at(packet_type, buffer, Instant::now())
+    }
+
+    /// Like [`Policy::check`], but using `now` as the current time for rate
+    /// limiting.
+    pub fn check_at(&mut self, packet_type: u8, buffer: &[u8], now: Instant) -> Result<(), Denied> {
+        let flag = ClientPacketTypes::from_packet_type(packet_type)
+            .filter(|flag| self.packet_types.contains(*flag))
+            .ok_or(Denied::PacketType { packet_type })?;
+        if flag == ClientPacketTypes::RCON {
+            let rcon: client_packets::Rcon =
+                from_bytes(buffer).map_err(|_| Denied::Malformed { packet_type })?;
+            if !self
+                .rcon_rules
+                .iter()
+                .any(|rule| rule.matches(rcon.command))
+            {
+                return Err(Denied::RconCommand {
+                    command: rcon.command.to_string(),
+                });
+            }
+        } else if flag == ClientPacketTypes::CHAT {
+            if let Some(limit) = &self.chat_limit {
+                if !limit.lock().unwrap().try_send(now) {
+                    return Err(Denied::ChatRateLimited);
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Policies by downstream credential.
+#[derive(Clone, Debug, Default)]
+pub struct Policies {
+    policies: HashMap<String, Policy>,
+}
+
+impl Policies {
+    pub fn new() -> Policies {
+        Policies::default()
+    }
+
+    /// Set the policy for a credential.
+    pub fn insert(&mut self, credential: impl Into<String>, policy: Policy) {
+        self.policies.insert(credential.into(), policy);
+    }
+
+    /// Returns the policy for a credential. Unknown credentials are not
+    /// allowed to send anything. The chat limit is shared by every
+    /// connection using the credential.
+    pub fn policy_for(&self, credential: &str) -> Policy {
+        self.policies
+            .get(credential)
+            .cloned()
+            .unwrap_or_else(|| Policy::new(ClientPacketTypes::empty()))
+    }
+}
+
+/// An error returned by [`PolicyWriter`].
+#[derive(Debug)]
+pub enum PolicyError {
+    /// The policy denied the packet.
+    Denied(Denied),
+    /// The packet could not be written.
+    Packet(crate::packet::serde::Error),
+}
+
+impl Display for PolicyError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PolicyError::Denied(denied) => denied.fmt(f),
+            PolicyError::Packet(err) => err.fmt(f),
+        }
+    }
+}
+
+impl std::error::Error for PolicyError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            PolicyError::Denied(denied) => Some(denied),
+            PolicyError::Packet(err) => Some(err),
+        }
+    }
+}
+
+impl From<Denied> for PolicyError {
+    fn from(denied: Denied) -> PolicyError {
+        PolicyError::Denied(denied)
+    }
+}
+
+impl From<crate::packet::serde::Error> for PolicyError {
+    fn from(err: crate::packet::serde::Error) -> PolicyError {
+        PolicyError::Packet(err)
+    }
+}
+
+impl From<std::io::Error> for PolicyError {
+    fn from(err: std::io::Error) -> PolicyError {
+        PolicyError::Packet(err.into())
+    }
+}
+
+/// Wraps the writer to the server and only passes on packets allowed by a
+/// policy.
+#[derive(Debug)]
+pub struct PolicyWriter<W> {
+    inner: W,
+    credential: String,
+    policy: Policy,
+}
+
+impl<W: std::io::Write> PolicyWriter<W> {
+    /// Create a writer for a downstream connection using `credential`.
+    pub fn new(inner: W, credential: impl Into<String>, policy: Policy) -> PolicyWriter<W> {
+        PolicyWriter {
+            inner,
+            credential: credential.into(),
+            policy,
+        }
+    }
+
+    /// Write a packet if the policy allows it.
+    pub fn write_packet<T: client_packets::Packet>(
+        &mut self,
+        packet: &T,
+    ) -> Result<(), PolicyError> {
+        let mut output = Vec::new();
+        output.write_packet(packet)?;
+        self.forward(<T as WritablePacket>::PACKET_TYPE, &output[3..])
+    }
+
+    /// Write a raw packet, as read from a downstream connection, if the
+    /// policy allows it.
+    pub fn forward(&mut self, packet_type: u8, buffer: &[u8]) -> Result<(), PolicyError> {
+        let size = buffer.len() + 3;
+        if size > MAX_PACKET_SIZE {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                format!(
+                    "packet of {} bytes exceeds the maximum of {}",
+                    size, MAX_PACKET_SIZE
+                ),
+            )
+            .into());
+        }
+        if let Err(denied) = self.policy.check(packet_type, buffer) {
+            log::warn!("denied packet from {}: {}", self.credential, denied);
+            return Err(denied.into());
+        }
+        self.inner.write_u16::<LittleEndian>(size as u16)?;
+        self.inner.write_u8(packet_type)?;
+        self.inner.write_all(buffer)?;
+        Ok(())
+    }
+
+    /// Returns the wrapped writer.
+    pub fn into_inner(self) -> W {
+        self.inner
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn rcon_buffer(command: &str) -> Vec<u8> {
+        let mut output = Vec::new();
+        output
+            .write_packet(&client_packets::Rcon { command })
+            .unwrap();
+        output.split_off(3)
+    }
+
+    #[test]
+    fn read_only_denies_rcon() {
+        let mut policy = Policy::read_only();
+        assert_eq!(policy.check(3, &[]), Ok(()));
+        assert_eq!(
+            policy.check(5, &rcon_buffer("ban 3")),
+            Err(Denied::PacketType { packet_type: 5 })
+        );
+    }
+
+    #[test]
+    fn rcon_whitelist() {
+        let mut policy = Policy::read_only()
+            .allow_rcon(RconRule::Prefix("clients".to_string()))
+            .allow_rcon(RconRule::regex("say \"[^\"]*\"").unwrap())
+            .allow_rcon(RconRule::Regex(Regex::new("pause|unpause").unwrap()));
+        assert_eq!(policy.check(5, &rcon_buffer("clients")), Ok(()));
+        assert_eq!(policy.check(5, &rcon_buffer("say \"hi\"")), Ok(()));
+        assert_eq!(policy.check(5, &rcon_buffer("clients ")), Ok(()));
+        assert_eq!(
+            policy.check(5, &rcon_buffer("clientsreset")),
+            Err(Denied::RconCommand {
+                command: "clientsreset".to_string()
+            })
+        );
+        assert_eq!(
+            policy.check(5, &rcon_buffer("reset_company 1")),
+            Err(Denied::RconCommand {
+                command: "reset_company 1".to_string()
+            })
+        );
+        assert_eq!(policy.check(5, &rcon_buffer(" unpause ")), Ok(()));
+        for command in &["reset_company 1; say \"hi\"", "pause; reset_company 1"] {
+            assert_eq!(
+                policy.check(5, &rcon_buffer(command)),
+                Err(Denied::RconCommand {
+                    command: command.to_string()
+                })
+            );
+        }
+    }
+
+    #[test]
+    fn chat_rate_limit() {
+        let mut policy =
+            Policy::new(ClientPacketTypes::CHAT).limit_chat(2, Duration::from_secs(10));
+        let start = Instant::now();
+        assert_eq!(policy.check_at(4, &[], start), Ok(()));
+        assert_eq!(policy.check_at(4, &[], start), Ok(()));
+        assert_eq!(
+            policy.check_at(4, &[], start + Duration::from_secs(5)),
+            Err(Denied::ChatRateLimited)
+        );
+        assert_eq!(
+            policy.check_at(4, &[], start + Duration::from_secs(10)),
+            Ok(())
+        );
+    }
+
+    #[test]
+    fn chat_rate_limit_per_credential() {
+        let mut policies = Policies::new();
+        policies.insert(
+            "bot",
+            Policy::new(ClientPacketTypes::CHAT).limit_chat(1, Duration::from_secs(10)),
+        );
+        let now = Instant::now();
+        assert_eq!(policies.policy_for("bot").check_at(4, &[], now), Ok(()));
+        // Reconnecting does not reset the limit.
+        assert_eq!(
+            policies.policy_for("bot").check_at(4, &[], now),
+            Err(Denied::ChatRateLimited)
+        );
+    }
+
+    #[test]
+    fn writer_forwards_allowed_packets() {
+        let mut writer = PolicyWriter::new(Vec::new(), "stats", Policy::read_only());
+        writer
+            .write_packet(&client_packets::Ping { id: 1 })
+            .unwrap();
+        assert!(writer
+            .write_packet(&client_packets::Rcon { command: "ban 1" })
+            .is_err());
+        // The length would not fit the header.
+        assert!(writer.forward(7, &[0; 70_000]).is_err());
+        assert_eq!(writer.into_inner(), vec![7, 0, 7, 1, 0, 0, 0]);
+    }
+}
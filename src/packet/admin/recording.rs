@@ -0,0 +1,454 @@
+//! Recording and replaying of admin connections. A [`Recorder`] wraps a
+//! stream and writes every packet that passes through it, together with a
+//! timestamp and its direction, to a recording. A [`Replayer`] reads such a
+//! recording and implements [`std::io::Read`], so the packets received from
+//! the server can be read again with [`AdminRead`](super::AdminRead).
+//!
+//! A recording starts with the bytes `OTTDREC` and a format version, followed
+//! by frames. Every frame consists of the timestamp in microseconds since the
+//! start of the recording (`u64`), the direction (`u8`), the packet type
+//! (`u8`), the buffer length (`u16`) and the buffer. All numbers are little
+//! endian.
+
+use super::MAX_PACKET_SIZE;
+use byteorder::{ByteOrder, LittleEndian, ReadBytesExt, WriteBytesExt};
+use std::io::{self, Read, Write};
+use std::time::{Duration, Instant};
+
+const MAGIC: &[u8; 7] = b"OTTDREC";
+const VERSION: u8 = 1;
+/// The timestamp, direction, packet type and buffer length of a frame.
+const FRAME_HEADER_SIZE: usize = 12;
+
+/// The direction of a recorded packet.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub enum Direction {
+    /// The packet was sent by the server.
+    Received,
+    /// The packet was sent by the admin.
+    Sent,
+}
+
+/// A single recorded packet.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Frame {
+    /// Time since the start of the recording.
+    pub timestamp: Duration,
+    /// Whether the packet was received or sent.
+    pub direction: Direction,
+    /// The packet type.
+    pub packet_type: u8,
+    /// The packet data, without the preceding length and packet type.
+    pub buffer: Vec<u8>,
+}
+
+impl Frame {
+    /// Write the frame in the recording format. Fails for frames that do not
+    /// fit in a packet.
+    pub fn write_to<W: Write>(&self, mut writer: W) -> io::Result<()> {
+        if self.buffer.len() + 3 > MAX_PACKET_SIZE {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "frame exceeds the maximum packet size",
+            ));
+        }
+        writer.write_u64::<LittleEndian>(self.timestamp.as_micros() as u64)?;
+        writer.write_u8(match self.direction {
+            Direction::Received => 0,
+            Direction::Sent => 1,
+        })?;
+        writer.write_u8(self.packet_type)?;
+        writer.write_u16::<LittleEndian>(self.buffer.len() as u16)?;
+        writer.write_all(&self.buffer)
+    }
+
+    /// Read a frame in the recording format. Returns `None` at the end of the
+    /// recording, and fails with [`io::ErrorKind::InvalidData`] if the
+    /// recording ends inside a frame.
+    pub fn read_from<R: Read>(mut reader: R) -> io::Result<Option<Frame>> {
+        let mut header = [0u8; FRAME_HEADER_SIZE];
+        let mut read = 0;
+        while read < header.len() {
+            match reader.read(&mut header[read..]) {
+                Ok(0) => break,
+                Ok(bytes) => read += bytes,
+                Err(ref err) if err.kind() == io::ErrorKind::Interrupted => {}
+                Err(err) => return Err(err),
+            }
+        }
+        match read {
+            0 => return Ok(None),
+            FRAME_HEADER_SIZE => {}
+            _ => return Err(invalid_data("recording ends inside a frame header")),
+        }
+        let timestamp = Duration::from_micros(LittleEndian::read_u64(&header));
+        let direction = match header[8] {
+            0 => Direction::Received,
+            1 => Direction::Sent,
+            _ => return Err(invalid_data("invalid frame direction")),
+        };
+        let packet_type = header[9];
+        let length = usize::from(LittleEndian::read_u16(&header[10..]));
+        if length + 3 > MAX_PACKET_SIZE {
+            return Err(invalid_data("frame exceeds the maximum packet size"));
+        }
+        let mut buffer = vec![0u8; length];
+        reader.read_exact(&mut buffer).map_err(|err| {
+            if err.kind() == io::ErrorKind::UnexpectedEof {
+                invalid_data("recording ends inside a frame")
+            } else {
+                err
+            }
+        })?;
+        Ok(Some(Frame {
+            timestamp,
+            direction,
+            packet_type,
+            buffer,
+        }))
+    }
+
+    /// Returns the packet as it was sent over the connection. Fails for
+    /// frames that do not fit in a packet.
+    pub fn to_packet_bytes(&self) -> io::Result<Vec<u8>> {
+        let length = self.buffer.len() + 3;
+        if length > MAX_PACKET_SIZE {
+            return Err(invalid_data("frame exceeds the maximum packet size"));
+        }
+        let mut bytes = Vec::with_capacity(length);
+        bytes.write_u16::<LittleEndian>(length as u16)?;
+        bytes.push(self.packet_type);
+        bytes.extend_from_slice(&self.buffer);
+        Ok(bytes)
+    }
+}
+
+fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+/// Write the header that starts a recording.
+fn write_header<W: Write>(mut writer: W) -> io::Result<()> {
+    writer.write_all(MAGIC)?;
+    writer.write_u8(VERSION)
+}
+
+/// Read and check the header that starts a recording.
+fn read_header<R: Read>(mut reader: R) -> io::Result<()> {
+    let mut magic = [0u8; 7];
+    reader.read_exact(&mut magic)?;
+    if &magic != MAGIC {
+        return Err(invalid_data("not an admin recording"));
+    }
+    if reader.read_u8()? != VERSION {
+        return Err(invalid_data("unsupported recording version"));
+    }
+    Ok(())
+}
+
+/// Read all frames of a recording.
+pub fn read_recording<R: Read>(mut reader: R) -> io::Result<Vec<Frame>> {
+    read_header(&mut reader)?;
+    let mut frames = Vec::new();
+    while let Some(frame) = Frame::read_from(&mut reader)? {
+        frames.push(frame);
+    }
+    Ok(frames)
+}
+
+/// Collects the bytes of a stream into packets.
+#[derive(Debug, Default)]
+struct FrameAssembler {
+    pending: Vec<u8>,
+}
+
+impl FrameAssembler {
+    /// Add bytes from the stream and return the packets that were completed.
+    /// Fails once a packet length is shorter than the packet header, as the
+    /// rest of the stream cannot be split into packets.
+    fn push(&mut self, bytes: &[u8]) -> io::Result<Vec<(u8, Vec<u8>)>> {
+        self.pending.extend_from_slice(bytes);
+        let mut packets = Vec::new();
+        while self.pending.len() >= 3 {
+            let length = usize::from(LittleEndian::read_u16(&self.pending));
+            if length < 3 {
+                return Err(invalid_data("packet length is shorter than its header"));
+            }
+            if self.pending.len() < length {
+                break;
+            }
+            let rest = self.pending.split_off(length);
+            let packet = std::mem::replace(&mut self.pending, rest);
+            packets.push((packet[2], packet[3..].to_vec()));
+        }
+        Ok(packets)
+    }
+}
+
+/// Wraps an admin stream and records all packets passing through it.
+#[derive(Debug)]
+pub struct Recorder<S, W: Write> {
+    stream: S,
+    output: W,
+    start: Instant,
+    received: FrameAssembler,
+    sent: FrameAssembler,
+}
+
+impl<S, W: Write> Recorder<S, W> {
+    /// Start recording `stream` to `output`.
+    pub fn new(stream: S, mut output: W) -> io::Result<Recorder<S, W>> {
+        write_header(&mut output)?;
+        Ok(Recorder {
+            stream,
+            output,
+            start: Instant::now(),
+            received: FrameAssembler::default(),
+            sent: FrameAssembler::default(),
+        })
+    }
+
+    fn record(&mut self, direction: Direction, bytes: &[u8]) -> io::Result<()> {
+        let timestamp = self.start.elapsed();
+        let packets = match direction {
+            Direction::Received => self.received.push(bytes)?,
+            Direction::Sent => self.sent.push(bytes)?,
+        };
+        for (packet_type, buffer) in packets {
+            Frame {
+                timestamp,
+                direction,
+                packet_type,
+                buffer,
+            }
+            .write_to(&mut self.output)?;
+        }
+        Ok(())
+    }
+
+    /// Returns the stream and the recording output.
+    pub fn into_inner(self) -> (S, W) {
+        (self.stream, self.output)
+    }
+}
+
+impl<S: Read, W: Write> Read for Recorder<S, W> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let read = self.stream.read(buf)?;
+        self.record(Direction::Received, &buf[..read])?;
+        Ok(read)
+    }
+}
+
+impl<S: Write, W: Write> Write for Recorder<S, W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let written = self.stream.write(buf)?;
+        self.record(Direction::Sent, &buf[..written])?;
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.stream.flush()?;
+        self.output.flush()
+    }
+}
+
+/// The speed at which a recording is replayed.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Speed {
+    /// Replay with the original timing.
+    Original,
+    /// Replay this many times faster than the original.
+    Accelerated(f64),
+    /// Replay without waiting.
+    Unlimited,
+}
+
+/// Replays the packets received in a recording. Sent packets are skipped.
+#[derive(Debug)]
+pub struct Replayer<R> {
+    recording: R,
+    speed: Speed,
+    start: Option<Instant>,
+    current: Vec<u8>,
+    position: usize,
+}
+
+impl<R: Read> Replayer<R> {
+    /// Replay a recording with the original timing.
+    pub fn new(mut recording: R) -> io::Result<Replayer<R>> {
+        read_header(&mut recording)?;
+        Ok(Replayer {
+            recording,
+            speed: Speed::Original,
+            start: None,
+            current: Vec::new(),
+            position: 0,
+        })
+    }
+
+    /// Set the replay speed. Fails if an accelerated speed is not a positive
+    /// factor.
+    pub fn with_speed(mut self, speed: Speed) -> io::Result<Replayer<R>> {
+        if let Speed::Accelerated(factor) = speed {
+            if factor.is_nan() || factor <= 0.0 {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "replay speed must be positive",
+                ));
+            }
+        }
+        self.speed = speed;
+        Ok(self)
+    }
+
+    /// Wait until the frame should be replayed.
+    fn wait_for(&mut self, frame: &Frame) {
+        let target = match self.speed {
+            Speed::Original => frame.timestamp,
+            // Very slow speeds wait forever instead of overflowing.
+            Speed::Accelerated(factor) => {
+                Duration::try_from_secs_f64(frame.timestamp.as_secs_f64() / factor)
+                    .unwrap_or(Duration::MAX)
+            }
+            Speed::Unlimited => return,
+        };
+        let start = *self.start.get_or_insert_with(Instant::now);
+        if let Some(remaining) = target.checked_sub(start.elapsed()) {
+            std::thread::sleep(remaining);
+        }
+    }
+
+    /// Load the next received packet. Returns false at the end of the
+    /// recording.
+    fn next_packet(&mut self) -> io::Result<bool> {
+        while let Some(frame) = Frame::read_from(&mut self.recording)? {
+            if frame.direction == Direction::Received {
+                self.wait_for(&frame);
+                self.current = frame.to_packet_bytes()?;
+                self.position = 0;
+                return Ok(true);
+            }
+        }
+        Ok(false)
+    }
+}
+
+impl<R: Read> Read for Replayer<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.position == self.current.len() && !self.next_packet()? {
+            return Ok(0);
+        }
+        let read = (&self.current[self.position..]).read(buf)?;
+        self.position += read;
+        Ok(read)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::packet::admin::{client_packets, server_packets, AdminRead, AdminWrite};
+    use crate::test_util::MockStream;
+
+    #[test]
+    fn record_and_replay() {
+        let stream = MockStream::new(&[7, 0, 126, 42, 0, 0, 0, 3, 0, 105]);
+        let mut recorder = Recorder::new(stream, Vec::new()).unwrap();
+        recorder
+            .write_packet(&client_packets::Ping { id: 42 })
+            .unwrap();
+        assert_eq!(
+            recorder.read_packet().unwrap(),
+            server_packets::Packet::Pong(server_packets::Pong { id: 42 })
+        );
+        assert_eq!(
+            recorder.read_packet().unwrap(),
+            server_packets::Packet::Newgame
+        );
+        let (_, recording) = recorder.into_inner();
+
+        let frames = read_recording(&recording[..]).unwrap();
+        assert_eq!(frames.len(), 3);
+        assert_eq!(frames[0].direction, Direction::Sent);
+        assert_eq!(frames[0].packet_type, 7);
+
+        let mut replayer = Replayer::new(&recording[..])
+            .unwrap()
+            .with_speed(Speed::Unlimited)
+            .unwrap();
+        assert_eq!(
+            replayer.read_packet().unwrap(),
+            server_packets::Packet::Pong(server_packets::Pong { id: 42 })
+        );
+        assert_eq!(
+            replayer.read_packet().unwrap(),
+            server_packets::Packet::Newgame
+        );
+        assert!(replayer.read_packet().is_err());
+    }
+
+    #[test]
+    fn invalid_header() {
+        assert!(Replayer::new(&b"OTTDREX\x01"[..]).is_err());
+    }
+
+    #[test]
+    fn invalid_speed() {
+        for &factor in &[0.0, -2.0, f64::NAN] {
+            assert!(Replayer::new(&b"OTTDREC\x01"[..])
+                .unwrap()
+                .with_speed(Speed::Accelerated(factor))
+                .is_err());
+        }
+    }
+
+    #[test]
+    fn oversized_frame() {
+        let frame = Frame {
+            timestamp: Duration::from_secs(1),
+            direction: Direction::Received,
+            packet_type: 120,
+            buffer: vec![0; MAX_PACKET_SIZE],
+        };
+        assert!(frame.to_packet_bytes().is_err());
+        assert!(frame.write_to(Vec::new()).is_err());
+
+        let mut recording = b"OTTDREC\x01".to_vec();
+        recording.extend_from_slice(&[0; 8]);
+        recording.extend_from_slice(&[0, 120, 0xff, 0xff]);
+        recording.resize(recording.len() + 0xffff, 0);
+        let err = read_recording(&recording[..]).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn truncated_frame() {
+        let mut recording = b"OTTDREC\x01".to_vec();
+        Frame {
+            timestamp: Duration::from_secs(1),
+            direction: Direction::Received,
+            packet_type: 126,
+            buffer: vec![42, 0, 0, 0],
+        }
+        .write_to(&mut recording)
+        .unwrap();
+        assert_eq!(read_recording(&recording[..]).unwrap().len(), 1);
+        // Inside the timestamp, the rest of the header and the buffer.
+        for &length in &[11, 17, 22] {
+            let err = read_recording(&recording[..length]).unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        }
+    }
+
+    #[test]
+    fn invalid_packet_length() {
+        let mut assembler = FrameAssembler::default();
+        assert_eq!(assembler.push(&[3, 0]).unwrap(), Vec::new());
+        assert_eq!(assembler.push(&[105, 1]).unwrap(), vec![(105, Vec::new())]);
+        let err = assembler.push(&[0, 105]).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        // The stream stays unusable.
+        assert!(assembler.push(&[3, 0, 105]).is_err());
+    }
+}
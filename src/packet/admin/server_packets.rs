@@ -54,13 +54,27 @@ pub struct Welcome {
     pub map_height: u16,
 }
 
+impl Welcome {
+    /// The size of the map.
+    pub fn map_size(&self) -> types::MapSize {
+        types::MapSize::new(u32::from(self.map_width), u32::from(self.map_height))
+    }
+}
+
 /// Send the current date of the game.
 #[derive(Serialize, Deserialize, Debug, Eq, PartialEq, Clone)]
 pub struct Date {
-    /// Current game date.
+    /// Current game date. Since OpenTTD 14 this is the calendar date.
     pub date: types::Date,
 }
 
+impl Date {
+    /// The current date of the calendar.
+    pub fn calendar_date(&self) -> types::CalendarDate {
+        types::CalendarDate(self.date)
+    }
+}
+
 /// Notification of a new client.
 #[derive(Serialize, Deserialize, Debug, Eq, PartialEq, Clone)]
 pub struct ClientJoin {
@@ -79,8 +93,9 @@ pub struct ClientInfo {
     pub name: String,
     /// Language of the client.
     pub language: u8,
-    /// Date the client joined the game.
-    pub date_joined: types::Date,
+    /// Economy date the client joined the game. Before OpenTTD 14 this is
+    /// the same as the calendar date.
+    pub date_joined: types::EconomyDate,
     /// ID of the company the client is playing as (255 for spectators).
     pub company_id: u8,
 }
@@ -377,6 +392,7 @@ pub enum Packet {
     RconEnd(RconEnd),
     /// Send a ping-reply (pong) to the admin that sent us the ping packet.
     Pong(Pong),
+    /// A packet of a type this crate does not know.
     UnknownPacket {
         packet_type: u8,
         buffer: Vec<u8>,
@@ -21,7 +21,30 @@ fn test_company_economy_deser() {
         delivered_previous: 0,
     };
     assert_eq!(
-        from_bytes::<server_packets::CompanyEconomy>(&buffer).unwrap(),
+        from_bytes::<server_packets::CompanyEconomy>(buffer).unwrap(),
         expected
     );
 }
+
+#[test]
+fn test_replay_fixture() {
+    use super::recording::{Replayer, Speed};
+    use super::AdminRead;
+    use crate::types;
+
+    let recording: &[u8] = include_bytes!("fixtures/company_economy.rec");
+    let mut replayer = Replayer::new(recording)
+        .unwrap()
+        .with_speed(Speed::Unlimited)
+        .unwrap();
+    match replayer.read_packet().unwrap() {
+        server_packets::Packet::CompanyEconomy(economy) => assert_eq!(economy.money, 99642),
+        packet => panic!("unexpected packet {:?}", packet),
+    }
+    assert_eq!(
+        replayer.read_packet().unwrap(),
+        server_packets::Packet::Date(server_packets::Date {
+            date: types::Date::from_openttd_date(712_000).unwrap()
+        })
+    );
+}
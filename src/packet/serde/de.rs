@@ -15,7 +15,7 @@ impl<'de> Deserializer<'de> {
         if let Some(end) = self.input.iter().position(|&b| b == 0) {
             let string = &self.input[0..end];
             self.input = &self.input[end + 1..];
-            let string = &std::str::from_utf8(&string)?;
+            let string = &std::str::from_utf8(string)?;
             Ok(string)
         } else {
             Err(Error::EndlessString)
@@ -66,9 +66,13 @@ impl<T: std::io::Read> PacketRead for T {
     }
 }
 
-impl<'de, 'a> de::Deserializer<'de> for &'a mut Deserializer<'de> {
+impl<'de> de::Deserializer<'de> for &mut Deserializer<'de> {
     type Error = Error;
 
+    fn is_human_readable(&self) -> bool {
+        false
+    }
+
     fn deserialize_any<V>(self, _visitor: V) -> Result<V::Value>
     where
         V: Visitor<'de>,
@@ -364,7 +368,7 @@ mod test {
             c: u32,
             d: bool
         }
-        let mut input: &[u8] = &vec![
+        let mut input: &[u8] = &[
             11, 0, // Length
             10, // PACKET_TYPE
             1, // a
@@ -384,7 +388,7 @@ mod test {
         struct VecStruct {
             item: Vec<u8>
         }
-        let mut input: &[u8] = &vec![
+        let mut input: &[u8] = &[
             14, 0, // Length
             0xFF, // Packet type
             1, 0, // boolean, item
@@ -411,7 +415,7 @@ mod test {
 
         #[test]
         fn test_some_ser() {
-            let mut input: &[u8] = &vec![
+            let mut input: &[u8] = &[
                 5, 0, // Length
                 3, // PACKET_TYPE
                 10, // mandatory
@@ -425,7 +429,7 @@ mod test {
 
         #[test]
         fn test_none_ser() {
-            let mut input: &[u8] = &vec![
+            let mut input: &[u8] = &[
                 4, 0, // Length
                 3, // PACKET_TYPE
                 10 // mandatory
@@ -34,22 +34,26 @@ impl de::Error for Error {
 
 impl Display for Error {
     fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
-        formatter.write_str(std::error::Error::description(self))
+        match *self {
+            Error::Message(ref msg) => formatter.write_str(msg),
+            Error::TrailingCharacters => formatter.write_str("the input has trailing characters"),
+            Error::IoError(ref err) => err.fmt(formatter),
+            Error::Utf8Error(ref err) => err.fmt(formatter),
+            Error::NotSupported => formatter.write_str("the data type is not supported"),
+            Error::EndlessString => formatter.write_str("string doesn't end with \0"),
+            Error::InvalidBool => formatter.write_str("invalid bool"),
+            Error::InvalidOption => formatter.write_str("invalid option"),
+            Error::InvalidChar => formatter.write_str("invalid char"),
+        }
     }
 }
 
 impl std::error::Error for Error {
-    fn description(&self) -> &str {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
         match *self {
-            Error::Message(ref msg) => msg,
-            Error::TrailingCharacters => "the input has trailing characters",
-            Error::IoError(ref err) => err.description(),
-            Error::Utf8Error(ref err) => err.description(),
-            Error::NotSupported => "the data type is not supported",
-            Error::EndlessString => "string doesn't end with \0",
-            Error::InvalidBool => "invalid bool",
-            Error::InvalidOption => "invalid option",
-            Error::InvalidChar => "invalid char",
+            Error::IoError(ref err) => Some(err),
+            Error::Utf8Error(ref err) => Some(err),
+            _ => None,
         }
     }
 }
@@ -44,7 +44,7 @@ impl<T: WritablePacket, W: std::io::Write> PacketWrite<T> for W {
     }
 }
 
-impl<'a> ser::Serializer for &'a mut Serializer {
+impl ser::Serializer for &mut Serializer {
     type Ok = ();
     type Error = Error;
 
@@ -57,6 +57,10 @@ impl<'a> ser::Serializer for &'a mut Serializer {
     type SerializeMap = Impossible<(), Error>;
     type SerializeStructVariant = Impossible<(), Error>;
 
+    fn is_human_readable(&self) -> bool {
+        false
+    }
+
     fn serialize_bool(self, v: bool) -> Result<()> {
         self.check_can_serialize_more()?;
         self.output.write_u8(if v { 1 } else { 0 })?;
@@ -253,7 +257,7 @@ impl<'a> ser::Serializer for &'a mut Serializer {
 }
 
 /// A sequence is serialized by prefixing each element with `true` and ending with `false`.
-impl<'a> ser::SerializeSeq for &'a mut Serializer {
+impl ser::SerializeSeq for &mut Serializer {
     // Must match the `Ok` type of the serializer.
     type Ok = ();
     // Must match the `Error` type of the serializer.
@@ -275,7 +279,7 @@ impl<'a> ser::SerializeSeq for &'a mut Serializer {
 }
 
 /// To serialize a tuple, simply output the elements since the size is known.
-impl<'a> ser::SerializeTuple for &'a mut Serializer {
+impl ser::SerializeTuple for &mut Serializer {
     type Ok = ();
     type Error = Error;
 
@@ -292,7 +296,7 @@ impl<'a> ser::SerializeTuple for &'a mut Serializer {
 }
 
 /// Implement structs by serializing the contents in order.
-impl<'a> ser::SerializeStruct for &'a mut Serializer {
+impl ser::SerializeStruct for &mut Serializer {
     type Ok = ();
     type Error = Error;
 
@@ -309,7 +313,7 @@ impl<'a> ser::SerializeStruct for &'a mut Serializer {
 }
 
 /// To serialize a tuple struct, simply output the elements since the size is known.
-impl<'a> ser::SerializeTupleStruct for &'a mut Serializer {
+impl ser::SerializeTupleStruct for &mut Serializer {
     type Ok = ();
     type Error = Error;
 
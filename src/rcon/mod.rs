@@ -0,0 +1,344 @@
+//! Building console commands and collecting their output.
+//!
+//! [`Command`] builds the command strings for common console commands,
+//! quoting arguments where needed. Company ids are given as in the admin
+//! packets, starting at 0, and converted to the numbers the console uses.
+//! [`Response`] collects the output of a command until the server signals
+//! the end of it, and the parsers in [`output`] turn the output of listing
+//! commands into typed values.
+//!
+//! ```
+//! use rust_openttd_admin::rcon::{Command, Target};
+//!
+//! let command = Command::kick(Target::Client(3), Some("Please read the \"rules\""));
+//! assert_eq!(command.as_str(), r#"kick 3 "Please read the \"rules\"""#);
+//! ```
+
+pub mod output;
+
+use crate::clients::SPECTATOR;
+use crate::connection::Connection;
+use crate::packet::admin::server_packets::{self, Packet};
+use crate::packet::admin::{client_packets, Result};
+use std::fmt::{self, Display};
+use std::io::{Read, Write};
+
+/// The colour of output reporting an error (CC_ERROR).
+pub const ERROR_COLOUR: u16 = 3;
+
+/// The client or address a kick or ban applies to.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Target<'a> {
+    Client(u32),
+    /// A network address. Applies to all clients from that address.
+    Address(&'a str),
+}
+
+/// A subcommand of the `content` command.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Content<'a> {
+    /// Download the list of content from the content server.
+    Update,
+    /// Select all content that has an upgrade available.
+    Upgrade,
+    /// Select the content with the given id, or list the selected content.
+    Select(Option<u32>),
+    /// Unselect the content with the given id, or all content.
+    Unselect(Option<u32>),
+    /// List the content, optionally filtered by name.
+    State(Option<&'a str>),
+    /// Download the selected content.
+    Download,
+}
+
+/// A console command.
+#[derive(Clone, Debug, Eq, PartialEq, Hash)]
+pub struct Command(String);
+
+impl Command {
+    /// A command from a name and its arguments, quoting them where needed.
+    pub fn new(name: &str, arguments: &[&str]) -> Command {
+        let mut command = name.to_string();
+        for argument in arguments {
+            command.push(' ');
+            command.push_str(&quote(argument));
+        }
+        Command(command)
+    }
+
+    /// A command that is executed exactly as given.
+    pub fn raw(command: &str) -> Command {
+        Command(command.to_string())
+    }
+
+    pub fn kick(target: Target, reason: Option<&str>) -> Command {
+        Command::with_reason("kick", target, reason)
+    }
+
+    /// Ban the address of a client, or an address directly.
+    pub fn ban(target: Target, reason: Option<&str>) -> Command {
+        Command::with_reason("ban", target, reason)
+    }
+
+    /// Remove a ban by address.
+    pub fn unban(address: &str) -> Command {
+        Command::new("unban", &[address])
+    }
+
+    /// Remove a ban by its number in the ban list, starting at 1.
+    pub fn unban_index(index: u32) -> Command {
+        Command::new("unban", &[&index.to_string()])
+    }
+
+    pub fn banlist() -> Command {
+        Command::new("banlist", &[])
+    }
+
+    /// Remove a company. The company should not have any clients.
+    pub fn reset_company(company_id: u8) -> Command {
+        Command::new("reset_company", &[&console_company(company_id)])
+    }
+
+    /// Move a client to a company, or to the spectators when `company_id` is
+    /// [`SPECTATOR`].
+    pub fn move_client(client_id: u32, company_id: u8) -> Command {
+        Command::new(
+            "move",
+            &[&client_id.to_string(), &console_company(company_id)],
+        )
+    }
+
+    pub fn pause() -> Command {
+        Command::new("pause", &[])
+    }
+
+    pub fn unpause() -> Command {
+        Command::new("unpause", &[])
+    }
+
+    /// Print the value of a setting.
+    pub fn setting(name: &str) -> Command {
+        Command::new("setting", &[name])
+    }
+
+    /// Change the value of a setting.
+    pub fn set_setting(name: &str, value: &str) -> Command {
+        Command::new("setting", &[name, value])
+    }
+
+    /// Save the game under the given file name.
+    pub fn save(file_name: &str) -> Command {
+        Command::new("save", &[file_name])
+    }
+
+    /// Start a new game, with a random seed unless one is given.
+    pub fn newgame(seed: Option<u32>) -> Command {
+        match seed {
+            Some(seed) => Command::new("newgame", &[&seed.to_string()]),
+            None => Command::new("newgame", &[]),
+        }
+    }
+
+    /// Restart the game with the same settings.
+    pub fn restart() -> Command {
+        Command::new("restart", &[])
+    }
+
+    /// List the connected clients.
+    pub fn clients() -> Command {
+        Command::new("clients", &[])
+    }
+
+    /// List the companies.
+    pub fn companies() -> Command {
+        Command::new("companies", &[])
+    }
+
+    pub fn content(content: Content) -> Command {
+        let id = |id: Option<u32>| id.map(|id| id.to_string());
+        let (subcommand, argument) = match content {
+            Content::Update => ("update", None),
+            Content::Upgrade => ("upgrade", None),
+            Content::Select(select) => ("select", id(select)),
+            Content::Unselect(unselect) => (
+                "unselect",
+                Some(id(unselect).unwrap_or_else(|| "all".to_string())),
+            ),
+            Content::State(filter) => ("state", filter.map(str::to_string)),
+            Content::Download => ("download", None),
+        };
+        match argument {
+            Some(argument) => Command::new("content", &[subcommand, &argument]),
+            None => Command::new("content", &[subcommand]),
+        }
+    }
+
+    fn with_reason(name: &str, target: Target, reason: Option<&str>) -> Command {
+        let target = match target {
+            Target::Client(client_id) => client_id.to_string(),
+            Target::Address(address) => address.to_string(),
+        };
+        match reason {
+            Some(reason) => Command::new(name, &[&target, reason]),
+            None => Command::new(name, &[&target]),
+        }
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+
+    /// The packet executing this command.
+    pub fn packet(&self) -> client_packets::Rcon<'_> {
+        client_packets::Rcon { command: &self.0 }
+    }
+}
+
+impl Display for Command {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+/// The console numbers companies from 1, and uses 255 for spectators.
+fn console_company(company_id: u8) -> String {
+    if company_id == SPECTATOR {
+        company_id.to_string()
+    } else {
+        (u32::from(company_id) + 1).to_string()
+    }
+}
+
+/// Quote an argument if it is empty or contains spaces or quotes.
+///
+/// The console only treats a backslash before a quote as an escape, and a
+/// quote merely groups the characters up to the next quote into the same
+/// argument. Trailing backslashes are therefore placed after the closing
+/// quote, where they cannot escape it.
+fn quote(argument: &str) -> String {
+    if !argument.is_empty() && !argument.contains(|c: char| c.is_whitespace() || c == '"') {
+        return argument.to_string();
+    }
+    let quoted = argument.trim_end_matches('\\');
+    format!(
+        "\"{}\"{}",
+        quoted.replace('"', "\\\""),
+        &argument[quoted.len()..]
+    )
+}
+
+/// The output of a console command.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct Response {
+    pub output: Vec<server_packets::Rcon>,
+    finished: bool,
+}
+
+impl Response {
+    pub fn new() -> Response {
+        Response::default()
+    }
+
+    /// Add a packet to the response. Returns true once the server has sent
+    /// all output. Packets other than rcon output are ignored.
+    pub fn push(&mut self, packet: &Packet) -> bool {
+        match packet {
+            Packet::Rcon(rcon) => self.output.push(rcon.clone()),
+            Packet::RconEnd(_) => self.finished = true,
+            _ => {}
+        }
+        self.finished
+    }
+
+    /// Returns true once the server has sent all output.
+    pub fn is_finished(&self) -> bool {
+        self.finished
+    }
+
+    /// The lines of output.
+    pub fn lines(&self) -> impl Iterator<Item = &str> {
+        self.output.iter().map(|rcon| rcon.output.as_str())
+    }
+
+    /// Returns true if the server reported an error.
+    pub fn is_error(&self) -> bool {
+        self.output.iter().any(|rcon| rcon.color == ERROR_COLOUR)
+    }
+}
+
+/// Execute a command and wait for its output. Other packets received in the
+/// meantime are passed to `other`.
+pub fn execute<S: Read + Write, F: FnMut(Packet)>(
+    connection: &mut Connection<S>,
+    command: &Command,
+    mut other: F,
+) -> Result<Response> {
+    connection.send(&command.packet())?;
+    let mut response = Response::new();
+    loop {
+        let packet = connection.read_packet()?;
+        match packet {
+            Packet::Rcon(_) | Packet::RconEnd(_) => {
+                if response.push(&packet) {
+                    return Ok(response);
+                }
+            }
+            packet => other(packet),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn build_commands() {
+        assert_eq!(Command::kick(Target::Client(5), None).as_str(), "kick 5");
+        assert_eq!(
+            Command::ban(Target::Address("192.0.2.1"), Some("griefing")).as_str(),
+            "ban 192.0.2.1 griefing"
+        );
+        assert_eq!(
+            Command::kick(Target::Client(5), Some("say \"hi\"")).as_str(),
+            r#"kick 5 "say \"hi\"""#
+        );
+        assert_eq!(Command::reset_company(0).as_str(), "reset_company 1");
+        assert_eq!(Command::move_client(3, SPECTATOR).as_str(), "move 3 255");
+        assert_eq!(
+            Command::set_setting("difficulty.max_loan", "500000").as_str(),
+            "setting difficulty.max_loan 500000"
+        );
+        assert_eq!(Command::save("My Game").as_str(), "save \"My Game\"");
+        assert_eq!(
+            Command::save(r"C:\My Games\").as_str(),
+            r#"save "C:\My Games"\"#
+        );
+        assert_eq!(Command::save(r"C:\saves\").as_str(), r"save C:\saves\");
+        assert_eq!(Command::new("echo", &[""]).as_str(), "echo \"\"");
+        assert_eq!(
+            Command::content(Content::Unselect(None)).as_str(),
+            "content unselect all"
+        );
+    }
+
+    #[test]
+    fn collect_response() {
+        let mut response = Response::new();
+        assert!(!response.push(&Packet::Rcon(server_packets::Rcon {
+            color: ERROR_COLOUR,
+            output: "ERROR: Invalid client".to_string(),
+        })));
+        assert!(!response.push(&Packet::Date(server_packets::Date {
+            date: crate::types::Date::from_ymd(1950, 0, 1).unwrap(),
+        })));
+        assert!(response.push(&Packet::RconEnd(server_packets::RconEnd {
+            command: "kick 99".to_string(),
+        })));
+        assert!(response.is_error());
+        assert_eq!(
+            response.lines().collect::<Vec<_>>(),
+            vec!["ERROR: Invalid client"]
+        );
+    }
+}
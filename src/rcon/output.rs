@@ -0,0 +1,284 @@
+//! Parsers for the output of the `clients`, `companies`, `banlist` and
+//! `setting` console commands. The parsers take the lines of a
+//! [`Response`](super::Response) and skip lines they do not recognize, such
+//! as headers. Company ids are converted to start at 0, as in the admin
+//! packets.
+
+use crate::clients::SPECTATOR;
+use lazy_static::lazy_static;
+use regex::Regex;
+
+lazy_static! {
+    static ref CLIENT: Regex =
+        Regex::new(r"^Client #(\d+)  name: '(.*)'  company: (\d+)  IP: (.*)$").unwrap();
+    static ref COMPANY: Regex = Regex::new(
+        r"^#:(\d+)\(([^)]*)\) Company Name: '(.*)'  Year Founded: (\d+)  Money: (-?\d+)  Loan: (-?\d+)  Value: (-?\d+)  \(T:(\d+), R:(\d+), P:(\d+), S:(\d+)\) (protected|unprotected)$"
+    )
+    .unwrap();
+    static ref BAN: Regex = Regex::new(r"^\s*(\d+)\) (.*)$").unwrap();
+    static ref SETTING: Regex = Regex::new(
+        r"^Current value for '(.*)' is:? '(.*)'(?: \(min: (?:\(0\) )?(-?\d+), max: (-?\d+)\))?\.?$"
+    )
+    .unwrap();
+}
+
+/// A client as listed by the `clients` command.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Client {
+    pub id: u32,
+    pub name: String,
+    /// The company of the client, or `None` for spectators.
+    pub company_id: Option<u8>,
+    /// The network address, or `None` for the server itself.
+    pub address: Option<String>,
+}
+
+/// A company as listed by the `companies` command.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Company {
+    pub id: u8,
+    /// The name of the company colour, e.g. `Dark Blue`.
+    pub colour: String,
+    pub name: String,
+    pub year_founded: u32,
+    pub money: i64,
+    pub loan: i64,
+    pub value: i64,
+    pub trains: u32,
+    pub road_vehicles: u32,
+    pub planes: u32,
+    pub ships: u32,
+    /// The company is password protected.
+    pub protected: bool,
+}
+
+/// An entry of the ban list.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Ban {
+    /// The number of the entry, starting at 1, as used by `unban`.
+    pub index: u32,
+    pub address: String,
+}
+
+/// The value of a setting.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum SettingValue {
+    Bool(bool),
+    Integer(i64),
+    /// Text, or the name of one of multiple options.
+    Text(String),
+}
+
+/// A setting as printed by the `setting` command.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Setting {
+    pub name: String,
+    pub value: SettingValue,
+    /// The range of numeric settings.
+    pub min: Option<i64>,
+    pub max: Option<i64>,
+}
+
+/// Parse the output of the `clients` command.
+pub fn parse_clients<'a, I: IntoIterator<Item = &'a str>>(lines: I) -> Vec<Client> {
+    lines
+        .into_iter()
+        .filter_map(|line| {
+            let captures = CLIENT.captures(line)?;
+            let company: u8 = captures[3].parse().ok()?;
+            // `NetworkPrintClients` only adds 1 to valid companies and prints
+            // other values, such as the spectator id, as they are. So 0 is not
+            // a company; it is treated as none instead of underflowing.
+            Some(Client {
+                id: captures[1].parse().ok()?,
+                name: captures[2].to_string(),
+                company_id: if company == SPECTATOR || company == 0 {
+                    None
+                } else {
+                    Some(company - 1)
+                },
+                address: match &captures[4] {
+                    "server" => None,
+                    address => Some(address.to_string()),
+                },
+            })
+        })
+        .collect()
+}
+
+/// Parse the output of the `companies` command.
+pub fn parse_companies<'a, I: IntoIterator<Item = &'a str>>(lines: I) -> Vec<Company> {
+    lines
+        .into_iter()
+        .filter_map(|line| {
+            let captures = COMPANY.captures(line)?;
+            let id: u8 = captures[1].parse().ok()?;
+            Some(Company {
+                id: id.checked_sub(1)?,
+                colour: captures[2].to_string(),
+                name: captures[3].to_string(),
+                year_founded: captures[4].parse().ok()?,
+                money: captures[5].parse().ok()?,
+                loan: captures[6].parse().ok()?,
+                value: captures[7].parse().ok()?,
+                trains: captures[8].parse().ok()?,
+                road_vehicles: captures[9].parse().ok()?,
+                planes: captures[10].parse().ok()?,
+                ships: captures[11].parse().ok()?,
+                protected: &captures[12] == "protected",
+            })
+        })
+        .collect()
+}
+
+/// Parse the output of the `banlist` command.
+pub fn parse_banlist<'a, I: IntoIterator<Item = &'a str>>(lines: I) -> Vec<Ban> {
+    lines
+        .into_iter()
+        .filter_map(|line| {
+            let captures = BAN.captures(line)?;
+            Some(Ban {
+                index: captures[1].parse().ok()?,
+                address: captures[2].trim().to_string(),
+            })
+        })
+        .collect()
+}
+
+/// Parse the output of the `setting` command for a single setting. Returns
+/// `None` if the output does not contain a value, for example because the
+/// setting does not exist.
+pub fn parse_setting<'a, I: IntoIterator<Item = &'a str>>(lines: I) -> Option<Setting> {
+    lines.into_iter().find_map(|line| {
+        let captures = SETTING.captures(line)?;
+        let min = captures.get(3).and_then(|min| min.as_str().parse().ok());
+        let max = captures.get(4).and_then(|max| max.as_str().parse().ok());
+        let value = &captures[2];
+        let value = match (value, min) {
+            ("on", _) | ("true", _) => SettingValue::Bool(true),
+            ("off", _) | ("false", _) => SettingValue::Bool(false),
+            (value, Some(_)) => value
+                .parse()
+                .map(SettingValue::Integer)
+                .unwrap_or_else(|_| SettingValue::Text(value.to_string())),
+            (value, None) => SettingValue::Text(value.to_string()),
+        };
+        Some(Setting {
+            name: captures[1].to_string(),
+            value,
+            min,
+            max,
+        })
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn clients_fixture() {
+        let clients = parse_clients(include_str!("fixtures/handwritten/clients.txt").lines());
+        assert_eq!(clients.len(), 4);
+        assert_eq!(
+            clients[0],
+            Client {
+                id: 1,
+                name: "Server".to_string(),
+                company_id: None,
+                address: None,
+            }
+        );
+        assert_eq!(clients[1].company_id, Some(0));
+        assert_eq!(clients[2].name, "Bob 'the builder'");
+        assert_eq!(clients[2].address.as_deref(), Some("2001:db8::7"));
+        assert_eq!(clients[3].company_id, None);
+    }
+
+    #[test]
+    fn companies_fixture() {
+        let companies = parse_companies(include_str!("fixtures/handwritten/companies.txt").lines());
+        assert_eq!(
+            companies,
+            vec![
+                Company {
+                    id: 0,
+                    colour: "Dark Blue".to_string(),
+                    name: "Alice Transport".to_string(),
+                    year_founded: 1950,
+                    money: 1_584_236,
+                    loan: 300_000,
+                    value: 2_310_459,
+                    trains: 12,
+                    road_vehicles: 4,
+                    planes: 0,
+                    ships: 1,
+                    protected: true,
+                },
+                Company {
+                    id: 2,
+                    colour: "Light Blue".to_string(),
+                    name: "Bob & Co.".to_string(),
+                    year_founded: 1962,
+                    money: -23871,
+                    loan: 500_000,
+                    value: 120_934,
+                    trains: 0,
+                    road_vehicles: 25,
+                    planes: 2,
+                    ships: 0,
+                    protected: false,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn banlist_fixture() {
+        let bans = parse_banlist(include_str!("fixtures/handwritten/banlist.txt").lines());
+        assert_eq!(
+            bans,
+            vec![
+                Ban {
+                    index: 1,
+                    address: "203.0.113.9".to_string()
+                },
+                Ban {
+                    index: 2,
+                    address: "2001:db8::dead".to_string()
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn setting_fixture() {
+        let settings: Vec<Setting> = include_str!("fixtures/handwritten/setting.txt")
+            .lines()
+            .filter_map(|line| parse_setting(Some(line)))
+            .collect();
+        assert_eq!(settings.len(), 5);
+        assert_eq!(
+            settings[0],
+            Setting {
+                name: "difficulty.max_loan".to_string(),
+                value: SettingValue::Integer(300_000),
+                min: Some(100_000),
+                max: Some(2_000_000_000),
+            }
+        );
+        assert_eq!(settings[1].value, SettingValue::Integer(12));
+        assert_eq!(settings[2].value, SettingValue::Bool(true));
+        assert_eq!(
+            settings[3],
+            Setting {
+                name: "network.server_name".to_string(),
+                value: SettingValue::Text("My \"Best\" Server".to_string()),
+                min: None,
+                max: None,
+            }
+        );
+        assert_eq!(settings[4].min, Some(0));
+        assert_eq!(parse_setting(vec!["ERROR: setting not found"]), None);
+    }
+}
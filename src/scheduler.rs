@@ -0,0 +1,335 @@
+//! Running tasks at game dates. A [`Scheduler`] is fed the dates of the
+//! game and runs its tasks once their date is reached: at a single date, or
+//! at the start of every month, quarter or year.
+//!
+//! The server may skip dates, for example when only monthly `Date` updates
+//! are requested or when the game is fast-forwarding. A task runs when its
+//! date lies between the previous and the current date, so no date is
+//! missed; a recurring task runs only once for each update, even if more
+//! than one of its dates was skipped. Recurring dates before the first
+//! update are not run, while a task at a single date that has already passed,
+//! for example because it was added late, runs at the next update.
+//!
+//! ```no_run
+//! # use rust_openttd_admin::rcon::Command;
+//! # use rust_openttd_admin::scheduler::{Action, Schedule, Scheduler};
+//! let mut scheduler = Scheduler::new();
+//! scheduler.add(Schedule::Monthly, Action::Rcon(Command::save("autosave")));
+//! scheduler.add(
+//!     Schedule::At("2000-01-01".parse().unwrap()),
+//!     Action::Chat("The competition has ended!".to_string()),
+//! );
+//! ```
+
+use crate::handler::{AdminHandler, Context};
+use crate::packet::admin::Result;
+use crate::rcon::Command;
+use crate::types::Date;
+use std::fmt;
+
+/// When a task runs.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Schedule {
+    /// Once, at the given date.
+    At(Date),
+    /// On the first day of every month.
+    Monthly,
+    /// On the first day of January, April, July and October.
+    Quarterly,
+    /// On the first day of every year.
+    Yearly,
+}
+
+impl Schedule {
+    /// The first date after `date` at which the schedule is due. A single
+    /// date stays due once it has passed, until its task ran.
+    fn next_due(self, date: Date) -> Option<Date> {
+        let (year, month, _) = date.to_ymd();
+        let month_start = Date::from_ymd(year, month, 1).ok()?;
+        match self {
+            Schedule::At(at) => Some(at),
+            Schedule::Monthly => month_start.add_months(1).ok(),
+            Schedule::Quarterly => month_start.add_months(3 - month as i32 % 3).ok(),
+            Schedule::Yearly => Date::from_ymd(year + 1, 0, 1).ok(),
+        }
+    }
+
+    /// Returns true if the schedule is due on `date`, without looking at
+    /// earlier dates.
+    fn matches(self, date: Date) -> bool {
+        match self {
+            Schedule::At(at) => at <= date,
+            Schedule::Monthly => date.is_month_start(),
+            Schedule::Quarterly => date.is_quarter_start(),
+            Schedule::Yearly => date.to_ymd() == (date.year(), 0, 1),
+        }
+    }
+
+    /// Returns true if the schedule has a date after `previous`, up to and
+    /// including `date`. A single date that has passed is always due, so
+    /// its task should only be run once.
+    pub(crate) fn is_due(self, previous: Option<Date>, date: Date) -> bool {
+        match previous {
+            Some(previous) if previous == date && self.is_recurring() => false,
+            Some(previous) if previous < date => self
+                .next_due(previous)
+                .map(|next| next <= date)
+                .unwrap_or(false),
+            // The first date, or another game was loaded.
+            _ => self.matches(date),
+        }
+    }
+
+    pub(crate) fn is_recurring(self) -> bool {
+        !matches!(self, Schedule::At(_))
+    }
+}
+
+type Callback = Box<dyn FnMut(&mut Context, Date) -> Result<()>>;
+
+/// What a task does.
+pub enum Action {
+    /// Broadcast a chat message.
+    Chat(String),
+    /// Execute a console command.
+    Rcon(Command),
+    /// Call a function with the current date.
+    Callback(Callback),
+}
+
+impl Action {
+    /// An action calling `callback` with the current date.
+    pub fn callback<F>(callback: F) -> Action
+    where
+        F: FnMut(&mut Context, Date) -> Result<()> + 'static,
+    {
+        Action::Callback(Box::new(callback))
+    }
+
+    fn run(&mut self, ctx: &mut Context, date: Date) -> Result<()> {
+        match self {
+            Action::Chat(message) => ctx.say(message),
+            Action::Rcon(command) => ctx.send(&command.packet()),
+            Action::Callback(callback) => callback(ctx, date),
+        }
+    }
+}
+
+impl fmt::Debug for Action {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Action::Chat(message) => f.debug_tuple("Chat").field(message).finish(),
+            Action::Rcon(command) => f.debug_tuple("Rcon").field(command).finish(),
+            Action::Callback(_) => f.write_str("Callback"),
+        }
+    }
+}
+
+/// Identifies a task of a [`Scheduler`].
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Ord, PartialOrd, Hash)]
+pub struct TaskId(u64);
+
+#[derive(Debug)]
+struct Task {
+    id: TaskId,
+    schedule: Schedule,
+    action: Action,
+}
+
+/// Runs tasks at game dates.
+#[derive(Debug, Default)]
+pub struct Scheduler {
+    tasks: Vec<Task>,
+    next_id: u64,
+    date: Option<Date>,
+}
+
+impl Scheduler {
+    pub fn new() -> Scheduler {
+        Scheduler::default()
+    }
+
+    /// Add a task. Tasks that are due at the same date run in the order they
+    /// were added.
+    pub fn add(&mut self, schedule: Schedule, action: Action) -> TaskId {
+        let id = TaskId(self.next_id);
+        self.next_id += 1;
+        self.tasks.push(Task {
+            id,
+            schedule,
+            action,
+        });
+        id
+    }
+
+    /// Remove a task. Returns false if it does not exist, for example because
+    /// it was scheduled at a single date that has passed.
+    pub fn cancel(&mut self, id: TaskId) -> bool {
+        let len = self.tasks.len();
+        self.tasks.retain(|task| task.id != id);
+        self.tasks.len() != len
+    }
+
+    /// The number of tasks that are still scheduled.
+    pub fn len(&self) -> usize {
+        self.tasks.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tasks.is_empty()
+    }
+
+    /// The latest date the scheduler was given.
+    pub fn date(&self) -> Option<Date> {
+        self.date
+    }
+
+    /// Run the tasks that are due at `date`. Tasks scheduled at a single date
+    /// are removed once they ran. If a task fails, the other tasks still run
+    /// and the first error is returned.
+    pub fn run_due(&mut self, ctx: &mut Context, date: Date) -> Result<()> {
+        let previous = self.date.filter(|&previous| previous <= date);
+        self.date = Some(date);
+        let mut first_error = None;
+        let mut index = 0;
+        while index < self.tasks.len() {
+            let task = &mut self.tasks[index];
+            if !task.schedule.is_due(previous, date) {
+                index += 1;
+                continue;
+            }
+            let recurring = task.schedule.is_recurring();
+            let result = task.action.run(ctx, date);
+            if recurring {
+                index += 1;
+            } else {
+                self.tasks.remove(index);
+            }
+            if let Err(err) = result {
+                first_error.get_or_insert(err);
+            }
+        }
+        first_error.map_or(Ok(()), Err)
+    }
+
+    /// Forget the latest date, for example when a new game starts.
+    pub fn reset(&mut self) {
+        self.date = None;
+    }
+}
+
+impl AdminHandler for Scheduler {
+    fn on_date(&mut self, ctx: &mut Context, date: Date) -> Result<()> {
+        self.run_due(ctx, date)
+    }
+
+    fn on_newgame(&mut self, _ctx: &mut Context) -> Result<()> {
+        self.reset();
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    #[test]
+    fn run_tasks() {
+        let runs = Rc::new(RefCell::new(Vec::new()));
+        let mut scheduler = Scheduler::new();
+        for (name, schedule) in &[
+            ("monthly", Schedule::Monthly),
+            ("quarterly", Schedule::Quarterly),
+            ("yearly", Schedule::Yearly),
+            ("end", Schedule::At("1951-02-10".parse().unwrap())),
+        ] {
+            let runs = runs.clone();
+            let name = *name;
+            scheduler.add(
+                *schedule,
+                Action::callback(move |_, date| {
+                    runs.borrow_mut().push(format!("{} {}", name, date));
+                    Ok(())
+                }),
+            );
+        }
+        let cancelled = scheduler.add(Schedule::Monthly, Action::Chat("hello".to_string()));
+        assert!(scheduler.cancel(cancelled));
+
+        let mut output = Vec::new();
+        let mut ctx = Context::new(&mut output);
+        // Only monthly updates, with a few months skipped. The same date
+        // may be sent twice when polled.
+        for date in &[
+            "1950-11-15",
+            "1950-12-01",
+            "1950-12-01",
+            "1951-01-01",
+            "1951-05-01",
+            "1951-05-02",
+        ] {
+            scheduler.run_due(&mut ctx, date.parse().unwrap()).unwrap();
+        }
+        assert_eq!(
+            *runs.borrow(),
+            vec![
+                "monthly 1950-12-01",
+                "monthly 1951-01-01",
+                "quarterly 1951-01-01",
+                "yearly 1951-01-01",
+                "monthly 1951-05-01",
+                "quarterly 1951-05-01",
+                "end 1951-05-01",
+            ]
+        );
+        assert_eq!(scheduler.len(), 3);
+        assert!(output.is_empty());
+    }
+
+    #[test]
+    fn overdue_tasks() {
+        let runs = Rc::new(RefCell::new(Vec::new()));
+        let mut scheduler = Scheduler::new();
+        let add = |scheduler: &mut Scheduler, at: &str| {
+            let runs = runs.clone();
+            scheduler.add(
+                Schedule::At(at.parse().unwrap()),
+                Action::callback(move |_, date| {
+                    runs.borrow_mut().push(date.to_string());
+                    Ok(())
+                }),
+            );
+        };
+        add(&mut scheduler, "1950-01-01");
+        let mut output = Vec::new();
+        let mut ctx = Context::new(&mut output);
+        scheduler
+            .run_due(&mut ctx, "1950-06-01".parse().unwrap())
+            .unwrap();
+        // Added after its date has passed.
+        add(&mut scheduler, "1950-03-01");
+        scheduler
+            .run_due(&mut ctx, "1950-06-02".parse().unwrap())
+            .unwrap();
+        scheduler
+            .run_due(&mut ctx, "1950-06-03".parse().unwrap())
+            .unwrap();
+        assert_eq!(*runs.borrow(), vec!["1950-06-01", "1950-06-02"]);
+        assert!(scheduler.is_empty());
+    }
+
+    #[test]
+    fn send_packets() {
+        let mut scheduler = Scheduler::new();
+        scheduler.add(Schedule::Yearly, Action::Rcon(Command::save("autosave")));
+        let mut output = Vec::new();
+        for date in &["1950-12-31", "1951-01-01"] {
+            let mut ctx = Context::new(&mut output);
+            scheduler.run_due(&mut ctx, date.parse().unwrap()).unwrap();
+        }
+        // The packet contains the command after the header.
+        assert!(output.ends_with(b"save autosave\0"));
+    }
+}
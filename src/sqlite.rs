@@ -0,0 +1,589 @@
+//! Storing the history of a server in SQLite, with the `sqlite` feature. A
+//! [`Store`] records client and company events, chat messages and economy
+//! and statistics snapshots, keyed by a server id chosen by the caller and
+//! the game date, so several servers can share a database.
+//!
+//! # Schema
+//!
+//! Every table has the columns `server` (the server id), `date` (the game
+//! date as the OpenTTD number of days since year 0, or `NULL` before the
+//! first `Date` packet) and `time` (the time of arrival in seconds since the
+//! Unix epoch).
+//!
+//! - `client_events`: `client_id`, `event` (`join`, `info`, `update`,
+//!   `quit` or `error`), `name`, `address`, `company_id` and `error`, where
+//!   the packet provides them.
+//! - `company_events`: `company_id`, `event` (`new`, `info`, `update` or
+//!   `remove`), `name`, `manager`, `ai` and `reason`.
+//! - `chat`: `client_id`, `action`, `destination` and `message`.
+//! - `company_economy` and `company_stats`: `company_id` and a column for
+//!   every field of the `CompanyEconomy` and `CompanyStats` packets.
+//!
+//! Money is stored as a signed 64-bit integer.
+//!
+//! # Migrations
+//!
+//! The version of the schema is kept in `PRAGMA user_version`. Opening a
+//! database applies the missing migrations in order, each in its own
+//! transaction; a database of a newer version than this crate knows is
+//! refused.
+
+use crate::handler::{AdminHandler, Context};
+use crate::packet::admin::server_packets::Packet;
+use crate::types::Date;
+use rusqlite::{params, Connection};
+use std::fmt::{self, Display};
+use std::io;
+use std::path::Path;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// The migrations of the schema. Migration `n` brings the database to
+/// version `n + 1`.
+const MIGRATIONS: &[&str] = &[r"
+CREATE TABLE client_events (
+    id INTEGER PRIMARY KEY,
+    server TEXT NOT NULL,
+    date INTEGER,
+    time INTEGER NOT NULL,
+    client_id INTEGER NOT NULL,
+    event TEXT NOT NULL,
+    name TEXT,
+    address TEXT,
+    company_id INTEGER,
+    error INTEGER
+);
+CREATE INDEX client_events_client ON client_events (server, client_id);
+
+CREATE TABLE company_events (
+    id INTEGER PRIMARY KEY,
+    server TEXT NOT NULL,
+    date INTEGER,
+    time INTEGER NOT NULL,
+    company_id INTEGER NOT NULL,
+    event TEXT NOT NULL,
+    name TEXT,
+    manager TEXT,
+    ai INTEGER,
+    reason INTEGER
+);
+CREATE INDEX company_events_company ON company_events (server, company_id);
+
+CREATE TABLE chat (
+    id INTEGER PRIMARY KEY,
+    server TEXT NOT NULL,
+    date INTEGER,
+    time INTEGER NOT NULL,
+    client_id INTEGER NOT NULL,
+    action INTEGER NOT NULL,
+    destination INTEGER NOT NULL,
+    message TEXT NOT NULL
+);
+CREATE INDEX chat_date ON chat (server, date);
+
+CREATE TABLE company_economy (
+    id INTEGER PRIMARY KEY,
+    server TEXT NOT NULL,
+    date INTEGER,
+    time INTEGER NOT NULL,
+    company_id INTEGER NOT NULL,
+    money INTEGER NOT NULL,
+    loan INTEGER NOT NULL,
+    income INTEGER NOT NULL,
+    delivered_cargo INTEGER NOT NULL,
+    company_value_last INTEGER NOT NULL,
+    performance_last INTEGER NOT NULL,
+    delivered_cargo_last INTEGER NOT NULL,
+    company_value_previous INTEGER NOT NULL,
+    performance_previous INTEGER NOT NULL,
+    delivered_previous INTEGER NOT NULL
+);
+CREATE INDEX company_economy_company ON company_economy (server, company_id, date);
+
+CREATE TABLE company_stats (
+    id INTEGER PRIMARY KEY,
+    server TEXT NOT NULL,
+    date INTEGER,
+    time INTEGER NOT NULL,
+    company_id INTEGER NOT NULL,
+    trains INTEGER NOT NULL,
+    lorries INTEGER NOT NULL,
+    busses INTEGER NOT NULL,
+    planes INTEGER NOT NULL,
+    ships INTEGER NOT NULL,
+    train_stations INTEGER NOT NULL,
+    lorry_stations INTEGER NOT NULL,
+    bus_stops INTEGER NOT NULL,
+    airports_and_heliports INTEGER NOT NULL,
+    harbours INTEGER NOT NULL
+);
+CREATE INDEX company_stats_company ON company_stats (server, company_id, date);
+"];
+
+/// The version of the schema created by this crate.
+pub const SCHEMA_VERSION: u32 = MIGRATIONS.len() as u32;
+
+/// An error returned by [`Store`].
+#[derive(Debug)]
+pub enum StoreError {
+    /// A query failed.
+    Sqlite(rusqlite::Error),
+    /// The database has a newer schema than this crate supports.
+    SchemaTooNew { version: u32 },
+}
+
+impl Display for StoreError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            StoreError::Sqlite(err) => err.fmt(f),
+            StoreError::SchemaTooNew { version } => write!(
+                f,
+                "the database has schema version {}, but at most {} is supported",
+                version, SCHEMA_VERSION
+            ),
+        }
+    }
+}
+
+impl std::error::Error for StoreError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            StoreError::Sqlite(err) => Some(err),
+            StoreError::SchemaTooNew { .. } => None,
+        }
+    }
+}
+
+impl From<rusqlite::Error> for StoreError {
+    fn from(err: rusqlite::Error) -> StoreError {
+        StoreError::Sqlite(err)
+    }
+}
+
+pub type Result<T> = std::result::Result<T, StoreError>;
+
+/// A client session, from joining to leaving the game.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Session {
+    pub client_id: u32,
+    /// The last known name of the client during the session.
+    pub name: Option<String>,
+    pub joined: SystemTime,
+    pub joined_date: Option<Date>,
+    /// When the client left, or `None` if it has not left yet.
+    pub left: Option<SystemTime>,
+    pub left_date: Option<Date>,
+}
+
+impl Session {
+    /// The real time the client was connected, if it has left.
+    pub fn duration(&self) -> Option<Duration> {
+        self.left
+            .map(|left| left.duration_since(self.joined).unwrap_or_default())
+    }
+
+    /// The number of game days the client was connected, if it has left and
+    /// the dates are known.
+    pub fn days(&self) -> Option<i64> {
+        Some(self.joined_date?.days_between(self.left_date?))
+    }
+}
+
+/// Stores the packets of a server in an SQLite database.
+#[derive(Debug)]
+pub struct Store {
+    connection: Connection,
+    server: String,
+    date: Option<Date>,
+}
+
+impl Store {
+    /// Open or create the database at `path`, storing packets for `server`.
+    pub fn open<P: AsRef<Path>>(path: P, server: &str) -> Result<Store> {
+        Store::with_connection(Connection::open(path)?, server)
+    }
+
+    /// A database that only lives in memory.
+    pub fn open_in_memory(server: &str) -> Result<Store> {
+        Store::with_connection(Connection::open_in_memory()?, server)
+    }
+
+    /// Use an existing connection, applying the missing migrations.
+    pub fn with_connection(mut connection: Connection, server: &str) -> Result<Store> {
+        migrate(&mut connection)?;
+        Ok(Store {
+            connection,
+            server: server.to_string(),
+            date: None,
+        })
+    }
+
+    /// The underlying connection, for queries of your own.
+    pub fn connection(&self) -> &Connection {
+        &self.connection
+    }
+
+    pub fn server(&self) -> &str {
+        &self.server
+    }
+
+    /// The version of the schema of the database.
+    pub fn schema_version(&self) -> Result<u32> {
+        schema_version(&self.connection)
+    }
+
+    /// Store a packet received from the server, if it is of interest.
+    pub fn record(&mut self, packet: &Packet) -> Result<()> {
+        self.record_at(packet, SystemTime::now())
+    }
+
+    /// Like [`Store::record`], using `time` as the time the packet arrived.
+    pub fn record_at(&mut self, packet: &Packet, time: SystemTime) -> Result<()> {
+        let time = time
+            .duration_since(UNIX_EPOCH)
+            .map(|duration| duration.as_secs() as i64)
+            .unwrap_or(0);
+        let server = self.server.as_str();
+        let date = self.date.map(Date::to_openttd_date);
+        let client_event = |event: &str,
+                            client_id: u32,
+                            name: Option<&str>,
+                            address: Option<&str>,
+                            company_id: Option<u8>,
+                            error: Option<u8>| {
+            self.connection.execute(
+                "INSERT INTO client_events
+                    (server, date, time, client_id, event, name, address, company_id, error)
+                    VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
+                params![server, date, time, client_id, event, name, address, company_id, error],
+            )
+        };
+        let company_event = |event: &str,
+                             company_id: u32,
+                             name: Option<&str>,
+                             manager: Option<&str>,
+                             ai: Option<bool>,
+                             reason: Option<u8>| {
+            self.connection.execute(
+                "INSERT INTO company_events
+                    (server, date, time, company_id, event, name, manager, ai, reason)
+                    VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
+                params![server, date, time, company_id, event, name, manager, ai, reason],
+            )
+        };
+        match packet {
+            Packet::Date(date) => {
+                self.date = Some(date.date);
+                return Ok(());
+            }
+            Packet::ClientJoin(join) => client_event("join", join.id, None, None, None, None)?,
+            Packet::ClientInfo(info) => client_event(
+                "info",
+                info.id,
+                Some(&info.name),
+                Some(&info.address),
+                Some(info.company_id),
+                None,
+            )?,
+            Packet::ClientUpdate(update) => client_event(
+                "update",
+                update.id,
+                Some(&update.name),
+                None,
+                Some(update.company_id),
+                None,
+            )?,
+            Packet::ClientQuit(quit) => client_event("quit", quit.id, None, None, None, None)?,
+            Packet::ClientError(error) => {
+                client_event("error", error.id, None, None, None, Some(error.error))?
+            }
+            Packet::CompanyNew(new) => company_event("new", new.id, None, None, None, None)?,
+            Packet::CompanyInfo(info) => company_event(
+                "info",
+                u32::from(info.id),
+                Some(&info.name),
+                Some(&info.manager),
+                Some(info.ai),
+                None,
+            )?,
+            Packet::CompanyUpdate(update) => company_event(
+                "update",
+                u32::from(update.id),
+                Some(&update.name),
+                Some(&update.manager),
+                None,
+                None,
+            )?,
+            Packet::CompanyRemove(remove) => company_event(
+                "remove",
+                u32::from(remove.id),
+                None,
+                None,
+                None,
+                Some(remove.reason),
+            )?,
+            Packet::Chat(chat) => self.connection.execute(
+                "INSERT INTO chat
+                    (server, date, time, client_id, action, destination, message)
+                    VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+                params![
+                    server,
+                    date,
+                    time,
+                    chat.client,
+                    chat.action,
+                    chat.destination,
+                    chat.message
+                ],
+            )?,
+            Packet::CompanyEconomy(economy) => self.connection.execute(
+                "INSERT INTO company_economy
+                    (server, date, time, company_id, money, loan, income, delivered_cargo,
+                     company_value_last, performance_last, delivered_cargo_last,
+                     company_value_previous, performance_previous, delivered_previous)
+                    VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14)",
+                params![
+                    server,
+                    date,
+                    time,
+                    economy.id,
+                    economy.money as i64,
+                    economy.loan as i64,
+                    economy.income,
+                    economy.delivered_cargo,
+                    economy.company_value_last as i64,
+                    economy.performance_last,
+                    economy.delivered_cargo_last,
+                    economy.company_value_previous as i64,
+                    economy.performance_previous,
+                    economy.delivered_previous
+                ],
+            )?,
+            Packet::CompanyStats(stats) => self.connection.execute(
+                "INSERT INTO company_stats
+                    (server, date, time, company_id, trains, lorries, busses, planes, ships,
+                     train_stations, lorry_stations, bus_stops, airports_and_heliports,
+                     harbours)
+                    VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14)",
+                params![
+                    server,
+                    date,
+                    time,
+                    stats.id,
+                    stats.trains,
+                    stats.lorries,
+                    stats.busses,
+                    stats.planes,
+                    stats.ships,
+                    stats.train_stations,
+                    stats.lorry_stations,
+                    stats.bus_stops,
+                    stats.airports_and_heliports,
+                    stats.harbours
+                ],
+            )?,
+            _ => return Ok(()),
+        };
+        Ok(())
+    }
+
+    /// The company value of the last quarter of a company at every economy
+    /// snapshot with a known date, oldest first.
+    pub fn company_value_over_time(&self, company_id: u8) -> Result<Vec<(Date, i64)>> {
+        let mut statement = self.connection.prepare(
+            "SELECT date, company_value_last FROM company_economy
+                WHERE server = ?1 AND company_id = ?2 AND date IS NOT NULL
+                ORDER BY date, id",
+        )?;
+        let rows = statement.query_map(params![self.server, company_id], |row| {
+            Ok((row.get::<_, u32>(0)?, row.get::<_, i64>(1)?))
+        })?;
+        let mut values = Vec::new();
+        for row in rows {
+            let (date, value) = row?;
+            if let Ok(date) = Date::from_openttd_date(date) {
+                values.push((date, value));
+            }
+        }
+        Ok(values)
+    }
+
+    /// The sessions of all clients that joined while recording, oldest
+    /// first. If the client of a session was not seen leaving, the session
+    /// ends when its client id joins again.
+    pub fn client_sessions(&self) -> Result<Vec<Session>> {
+        let mut statement = self.connection.prepare(
+            "SELECT client_id, event, name, date, time FROM client_events
+                WHERE server = ?1 ORDER BY id",
+        )?;
+        let rows = statement.query_map(params![self.server], |row| {
+            Ok((
+                row.get::<_, u32>(0)?,
+                row.get::<_, String>(1)?,
+                row.get::<_, Option<String>>(2)?,
+                row.get::<_, Option<u32>>(3)?,
+                row.get::<_, i64>(4)?,
+            ))
+        })?;
+        let mut sessions: Vec<Session> = Vec::new();
+        for row in rows {
+            let (client_id, event, name, date, time) = row?;
+            let date = date.and_then(|date| Date::from_openttd_date(date).ok());
+            let time = UNIX_EPOCH + Duration::from_secs(time.max(0) as u64);
+            let open = sessions
+                .iter_mut()
+                .rev()
+                .find(|session| session.client_id == client_id && session.left.is_none());
+            match (event.as_str(), open) {
+                ("join", open) => {
+                    if let Some(session) = open {
+                        session.left = Some(time);
+                        session.left_date = date;
+                    }
+                    sessions.push(Session {
+                        client_id,
+                        name,
+                        joined: time,
+                        joined_date: date,
+                        left: None,
+                        left_date: None,
+                    })
+                }
+                ("quit", Some(session)) | ("error", Some(session)) => {
+                    session.left = Some(time);
+                    session.left_date = date;
+                }
+                (_, Some(session)) if name.is_some() => session.name = name,
+                _ => {}
+            }
+        }
+        Ok(sessions)
+    }
+}
+
+impl AdminHandler for Store {
+    fn on_packet(
+        &mut self,
+        _ctx: &mut Context,
+        packet: &Packet,
+    ) -> crate::packet::admin::Result<()> {
+        self.record(packet)
+            .map_err(|err| io::Error::other(err).into())
+    }
+}
+
+fn schema_version(connection: &Connection) -> Result<u32> {
+    Ok(connection.pragma_query_value(None, "user_version", |row| row.get(0))?)
+}
+
+/// Apply the missing migrations.
+fn migrate(connection: &mut Connection) -> Result<()> {
+    let version = schema_version(connection)?;
+    if version > SCHEMA_VERSION {
+        return Err(StoreError::SchemaTooNew { version });
+    }
+    for (index, migration) in MIGRATIONS.iter().enumerate().skip(version as usize) {
+        let transaction = connection.transaction()?;
+        transaction.execute_batch(migration)?;
+        transaction.pragma_update(None, "user_version", index as u32 + 1)?;
+        transaction.commit()?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::packet::admin::server_packets;
+    use crate::test_util::{date, economy, TempDir};
+
+    #[test]
+    fn store_and_query() {
+        let directory = TempDir::new("sqlite-query");
+        let path = directory.join("history.sqlite");
+        let mut store = Store::open(&path, "main").unwrap();
+        assert_eq!(store.schema_version().unwrap(), SCHEMA_VERSION);
+        let at = |seconds| UNIX_EPOCH + Duration::from_secs(seconds);
+        let packets = vec![
+            (0, economy(0, 100)),
+            (10, date("1950-01-01")),
+            (10, Packet::ClientJoin(server_packets::ClientJoin { id: 3 })),
+            (
+                11,
+                Packet::ClientInfo(server_packets::ClientInfo {
+                    id: 3,
+                    address: "192.0.2.3".to_string(),
+                    name: "Alice".to_string(),
+                    language: 0,
+                    date_joined: "1950-01-01".parse::<Date>().unwrap().into(),
+                    company_id: 255,
+                }),
+            ),
+            (20, economy(0, 200)),
+            (20, economy(1, 50)),
+            (30, date("1950-02-01")),
+            (30, economy(0, 300)),
+            (40, Packet::ClientQuit(server_packets::ClientQuit { id: 3 })),
+            (50, Packet::ClientJoin(server_packets::ClientJoin { id: 4 })),
+            // The quit of client 4 was missed.
+            (60, Packet::ClientJoin(server_packets::ClientJoin { id: 4 })),
+        ];
+        for (seconds, packet) in &packets {
+            store.record_at(packet, at(*seconds)).unwrap();
+        }
+
+        // Another server in the same database.
+        let mut other = Store::open(&path, "other").unwrap();
+        other.record_at(&date("1950-01-01"), at(0)).unwrap();
+        other.record_at(&economy(0, 999), at(0)).unwrap();
+        other
+            .record_at(
+                &Packet::ClientJoin(server_packets::ClientJoin { id: 3 }),
+                at(0),
+            )
+            .unwrap();
+
+        let january: Date = "1950-01-01".parse().unwrap();
+        assert_eq!(
+            store.company_value_over_time(0).unwrap(),
+            vec![(january, 200), (january + 31, 300)]
+        );
+        assert_eq!(
+            other.company_value_over_time(0).unwrap(),
+            vec![(january, 999)]
+        );
+        assert_eq!(other.client_sessions().unwrap().len(), 1);
+
+        let sessions = store.client_sessions().unwrap();
+        assert_eq!(sessions.len(), 3);
+        assert_eq!(sessions[0].name.as_deref(), Some("Alice"));
+        assert_eq!(sessions[0].duration(), Some(Duration::from_secs(30)));
+        assert_eq!(sessions[0].days(), Some(31));
+        assert_eq!(sessions[1].left, Some(at(60)));
+        assert_eq!(sessions[2].left, None);
+    }
+
+    #[test]
+    fn migrations() {
+        let directory = TempDir::new("sqlite-migrations");
+        let path = directory.join("history.sqlite");
+
+        let mut store = Store::open(&path, "main").unwrap();
+        store.record(&date("1960-06-01")).unwrap();
+        store.record(&economy(2, 42)).unwrap();
+        drop(store);
+
+        // Reopening keeps the data.
+        let store = Store::open(&path, "main").unwrap();
+        assert_eq!(store.schema_version().unwrap(), SCHEMA_VERSION);
+        assert_eq!(store.company_value_over_time(2).unwrap().len(), 1);
+        store
+            .connection()
+            .pragma_update(None, "user_version", SCHEMA_VERSION + 1)
+            .unwrap();
+        drop(store);
+        assert!(matches!(
+            Store::open(&path, "main"),
+            Err(StoreError::SchemaTooNew { .. })
+        ));
+    }
+}
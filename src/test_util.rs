@@ -0,0 +1,85 @@
+//! Helpers shared by the tests of several modules.
+
+use crate::packet::admin::server_packets::{self, Packet};
+use std::fs;
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+
+/// A stream that reads from a fixed input and collects the output.
+pub struct MockStream {
+    pub input: &'static [u8],
+    pub output: Vec<u8>,
+}
+
+impl MockStream {
+    pub fn new(input: &'static [u8]) -> MockStream {
+        MockStream {
+            input,
+            output: Vec::new(),
+        }
+    }
+}
+
+impl Read for MockStream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.input.read(buf)
+    }
+}
+
+impl Write for MockStream {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.output.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// An empty directory for the files of a test, removed when dropped. The
+/// name should be unique among the tests.
+pub struct TempDir(PathBuf);
+
+impl TempDir {
+    pub fn new(name: &str) -> TempDir {
+        let path =
+            std::env::temp_dir().join(format!("openttd-admin-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&path);
+        fs::create_dir_all(&path).unwrap();
+        TempDir(path)
+    }
+
+    pub fn join<P: AsRef<Path>>(&self, path: P) -> PathBuf {
+        self.0.join(path)
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
+
+/// An economy update with only the company value set.
+pub fn economy(id: u8, company_value_last: u64) -> Packet {
+    Packet::CompanyEconomy(server_packets::CompanyEconomy {
+        id,
+        money: 0,
+        loan: 0,
+        income: 0,
+        delivered_cargo: 0,
+        company_value_last,
+        performance_last: 0,
+        delivered_cargo_last: 0,
+        company_value_previous: 0,
+        performance_previous: 0,
+        delivered_previous: 0,
+    })
+}
+
+/// A date update, with the date as `YYYY-MM-DD`.
+pub fn date(date: &str) -> Packet {
+    Packet::Date(server_packets::Date {
+        date: date.parse().unwrap(),
+    })
+}
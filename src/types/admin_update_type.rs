@@ -30,6 +30,38 @@ pub enum AdminUpdateType {
 
 const ADMIN_UPDATE_TYPE_SERIALIZE_ERROR: &str = "could not serialze AdminUpdateType";
 
+const NAMES: [(AdminUpdateType, &str); 10] = [
+    (AdminUpdateType::Date, "Date"),
+    (AdminUpdateType::ClientInfo, "ClientInfo"),
+    (AdminUpdateType::CompanyInfo, "CompanyInfo"),
+    (AdminUpdateType::CompanyEconomy, "CompanyEconomy"),
+    (AdminUpdateType::CompanyStats, "CompanyStats"),
+    (AdminUpdateType::Chat, "Chat"),
+    (AdminUpdateType::Console, "Console"),
+    (AdminUpdateType::CmdNames, "CmdNames"),
+    (AdminUpdateType::CmdLogging, "CmdLogging"),
+    (AdminUpdateType::Gamescript, "Gamescript"),
+];
+
+impl AdminUpdateType {
+    /// Returns the name of the variant, as used in the JSON representation.
+    pub fn name(self) -> &'static str {
+        NAMES
+            .iter()
+            .find(|(update_type, _)| *update_type == self)
+            .map(|(_, name)| *name)
+            .unwrap()
+    }
+
+    /// Returns the variant with the given name.
+    pub fn from_name(name: &str) -> Option<AdminUpdateType> {
+        NAMES
+            .iter()
+            .find(|(_, variant_name)| *variant_name == name)
+            .map(|(update_type, _)| *update_type)
+    }
+}
+
 impl Serialize for AdminUpdateType {
     fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
     where
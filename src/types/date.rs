@@ -1,9 +1,13 @@
 // Much of this code is based on https://github.com/OpenTTD/OpenTTD/blob/master/src/date.cpp.
 
+// The `Fail` derive predates the `non_local_definitions` lint.
+#![allow(non_local_definitions)]
+
 use failure::Fail;
 use lazy_static::*;
 use serde::de::{Deserialize, Deserializer};
 use serde::ser::{Serialize, Serializer};
+use std::ops::{Add, AddAssign, Sub, SubAssign};
 
 const DAYS_IN_YEAR: u32 = 365;
 const DAYS_IN_LEAP_YEAR: u32 = 366;
@@ -430,9 +434,36 @@ fn accumulated_days_for_month(month: u32) -> Result<u32, DateError> {
     }
 }
 
-/// The number of days till the last day.
+/// A day of the week.
+#[derive(Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash, Debug)]
+pub enum Weekday {
+    Monday,
+    Tuesday,
+    Wednesday,
+    Thursday,
+    Friday,
+    Saturday,
+    Sunday,
+}
+
+impl Weekday {
+    /// The weekday after `days` days, counting from Monday.
+    fn from_days(days: u32) -> Weekday {
+        match days % 7 {
+            0 => Weekday::Monday,
+            1 => Weekday::Tuesday,
+            2 => Weekday::Wednesday,
+            3 => Weekday::Thursday,
+            4 => Weekday::Friday,
+            5 => Weekday::Saturday,
+            _ => Weekday::Sunday,
+        }
+    }
+}
+
 lazy_static! {
-    static ref MAX_DAY: u32 = (days_till!(MAX_YEAR + 1) - 1);
+    /// The number of days till the last day.
+    static ref MAX_DAY: u32 = days_till!(MAX_YEAR + 1) - 1;
 }
 
 /// An OpenTTD date.
@@ -453,6 +484,7 @@ impl std::fmt::Debug for Date {
     }
 }
 
+#[allow(clippy::enum_variant_names)]
 #[derive(Copy, Clone, Eq, PartialEq, Debug, Fail)]
 pub enum DateError {
     #[fail(display = "the date {} is out of range", date)]
@@ -466,6 +498,12 @@ pub enum DateError {
     MonthOutOfRange { month: u32 },
     #[fail(display = "year {} is out of range", year)]
     YearOutOfRange { year: u32 },
+    #[fail(display = "a date should be formatted as YYYY-MM-DD")]
+    InvalidFormat,
+    #[fail(display = "the date would be before year 0")]
+    BeforeYearZero,
+    #[fail(display = "year {} is not supported by the other date type", year)]
+    UnsupportedYear { year: i64 },
 }
 
 impl Date {
@@ -555,8 +593,84 @@ impl Date {
         Ok(Date(days_till!(year) + days))
     }
 
+    /// The date `days` days later, or `None` if it is out of range.
+    pub fn checked_add_days(self, days: u32) -> Option<Date> {
+        self.0
+            .checked_add(days)
+            .and_then(|date| Date::from_openttd_date(date).ok())
+    }
+
+    /// The date `days` days earlier, or `None` if it is before year 0.
+    pub fn checked_sub_days(self, days: u32) -> Option<Date> {
+        self.0.checked_sub(days).map(Date)
+    }
+
+    /// The number of days from this date to `other`, negative if `other` is
+    /// earlier.
+    pub fn days_between(self, other: Date) -> i64 {
+        i64::from(other.0) - i64::from(self.0)
+    }
+
+    /// Add a number of months, which may be negative. The day is clamped to
+    /// the length of the resulting month, so January 31st plus one month is
+    /// the last day of February.
+    pub fn add_months(self, months: i32) -> Result<Date, DateError> {
+        let (year, month, day) = self.to_ymd();
+        let months = i64::from(year) * 12 + i64::from(month) + i64::from(months);
+        if months < 0 {
+            return Err(DateError::BeforeYearZero);
+        } else if months / 12 > i64::from(MAX_YEAR) {
+            return Err(DateError::YearOutOfRange {
+                year: (months / 12) as u32,
+            });
+        }
+        let (year, month) = ((months / 12) as u32, (months % 12) as u32);
+        let day = day.min(Date::days_in_month(year, month)?);
+        Date::from_ymd(year, month, day)
+    }
+
+    /// Add a number of years, which may be negative. February 29th becomes
+    /// February 28th in years that are not leap years.
+    pub fn add_years(self, years: i32) -> Result<Date, DateError> {
+        self.add_months(years.saturating_mul(12))
+    }
+
+    /// The year, from 0 to 5.000.000.
+    pub fn year(self) -> u32 {
+        self.to_ymd().0
+    }
+
+    /// The day of the week.
+    pub fn weekday(self) -> Weekday {
+        // January 1st of year 0 is a Saturday.
+        Weekday::from_days(self.0 + 5)
+    }
+
+    /// The day of the year, from 1 for January 1st to 366.
+    pub fn day_of_year(self) -> u32 {
+        self.0 - days_till!(self.year()) + 1
+    }
+
+    /// The quarter of the year, from 1 to 4.
+    pub fn quarter(self) -> u32 {
+        self.to_ymd().1 / 3 + 1
+    }
+
+    /// Returns true on the first day of a month.
+    pub fn is_month_start(self) -> bool {
+        self.to_ymd().2 == 1
+    }
+
+    /// Returns true on the first day of a quarter.
+    pub fn is_quarter_start(self) -> bool {
+        let (_, month, day) = self.to_ymd();
+        month % 3 == 0 && day == 1
+    }
+
     /// Returns true if the year is a leap year.
-    fn is_leap_year(yr: u32) -> bool {
+    // `u32::is_multiple_of` needs Rust 1.87.
+    #[allow(clippy::manual_is_multiple_of)]
+    pub fn is_leap_year(yr: u32) -> bool {
         yr % 4 == 0 && (yr % 100 != 0 || yr % 400 == 0)
     }
 
@@ -569,7 +683,9 @@ impl Date {
         }
     }
 
-    fn days_in_month(year: u32, month: u32) -> Result<u32, DateError> {
+    /// Returns the number of days in a month of a year, with the month
+    /// ranging from 0 to 11.
+    pub fn days_in_month(year: u32, month: u32) -> Result<u32, DateError> {
         match month {
             0 => Ok(31),
             1 => {
@@ -594,6 +710,80 @@ impl Date {
     }
 }
 
+/// Adds a number of days.
+///
+/// # Panics
+///
+/// Panics if the result is out of range; see [`Date::checked_add_days`].
+impl Add<u32> for Date {
+    type Output = Date;
+
+    fn add(self, days: u32) -> Date {
+        self.checked_add_days(days)
+            .expect("date out of range when adding days")
+    }
+}
+
+impl AddAssign<u32> for Date {
+    fn add_assign(&mut self, days: u32) {
+        *self = *self + days;
+    }
+}
+
+/// Subtracts a number of days.
+///
+/// # Panics
+///
+/// Panics if the result is before year 0; see [`Date::checked_sub_days`].
+impl Sub<u32> for Date {
+    type Output = Date;
+
+    fn sub(self, days: u32) -> Date {
+        self.checked_sub_days(days)
+            .expect("date out of range when subtracting days")
+    }
+}
+
+impl SubAssign<u32> for Date {
+    fn sub_assign(&mut self, days: u32) {
+        *self = *self - days;
+    }
+}
+
+/// The number of days between two dates, as in [`Date::days_between`].
+impl Sub<Date> for Date {
+    type Output = i64;
+
+    fn sub(self, other: Date) -> i64 {
+        other.days_between(self)
+    }
+}
+
+/// Parses a date formatted as `YYYY-MM-DD`, like it is displayed.
+impl std::str::FromStr for Date {
+    type Err = DateError;
+
+    fn from_str(s: &str) -> Result<Date, DateError> {
+        let mut parts = s.splitn(3, '-').map(|part| {
+            if part.is_empty() || !part.bytes().all(|b| b.is_ascii_digit()) {
+                Err(DateError::InvalidFormat)
+            } else {
+                part.parse::<u32>().map_err(|_| DateError::InvalidFormat)
+            }
+        });
+        match (parts.next(), parts.next(), parts.next()) {
+            (Some(year), Some(month), Some(day)) => {
+                let month = month?;
+                if month == 0 {
+                    return Err(DateError::MonthOutOfRange { month });
+                }
+                Date::from_ymd(year?, month - 1, day?)
+            }
+            _ => Err(DateError::InvalidFormat),
+        }
+    }
+}
+
 impl Serialize for Date {
     fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
     where
@@ -632,6 +822,62 @@ mod test {
         );
     }
 
+    #[test]
+    fn from_str() {
+        assert_eq!(
+            "1950-03-01".parse::<Date>().unwrap(),
+            Date::from_ymd(1950, 2, 1).unwrap()
+        );
+        assert_eq!(
+            "1950-02-29".parse::<Date>(),
+            Err(DateError::DayOutOfRange {
+                day: 29,
+                month: 1,
+                year: 1950
+            })
+        );
+        assert_eq!("1950-03".parse::<Date>(), Err(DateError::InvalidFormat));
+        assert_eq!("1950-+3-01".parse::<Date>(), Err(DateError::InvalidFormat));
+    }
+
+    #[test]
+    fn arithmetic() {
+        let date: Date = "1950-01-31".parse().unwrap();
+        assert_eq!((date + 30).to_string(), "1950-03-02");
+        assert_eq!(date - 31, "1949-12-31".parse().unwrap());
+        assert_eq!(date.days_between(date + 400), 400);
+        assert_eq!(date - (date + 400), -400);
+        assert_eq!(date.checked_sub_days(date.to_openttd_date() + 1), None);
+        assert_eq!(date.add_months(1).unwrap().to_string(), "1950-02-28");
+        assert_eq!(date.add_months(-2).unwrap().to_string(), "1949-11-30");
+        assert_eq!(date.add_months(13).unwrap().to_string(), "1951-02-28");
+        let leap_day: Date = "2000-02-29".parse().unwrap();
+        assert_eq!(leap_day.add_years(1).unwrap().to_string(), "2001-02-28");
+        assert_eq!(
+            leap_day.add_years(4).unwrap(),
+            "2004-02-29".parse().unwrap()
+        );
+        assert_eq!(leap_day.add_years(-2001), Err(DateError::BeforeYearZero));
+        assert_eq!(leap_day.add_years(-2000).unwrap().to_string(), "0000-02-29");
+    }
+
+    #[test]
+    fn calendar() {
+        let date: Date = "1970-01-01".parse().unwrap();
+        assert_eq!(date.weekday(), Weekday::Thursday);
+        assert_eq!((date + 3).weekday(), Weekday::Sunday);
+        assert_eq!(date.day_of_year(), 1);
+        assert_eq!("2000-12-31".parse::<Date>().unwrap().day_of_year(), 366);
+        assert_eq!("1999-12-31".parse::<Date>().unwrap().day_of_year(), 365);
+        let date: Date = "1950-04-01".parse().unwrap();
+        assert_eq!(date.quarter(), 2);
+        assert!(date.is_month_start() && date.is_quarter_start());
+        assert!(!(date + 1).is_month_start());
+        assert!((date + 30).is_month_start() && !(date + 30).is_quarter_start());
+        assert!(Date::is_leap_year(2000) && !Date::is_leap_year(1900));
+        assert_eq!(Date::days_in_month(2024, 1), Ok(29));
+    }
+
     proptest! {
         /// The inner representation should not be tested, just that it converts
         /// losslessly.
@@ -656,4 +902,13 @@ mod test {
             assert_eq!(new_date, date);
         }
     }
+
+    proptest! {
+        /// Test whether a displayed date parses to the same date.
+        #[test]
+        fn display_conversion(openttd_date in 0..(days_till!(MAX_YEAR + 1) - 1)) {
+            let date = Date::from_openttd_date(openttd_date).unwrap();
+            assert_eq!(date.to_string().parse::<Date>().unwrap(), date);
+        }
+    }
 }
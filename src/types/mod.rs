@@ -3,7 +3,13 @@
 mod admin_update_frequency;
 mod admin_update_type;
 mod date;
+mod tile_index;
+mod timekeeping;
+mod ymd;
 
 pub use admin_update_frequency::UpdateFrequencies;
 pub use admin_update_type::AdminUpdateType;
-pub use date::Date;
+pub use date::{Date, DateError, Weekday};
+pub use tile_index::{MapSize, TileError, TileIndex};
+pub use timekeeping::{CalendarDate, EconomyDate, EconomyTime, TimekeepingUnits};
+pub use ymd::{Month, Ymd};
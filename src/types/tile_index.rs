@@ -0,0 +1,181 @@
+use std::convert::TryFrom;
+use std::fmt::{self, Display};
+
+/// The size of a map in tiles.
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug)]
+pub struct MapSize {
+    pub width: u32,
+    pub height: u32,
+}
+
+impl MapSize {
+    pub fn new(width: u32, height: u32) -> MapSize {
+        MapSize { width, height }
+    }
+
+    /// Returns true if the coordinates lie on the map.
+    pub fn contains(self, x: u32, y: u32) -> bool {
+        x < self.width && y < self.height
+    }
+
+    /// The number of tiles on the map.
+    pub fn tiles(self) -> u64 {
+        u64::from(self.width) * u64::from(self.height)
+    }
+}
+
+/// A tile index that does not lie on the map.
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub enum TileError {
+    IndexOutOfBounds { index: u32, map_size: MapSize },
+    CoordinatesOutOfBounds { x: u32, y: u32, map_size: MapSize },
+}
+
+impl Display for TileError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TileError::IndexOutOfBounds { index, map_size } => write!(
+                f,
+                "tile {} is outside of the {}x{} map",
+                index, map_size.width, map_size.height
+            ),
+            TileError::CoordinatesOutOfBounds { x, y, map_size } => write!(
+                f,
+                "tile ({}, {}) is outside of the {}x{} map",
+                x, y, map_size.width, map_size.height
+            ),
+        }
+    }
+}
+
+impl std::error::Error for TileError {}
+
+/// A tile on a map of a known size. Tiles are numbered row by row, so the
+/// index is `y * width + x`.
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug)]
+pub struct TileIndex {
+    index: u32,
+    map_size: MapSize,
+}
+
+impl TileIndex {
+    /// The tile with the given index.
+    pub fn new(index: u32, map_size: MapSize) -> Result<TileIndex, TileError> {
+        if u64::from(index) < map_size.tiles() {
+            Ok(TileIndex { index, map_size })
+        } else {
+            Err(TileError::IndexOutOfBounds { index, map_size })
+        }
+    }
+
+    /// The tile at the given coordinates.
+    pub fn from_xy(x: u32, y: u32, map_size: MapSize) -> Result<TileIndex, TileError> {
+        if map_size.contains(x, y) {
+            Ok(TileIndex {
+                index: y * map_size.width + x,
+                map_size,
+            })
+        } else {
+            Err(TileError::CoordinatesOutOfBounds { x, y, map_size })
+        }
+    }
+
+    /// The index as used by OpenTTD.
+    pub fn index(self) -> u32 {
+        self.index
+    }
+
+    pub fn map_size(self) -> MapSize {
+        self.map_size
+    }
+
+    pub fn x(self) -> u32 {
+        self.index % self.map_size.width
+    }
+
+    pub fn y(self) -> u32 {
+        self.index / self.map_size.width
+    }
+
+    pub fn to_xy(self) -> (u32, u32) {
+        (self.x(), self.y())
+    }
+
+    /// The tile at an offset from this tile, if it lies on the map.
+    pub fn offset(self, dx: i64, dy: i64) -> Option<TileIndex> {
+        let x = u32::try_from(i64::from(self.x()) + dx).ok()?;
+        let y = u32::try_from(i64::from(self.y()) + dy).ok()?;
+        TileIndex::from_xy(x, y, self.map_size).ok()
+    }
+
+    /// The number of tiles to travel between two tiles when moving only
+    /// along the axes.
+    pub fn manhattan_distance(self, other: TileIndex) -> u32 {
+        let (dx, dy) = self.delta(other);
+        dx + dy
+    }
+
+    /// The straight-line distance between two tiles.
+    pub fn euclidean_distance(self, other: TileIndex) -> f64 {
+        let (dx, dy) = self.delta(other);
+        f64::from(dx).hypot(f64::from(dy))
+    }
+
+    fn delta(self, other: TileIndex) -> (u32, u32) {
+        let (x1, y1) = self.to_xy();
+        let (x2, y2) = other.to_xy();
+        (x1.abs_diff(x2), y1.abs_diff(y2))
+    }
+}
+
+impl Display for TileIndex {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "({}, {})", self.x(), self.y())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use proptest::*;
+
+    const MAP: MapSize = MapSize {
+        width: 256,
+        height: 128,
+    };
+
+    #[test]
+    fn coordinates() {
+        let tile = TileIndex::new(256 * 20 + 10, MAP).unwrap();
+        assert_eq!(tile.to_xy(), (10, 20));
+        assert_eq!(tile.to_string(), "(10, 20)");
+        assert_eq!(TileIndex::from_xy(10, 20, MAP), Ok(tile));
+        assert_eq!(
+            TileIndex::new(256 * 128, MAP),
+            Err(TileError::IndexOutOfBounds {
+                index: 256 * 128,
+                map_size: MAP
+            })
+        );
+        assert!(TileIndex::from_xy(256, 0, MAP).is_err());
+        assert_eq!(tile.offset(-10, 1).map(TileIndex::to_xy), Some((0, 21)));
+        assert_eq!(tile.offset(-11, 0), None);
+    }
+
+    #[test]
+    fn distances() {
+        let a = TileIndex::from_xy(1, 1, MAP).unwrap();
+        let b = TileIndex::from_xy(4, 5, MAP).unwrap();
+        assert_eq!(a.manhattan_distance(b), 7);
+        assert_eq!(b.manhattan_distance(a), 7);
+        assert!((a.euclidean_distance(b) - 5.0).abs() < 1e-9);
+    }
+
+    proptest! {
+        #[test]
+        fn xy_conversion(x in 0u32..256, y in 0u32..128) {
+            let tile = TileIndex::from_xy(x, y, MAP).unwrap();
+            prop_assert_eq!(TileIndex::new(tile.index(), MAP).unwrap().to_xy(), (x, y));
+        }
+    }
+}
@@ -0,0 +1,209 @@
+//! Since OpenTTD 14 the calendar and the economy keep time separately. In
+//! the default calendar mode both advance together. In wallclock mode the
+//! calendar can be slowed down or frozen, while the economy advances in
+//! minutes of real time: every economy month lasts a minute and has 30 days,
+//! and twelve of them make up a period instead of a year.
+//!
+//! The `Date` packet carries the calendar date, see
+//! [`Date::calendar_date`](crate::packet::admin::server_packets::Date::calendar_date).
+//! The date a client joined is an [`EconomyDate`].
+
+use super::date::{Date, DateError};
+use super::ymd::{Month, Ymd};
+use serde::de::{Deserialize, Deserializer};
+use serde::ser::{Serialize, Serializer};
+use std::fmt::{self, Display};
+use std::str::FromStr;
+
+/// The number of days in an economy month in wallclock mode.
+const DAYS_IN_ECONOMY_MONTH: u32 = 30;
+/// The number of economy months, or minutes, in a period.
+const MINUTES_IN_PERIOD: u32 = 12;
+
+/// The unit a server keeps time in, as in the `economy.timekeeping_units`
+/// setting.
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug, Default)]
+pub enum TimekeepingUnits {
+    /// The economy follows the calendar, as before OpenTTD 14.
+    #[default]
+    Calendar,
+    /// The economy advances in minutes of real time.
+    Wallclock,
+}
+
+/// Parses the value of the setting as printed by the console, either the
+/// name or the number of the option.
+impl FromStr for TimekeepingUnits {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<TimekeepingUnits, ()> {
+        match s {
+            "calendar" | "0" => Ok(TimekeepingUnits::Calendar),
+            "wallclock" | "1" => Ok(TimekeepingUnits::Wallclock),
+            _ => Err(()),
+        }
+    }
+}
+
+/// A date of the calendar, which decides things like the introduction of
+/// vehicles and the seasons.
+#[derive(Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash, Debug)]
+pub struct CalendarDate(pub Date);
+
+impl From<Date> for CalendarDate {
+    fn from(date: Date) -> CalendarDate {
+        CalendarDate(date)
+    }
+}
+
+impl From<CalendarDate> for Date {
+    fn from(date: CalendarDate) -> Date {
+        date.0
+    }
+}
+
+impl Display for CalendarDate {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+/// A date of the economy, which decides things like company finances and
+/// cargo payment. In calendar mode it is equal to the calendar date.
+#[derive(Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash, Debug)]
+pub struct EconomyDate(u32);
+
+impl EconomyDate {
+    /// The economy date with the given number of days since year 0.
+    pub fn from_days(days: u32) -> EconomyDate {
+        EconomyDate(days)
+    }
+
+    /// The number of days since year 0.
+    pub fn days(self) -> u32 {
+        self.0
+    }
+
+    /// Convert the date to a year, month and day in the given units. In
+    /// wallclock mode every month has 30 days.
+    pub fn to_ymd(self, units: TimekeepingUnits) -> Result<Ymd, DateError> {
+        match units {
+            TimekeepingUnits::Calendar => Date::from_openttd_date(self.0).map(Ymd::from),
+            TimekeepingUnits::Wallclock => {
+                let minutes = self.0 / DAYS_IN_ECONOMY_MONTH;
+                Ok(Ymd {
+                    year: minutes / MINUTES_IN_PERIOD,
+                    month: Month::from_number(minutes % MINUTES_IN_PERIOD + 1).unwrap(),
+                    day: self.0 % DAYS_IN_ECONOMY_MONTH + 1,
+                })
+            }
+        }
+    }
+
+    /// The economy time in wallclock mode.
+    pub fn to_economy_time(self) -> EconomyTime {
+        EconomyTime::from_minutes(self.0 / DAYS_IN_ECONOMY_MONTH)
+    }
+}
+
+/// Reinterprets a date sent by the server as an economy date.
+impl From<Date> for EconomyDate {
+    fn from(date: Date) -> EconomyDate {
+        EconomyDate(date.to_openttd_date())
+    }
+}
+
+/// Economy dates are serialized as the number of days since year 0, also in
+/// human readable formats, as their year, month and day depend on the
+/// timekeeping units of the server.
+impl Serialize for EconomyDate {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.serialize_u32(self.0)
+    }
+}
+
+impl<'de> Deserialize<'de> for EconomyDate {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        u32::deserialize(deserializer).map(EconomyDate)
+    }
+}
+
+/// A point in economy time in wallclock mode, in whole minutes since the
+/// start of the economy.
+#[derive(Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash, Debug, Default)]
+pub struct EconomyTime {
+    minutes: u32,
+}
+
+impl EconomyTime {
+    pub fn from_minutes(minutes: u32) -> EconomyTime {
+        EconomyTime { minutes }
+    }
+
+    pub fn minutes(self) -> u32 {
+        self.minutes
+    }
+
+    /// The number of the period, which lasts twelve minutes and takes the
+    /// place of a year.
+    pub fn period(self) -> u32 {
+        self.minutes / MINUTES_IN_PERIOD
+    }
+
+    /// The minute within the period, from 0 to 11.
+    pub fn minute_of_period(self) -> u32 {
+        self.minutes % MINUTES_IN_PERIOD
+    }
+
+    /// The first economy date of the minute.
+    pub fn to_economy_date(self) -> EconomyDate {
+        EconomyDate(self.minutes * DAYS_IN_ECONOMY_MONTH)
+    }
+}
+
+impl Display for EconomyTime {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "period {} minute {}",
+            self.period(),
+            self.minute_of_period()
+        )
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn economy_dates() {
+        let date: Date = "1950-03-01".parse().unwrap();
+        let economy = EconomyDate::from(date);
+        assert_eq!(
+            economy.to_ymd(TimekeepingUnits::Calendar).unwrap(),
+            date.ymd()
+        );
+
+        let economy = EconomyDate::from_days(360 * 2 + 30 * 3 + 4);
+        assert_eq!(
+            economy.to_ymd(TimekeepingUnits::Wallclock).unwrap(),
+            Ymd {
+                year: 2,
+                month: Month::April,
+                day: 5
+            }
+        );
+        let time = economy.to_economy_time();
+        assert_eq!(time.minutes(), 27);
+        assert_eq!(time.to_string(), "period 2 minute 3");
+        assert_eq!(time.to_economy_date(), EconomyDate::from_days(810));
+        assert_eq!("wallclock".parse(), Ok(TimekeepingUnits::Wallclock));
+    }
+}
@@ -0,0 +1,294 @@
+use super::date::{Date, DateError};
+use std::convert::TryFrom;
+use std::fmt::{self, Display};
+use std::str::FromStr;
+
+/// A month of the year.
+#[derive(Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash, Debug)]
+pub enum Month {
+    January,
+    February,
+    March,
+    April,
+    May,
+    June,
+    July,
+    August,
+    September,
+    October,
+    November,
+    December,
+}
+
+const MONTHS: [Month; 12] = [
+    Month::January,
+    Month::February,
+    Month::March,
+    Month::April,
+    Month::May,
+    Month::June,
+    Month::July,
+    Month::August,
+    Month::September,
+    Month::October,
+    Month::November,
+    Month::December,
+];
+
+impl Month {
+    /// The month with the given number, from 1 for January to 12 for
+    /// December.
+    pub fn from_number(number: u32) -> Option<Month> {
+        number
+            .checked_sub(1)
+            .and_then(|index| MONTHS.get(index as usize))
+            .copied()
+    }
+
+    /// The number of the month, from 1 for January to 12 for December.
+    pub fn number(self) -> u32 {
+        self.index() + 1
+    }
+
+    /// The month as used by OpenTTD and [`Date::from_ymd`], from 0 for
+    /// January to 11 for December.
+    pub fn index(self) -> u32 {
+        self as u32
+    }
+
+    /// The number of days in the month of a year.
+    pub fn days(self, year: u32) -> u32 {
+        Date::days_in_month(year, self.index()).unwrap()
+    }
+}
+
+/// A year, month and day. Unlike [`Date::to_ymd`], the month is a [`Month`]
+/// and both the month and the day are displayed and parsed as they are
+/// numbered in the calendar.
+#[derive(Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash, Debug)]
+pub struct Ymd {
+    /// The year, from 0 to 5.000.000.
+    pub year: u32,
+    pub month: Month,
+    /// The day of the month, starting at 1.
+    pub day: u32,
+}
+
+impl Ymd {
+    /// A year, month and day that exists.
+    pub fn new(year: u32, month: Month, day: u32) -> Result<Ymd, DateError> {
+        let ymd = Ymd { year, month, day };
+        ymd.to_date()?;
+        Ok(ymd)
+    }
+
+    pub fn to_date(self) -> Result<Date, DateError> {
+        Date::from_ymd(self.year, self.month.index(), self.day)
+    }
+}
+
+impl From<Date> for Ymd {
+    fn from(date: Date) -> Ymd {
+        let (year, month, day) = date.to_ymd();
+        Ymd {
+            year,
+            month: MONTHS[month as usize],
+            day,
+        }
+    }
+}
+
+impl TryFrom<Ymd> for Date {
+    type Error = DateError;
+
+    fn try_from(ymd: Ymd) -> Result<Date, DateError> {
+        ymd.to_date()
+    }
+}
+
+impl Display for Ymd {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{:04}-{:02}-{:02}",
+            self.year,
+            self.month.number(),
+            self.day
+        )
+    }
+}
+
+/// Parses a date formatted as `YYYY-MM-DD`. The date has to exist.
+impl FromStr for Ymd {
+    type Err = DateError;
+
+    fn from_str(s: &str) -> Result<Ymd, DateError> {
+        s.parse::<Date>().map(Ymd::from)
+    }
+}
+
+impl Date {
+    /// The year, month and day of the date.
+    pub fn ymd(self) -> Ymd {
+        Ymd::from(self)
+    }
+}
+
+#[cfg(feature = "chrono")]
+mod chrono_conversions {
+    use super::*;
+    use chrono::Datelike;
+
+    impl TryFrom<Ymd> for chrono::NaiveDate {
+        type Error = DateError;
+
+        fn try_from(ymd: Ymd) -> Result<chrono::NaiveDate, DateError> {
+            i32::try_from(ymd.year)
+                .ok()
+                .and_then(|year| chrono::NaiveDate::from_ymd_opt(year, ymd.month.number(), ymd.day))
+                .ok_or(DateError::UnsupportedYear {
+                    year: i64::from(ymd.year),
+                })
+        }
+    }
+
+    impl TryFrom<chrono::NaiveDate> for Ymd {
+        type Error = DateError;
+
+        fn try_from(date: chrono::NaiveDate) -> Result<Ymd, DateError> {
+            let year = u32::try_from(date.year()).map_err(|_| DateError::UnsupportedYear {
+                year: i64::from(date.year()),
+            })?;
+            Ymd::new(year, MONTHS[date.month0() as usize], date.day())
+        }
+    }
+
+    impl TryFrom<Date> for chrono::NaiveDate {
+        type Error = DateError;
+
+        fn try_from(date: Date) -> Result<chrono::NaiveDate, DateError> {
+            chrono::NaiveDate::try_from(date.ymd())
+        }
+    }
+
+    impl TryFrom<chrono::NaiveDate> for Date {
+        type Error = DateError;
+
+        fn try_from(date: chrono::NaiveDate) -> Result<Date, DateError> {
+            Ymd::try_from(date)?.to_date()
+        }
+    }
+}
+
+#[cfg(feature = "time")]
+mod time_conversions {
+    use super::*;
+
+    impl TryFrom<Ymd> for time::Date {
+        type Error = DateError;
+
+        fn try_from(ymd: Ymd) -> Result<time::Date, DateError> {
+            let month = time::Month::try_from(ymd.month.number() as u8).unwrap();
+            let day = u8::try_from(ymd.day).map_err(|_| DateError::DayOutOfRange {
+                day: ymd.day,
+                month: ymd.month.index(),
+                year: ymd.year,
+            })?;
+            i32::try_from(ymd.year)
+                .ok()
+                .and_then(|year| time::Date::from_calendar_date(year, month, day).ok())
+                .ok_or(DateError::UnsupportedYear {
+                    year: i64::from(ymd.year),
+                })
+        }
+    }
+
+    impl TryFrom<time::Date> for Ymd {
+        type Error = DateError;
+
+        fn try_from(date: time::Date) -> Result<Ymd, DateError> {
+            let year = u32::try_from(date.year()).map_err(|_| DateError::UnsupportedYear {
+                year: i64::from(date.year()),
+            })?;
+            let month = Month::from_number(u32::from(u8::from(date.month()))).unwrap();
+            Ymd::new(year, month, u32::from(date.day()))
+        }
+    }
+
+    impl TryFrom<Date> for time::Date {
+        type Error = DateError;
+
+        fn try_from(date: Date) -> Result<time::Date, DateError> {
+            time::Date::try_from(date.ymd())
+        }
+    }
+
+    impl TryFrom<time::Date> for Date {
+        type Error = DateError;
+
+        fn try_from(date: time::Date) -> Result<Date, DateError> {
+            Ymd::try_from(date)?.to_date()
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn months() {
+        assert_eq!(Month::from_number(1), Some(Month::January));
+        assert_eq!(Month::from_number(12), Some(Month::December));
+        assert_eq!(Month::from_number(0), None);
+        assert_eq!(Month::from_number(13), None);
+        assert_eq!(Month::March.index(), 2);
+        assert_eq!(Month::February.days(2000), 29);
+        assert_eq!(Month::February.days(1900), 28);
+    }
+
+    #[test]
+    fn ymd() {
+        let ymd: Ymd = "1950-03-01".parse().unwrap();
+        assert_eq!(
+            ymd,
+            Ymd {
+                year: 1950,
+                month: Month::March,
+                day: 1
+            }
+        );
+        assert_eq!(ymd.to_string(), "1950-03-01");
+        assert_eq!(ymd.to_date(), Date::from_ymd(1950, 2, 1));
+        assert_eq!(Date::from_ymd(1950, 2, 1).unwrap().ymd(), ymd);
+        assert!(Ymd::new(1950, Month::February, 29).is_err());
+        assert!("1950-13-01".parse::<Ymd>().is_err());
+
+        let last: Ymd = "5000000-12-31".parse().unwrap();
+        assert_eq!(Date::try_from(last).unwrap().ymd(), last);
+    }
+
+    #[cfg(feature = "chrono")]
+    #[test]
+    fn chrono_conversion() {
+        let date: Date = "2024-02-29".parse().unwrap();
+        let naive = chrono::NaiveDate::from_ymd_opt(2024, 2, 29).unwrap();
+        assert_eq!(chrono::NaiveDate::try_from(date), Ok(naive));
+        assert_eq!(Date::try_from(naive), Ok(date));
+        assert!(chrono::NaiveDate::try_from("5000000-01-01".parse::<Date>().unwrap()).is_err());
+        assert_eq!(
+            Date::try_from(chrono::NaiveDate::from_ymd_opt(-1, 1, 1).unwrap()),
+            Err(DateError::UnsupportedYear { year: -1 })
+        );
+    }
+
+    #[cfg(feature = "time")]
+    #[test]
+    fn time_conversion() {
+        let date: Date = "2024-02-29".parse().unwrap();
+        let converted = time::Date::from_calendar_date(2024, time::Month::February, 29).unwrap();
+        assert_eq!(time::Date::try_from(date), Ok(converted));
+        assert_eq!(Date::try_from(converted), Ok(date));
+        assert!(time::Date::try_from("5000000-01-01".parse::<Date>().unwrap()).is_err());
+    }
+}